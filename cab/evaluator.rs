@@ -0,0 +1,584 @@
+use std::{
+   any,
+   fmt,
+   path::PathBuf,
+   sync::Arc,
+};
+
+use cab_util::suffix::Arc as _;
+use dup::Dupe as _;
+use ranged::Span;
+use rpds::ListSync as List;
+use serde::{
+   Serialize,
+   de::DeserializeOwned,
+};
+use ust::{
+   Display,
+   Write,
+   report::{
+      self,
+      Report,
+   },
+};
+
+use crate::{
+   runtime::{
+      self,
+      Value,
+      value,
+   },
+   syntax,
+};
+
+/// The stage of the evaluation a [`Failure`] happened in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+   /// The source couldn't be read.
+   Read,
+   /// The source couldn't be parsed.
+   Parse,
+   /// The parsed source couldn't be lowered and compiled.
+   Compile,
+   /// The evaluation resulted in an error.
+   Evaluate,
+   /// The result couldn't be converted into the requested Rust type.
+   Convert,
+}
+
+/// A failed evaluation.
+pub struct Failure {
+   /// The stage the evaluation failed in.
+   pub stage: Stage,
+
+   /// The reports of the stage. Only parsing and compiling produce reports.
+   pub reports: Arc<[Report]>,
+
+   /// The error that caused the failure.
+   pub chain: cyn::Chain,
+
+   location: value::Path,
+   source:   String,
+}
+
+impl Display for Failure {
+   fn display_styled(&self, writer: &mut dyn Write) -> fmt::Result {
+      self.write_reports(writer)?;
+      self.chain.display_styled(writer)
+   }
+}
+
+/// Writes the reports with the source they point into.
+fn write_reports(
+   writer: &mut dyn Write,
+   reports: &[Report],
+   location: &value::Path,
+   source: &str,
+) -> fmt::Result {
+   let source = report::PositionStr::new(source);
+
+   for report in reports {
+      writer.write_report(report, location, &source)?;
+      write!(writer, "\n\n")?;
+   }
+
+   Ok(())
+}
+
+impl Failure {
+   /// Writes the reports with the source they point into, without the chain.
+   pub fn write_reports(&self, writer: &mut dyn Write) -> fmt::Result {
+      write_reports(writer, &self.reports, &self.location, &self.source)
+   }
+
+   fn new(stage: Stage, location: &value::Path, source: &str, chain: cyn::Chain) -> Self {
+      Self {
+         stage,
+         reports: Arc::from([]),
+         chain,
+         location: location.dupe(),
+         source: source.to_owned(),
+      }
+   }
+
+   /// Fails if any of the reports is an error.
+   fn check(
+      stage: Stage,
+      location: &value::Path,
+      source: &str,
+      reports: &Arc<[Report]>,
+   ) -> Result<(), Self> {
+      let fail = reports
+         .iter()
+         .filter(|report| {
+            matches!(
+               report.severity,
+               report::Severity::Error | report::Severity::Bug
+            )
+         })
+         .count();
+
+      if fail == 0 {
+         return Ok(());
+      }
+
+      Err(Self {
+         reports: reports.dupe(),
+         ..Self::new(
+            stage,
+            location,
+            source,
+            cyn::chain!(
+               "{stage} failed due to {fail} previous error{s}",
+               stage = match stage {
+                  Stage::Parse => "parsing",
+                  _ => "lowering",
+               },
+               s = if fail == 1 { "" } else { "s" },
+            ),
+         )
+      })
+   }
+}
+
+/// Returns the first error in the deeply forced value, if any.
+fn error(value: &Value) -> Option<&Value> {
+   // Walk with an explicit stack, so long lists don't overflow the native one.
+   let mut values = vec![value];
+
+   while let Some(value) = values.pop() {
+      match *value {
+         Value::Error(_) => return Some(value),
+
+         Value::Cons(ref cons) => {
+            values.push(&cons.1);
+            values.push(&cons.0);
+         },
+
+         Value::Attributes(ref attributes) => {
+            values.extend(attributes.iter().rev().map(|(_, value)| value));
+         },
+
+         _ => {},
+      }
+   }
+
+   None
+}
+
+/// Returns the location of a value registered by the embedder.
+fn location(name: &str) -> value::Location {
+   value::Location::new(
+      value::Path::rootless(
+         [value::string::new!("native"), value::SString::from(name)]
+            .into_iter()
+            .collect(),
+      ),
+      Span::empty(0_u32),
+   )
+}
+
+/// Evaluates cab source from Rust, with a prelude of values in scope.
+///
+/// # Example
+///
+/// ```rs
+/// let evaluator = Evaluator::new()
+///    .bind("port", Value::from(value::Integer::from(num::BigInt::from(8080))))
+///    .function("double", |integer: i64| Ok::<_, String>(integer * 2));
+///
+/// let port: i64 = evaluator.evaluate_as("double port").await?;
+/// ```
+pub struct Evaluator {
+   prelude:         value::Attributes,
+   cache:           Option<PathBuf>,
+   reproducibility: runtime::Reproducibility,
+   compile_oracle:  runtime::CompileOracle,
+   profile:         bool,
+}
+
+/// Source that was parsed, lowered and compiled by an [`Evaluator`].
+pub struct Compiled {
+   /// The location of the source.
+   pub path:   value::Path,
+   /// The source.
+   pub source: String,
+
+   /// The reports of parsing and lowering the source, which are warnings.
+   pub reports: Arc<[Report]>,
+
+   /// The code the source compiled to, before it was optimized.
+   pub code:      runtime::Code,
+   /// The optimized code, which is what gets evaluated.
+   pub optimized: Arc<runtime::Code>,
+}
+
+impl Compiled {
+   /// Writes the reports with the source they point into.
+   pub fn write_reports(&self, writer: &mut dyn Write) -> fmt::Result {
+      write_reports(writer, &self.reports, &self.path, &self.source)
+   }
+}
+
+impl Evaluator {
+   /// Creates an evaluator with the builtins in its prelude, that caches the
+   /// outputs of commands in the user's cache directory.
+   #[must_use]
+   pub fn new() -> Self {
+      Self {
+         prelude:         runtime::builtins(),
+         cache:           dirs::cache_dir().map(|directory| directory.join("cab")),
+         reproducibility: runtime::Reproducibility::pure(),
+         compile_oracle:  runtime::CompileOracle::new(),
+         profile:         false,
+      }
+   }
+
+   /// Creates an evaluator with an empty prelude, that can't run commands.
+   #[must_use]
+   pub fn empty() -> Self {
+      Self {
+         prelude:         value::attributes::new! {},
+         cache:           None,
+         reproducibility: runtime::Reproducibility::pure(),
+         compile_oracle:  runtime::CompileOracle::new(),
+         profile:         false,
+      }
+   }
+
+   /// Sets the directory to cache the outputs of commands in.
+   #[must_use]
+   pub fn cache(mut self, directory: Option<PathBuf>) -> Self {
+      self.cache = directory;
+      self
+   }
+
+   /// Sets how builtins that read the environment of the evaluation behave.
+   /// Evaluations are pure with an empty lock by default.
+   #[must_use]
+   pub fn reproducibility(mut self, reproducibility: runtime::Reproducibility) -> Self {
+      self.reproducibility = reproducibility;
+      self
+   }
+
+   /// Sets the oracle sources are compiled with.
+   #[must_use]
+   pub fn compile_oracle(mut self, compile_oracle: runtime::CompileOracle) -> Self {
+      self.compile_oracle = compile_oracle;
+      self
+   }
+
+   /// Sets whether the states evaluations run with have a profiler.
+   #[must_use]
+   pub fn profile(mut self, profile: bool) -> Self {
+      self.profile = profile;
+      self
+   }
+
+   /// Binds the name to the value in the prelude, replacing any previous
+   /// value.
+   #[must_use]
+   pub fn bind(mut self, name: &str, value: impl Into<Value>) -> Self {
+      self.prelude = self
+         .prelude
+         .insert(value::SString::from(name), value.into());
+      self
+   }
+
+   /// Binds the name to a lambda that runs the native code with its argument,
+   /// which isn't forced.
+   #[must_use]
+   pub fn native(
+      self,
+      name: &str,
+      code: impl for<'a> Fn(&'a runtime::State, Value) -> value::NativeFuture<'a>
+      + Send
+      + Sync
+      + 'static,
+   ) -> Self {
+      self.bind(
+         name,
+         value::Thunk::needs_argument_native(code).location(location(name)),
+      )
+   }
+
+   /// Binds the name to a lambda that converts its deeply forced argument
+   /// into `A`, calls the function with it and converts the result back.
+   ///
+   /// Functions that need multiple arguments can take a tuple, which is
+   /// converted from a list. Errors returned by the function are turned into
+   /// error values.
+   #[must_use]
+   pub fn function<A, R, E>(
+      self,
+      name: &str,
+      function: impl Fn(A) -> Result<R, E> + Send + Sync + 'static,
+   ) -> Self
+   where
+      A: DeserializeOwned + 'static,
+      R: Serialize + 'static,
+      E: fmt::Display + 'static,
+   {
+      let function = function.arc();
+      let name_owned: Arc<str> = Arc::from(name);
+
+      self.native(name, move |state, argument| {
+         let function = function.dupe();
+         let name = name_owned.dupe();
+
+         Box::pin(async move {
+            let argument = argument.forced_deep(state).await;
+
+            if let Some(error) = error(&argument) {
+               return error.dupe();
+            }
+
+            let fail = |message: String| {
+               Value::from(value::Error::new(value::SString::from(&*message)).arc())
+            };
+
+            let argument = match value::from_value::<A>(argument) {
+               Ok(argument) => argument,
+               Err(error) => return fail(format!("invalid argument to '{name}': {error}")),
+            };
+
+            match function(argument) {
+               Ok(result) => {
+                  value::to_value(&result).unwrap_or_else(|error| {
+                     fail(format!("invalid result from '{name}': {error}"))
+                  })
+               },
+
+               Err(error) => fail(error.to_string()),
+            }
+         })
+      })
+   }
+
+   /// Evaluates the source and forces the result deeply. Fails if the source
+   /// can't be parsed or compiled, or if the result contains an error.
+   pub async fn evaluate(&self, source: &str) -> Result<Value, Failure> {
+      let path = value::Path::new()
+         .root(value::path::blob(Value::from(value::SString::from(source))).arc())
+         .subpath(List::new_sync());
+
+      self.evaluate_path(path).await
+   }
+
+   /// Reads the path, evaluates its contents and forces the result deeply.
+   /// Fails if the path can't be read, its contents can't be parsed or
+   /// compiled, or if the result contains an error.
+   pub async fn evaluate_path(&self, path: value::Path) -> Result<Value, Failure> {
+      let compiled = self.compile_path(path).await?;
+
+      let state = self.state();
+      let value = self.run(&compiled, &state).await.forced_deep(&state).await;
+
+      if let Some(error) = error(&value) {
+         return Err(Failure::new(
+            Stage::Evaluate,
+            &compiled.path,
+            &compiled.source,
+            cyn::Chain::new().push_front_tags(error),
+         ));
+      }
+
+      Ok(value)
+   }
+
+   /// Reads the path and compiles its contents. Fails if the path can't be
+   /// read or its contents can't be parsed or compiled.
+   pub async fn compile_path(&self, path: value::Path) -> Result<Compiled, Failure> {
+      let source = path
+         .read()
+         .await
+         .map_err(|chain| Failure::new(Stage::Read, &path, "", chain))?;
+
+      let source = String::from_utf8(source.to_vec()).map_err(|error| {
+         Failure::new(
+            Stage::Read,
+            &path,
+            "",
+            cyn::chain!("source is not valid UTF-8: {error}"),
+         )
+      })?;
+
+      self.compile(path, source)
+   }
+
+   /// Parses, lowers and compiles the source, which is located at the path.
+   /// Fails if the source can't be parsed or compiled.
+   pub fn compile(&self, path: value::Path, source: String) -> Result<Compiled, Failure> {
+      let parse = syntax::ParseOracle::new().parse(syntax::tokenize(&source));
+      Failure::check(Stage::Parse, &path, &source, &parse.reports)?;
+
+      let lower = syntax::LowerOracle::new().lower(parse.expression.as_ref());
+      let reports = parse
+         .reports
+         .iter()
+         .chain(&*lower.reports)
+         .cloned()
+         .collect::<Arc<[_]>>();
+      Failure::check(Stage::Compile, &path, &source, &reports)?;
+
+      let compile = self
+         .compile_oracle
+         .compile(lower.expression())
+         .path(path.dupe());
+      let reports = reports.iter().chain(&*compile.reports).cloned().collect();
+
+      let code = compile.code;
+      let optimized = code.optimized().arc();
+
+      Ok(Compiled {
+         path,
+         source,
+         reports,
+         code,
+         optimized,
+      })
+   }
+
+   /// Returns a new state to evaluate compiled sources with.
+   #[must_use]
+   pub fn state(&self) -> runtime::State {
+      runtime::State {
+         compile_oracle: self.compile_oracle,
+
+         profiler: self.profile.then(runtime::Profiler::new),
+         cache:    self.cache.clone(),
+
+         reproducibility: self.reproducibility.clone(),
+
+         ..runtime::State::new()
+      }
+   }
+
+   /// Evaluates the compiled source with the prelude in scope, forcing the
+   /// result to weak head normal form.
+   pub async fn run(&self, compiled: &Compiled, state: &runtime::State) -> Value {
+      let thunk = value::Thunk::forceable(compiled.optimized.dupe())
+         .scopes(runtime::Scopes::new().push(runtime::Scope::from(&self.prelude)))
+         .location(value::Location::new(
+            compiled.path.dupe(),
+            Span::at(0_u32, compiled.source.len()),
+         ));
+
+      Value::from(thunk).forced(state).await
+   }
+
+   /// Evaluates the source and converts the result into `T`. Fails if the
+   /// evaluation fails or if the result can't be converted into `T`.
+   pub async fn evaluate_as<T: DeserializeOwned>(&self, source: &str) -> Result<T, Failure> {
+      let value = self.evaluate(source).await?;
+
+      value::from_value(value).map_err(|error| {
+         Failure::new(
+            Stage::Convert,
+            &value::Path::rootless(List::new_sync()),
+            source,
+            cyn::Chain::new()
+               .push_front_display(error)
+               .push_front_display(format!(
+                  "failed to convert the result into {type_}",
+                  type_ = any::type_name::<T>(),
+               )),
+         )
+      })
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use serde::Deserialize;
+
+   use super::*;
+
+   #[derive(Debug, PartialEq, Deserialize)]
+   struct Config {
+      port:  u16,
+      hosts: Vec<String>,
+   }
+
+   #[tokio::test]
+   async fn evaluates() {
+      let config = Evaluator::new()
+         .evaluate_as::<Config>(r#"{ @port = 8080; @hosts = [ "a", "b" ] }"#)
+         .await
+         .unwrap_or_else(|_| panic!("source must evaluate to a config"));
+
+      assert_eq!(config, Config {
+         port:  8080,
+         hosts: vec!["a".to_owned(), "b".to_owned()],
+      });
+   }
+
+   #[tokio::test]
+   async fn prelude() {
+      let evaluator = Evaluator::empty()
+         .bind("greeting", value::string::new!("hello"))
+         .function("add", |(left, right): (i64, i64)| {
+            left.checked_add(right).ok_or("overflow")
+         })
+         .native("identity", |_, argument| Box::pin(async move { argument }));
+
+      assert_eq!(
+         evaluator
+            .evaluate_as::<(String, i64, bool)>("[ greeting, add [ 1, 2 ], identity true ]")
+            .await
+            .ok(),
+         Some(("hello".to_owned(), 3, true)),
+      );
+
+      let Err(failure) = evaluator.evaluate("toJSON 1").await else {
+         panic!("builtins must not be in an empty prelude");
+      };
+      assert_eq!(failure.stage, Stage::Evaluate);
+   }
+
+   #[tokio::test]
+   async fn function_errors() {
+      let evaluator = Evaluator::empty().function("half", |integer: i64| {
+         if integer % 2 == 0 {
+            Ok(integer / 2)
+         } else {
+            Err(format!("{integer} is odd"))
+         }
+      });
+
+      for source in ["half 3", "half \"2\""] {
+         let Err(failure) = evaluator.evaluate(source).await else {
+            panic!("{source} must fail");
+         };
+
+         assert_eq!(failure.stage, Stage::Evaluate, "{source}");
+      }
+   }
+
+   #[tokio::test]
+   async fn failures() {
+      let evaluator = Evaluator::new();
+
+      for (source, stage) in [
+         ("1 )", Stage::Parse),
+         ("()", Stage::Compile),
+         ("[ 1, undefined ]", Stage::Evaluate),
+      ] {
+         let Err(failure) = evaluator.evaluate(source).await else {
+            panic!("{source} must fail");
+         };
+
+         assert_eq!(failure.stage, stage, "{source}");
+         assert_eq!(
+            failure.reports.is_empty(),
+            stage == Stage::Evaluate,
+            "{source}",
+         );
+      }
+
+      let Err(failure) = evaluator.evaluate_as::<Config>("{ @port = true }").await else {
+         panic!("source must not convert into a config");
+      };
+      assert_eq!(failure.stage, Stage::Convert);
+   }
+}
@@ -0,0 +1,263 @@
+#![no_main]
+
+use std::{
+   env,
+   fs,
+   hash::{
+      self,
+      Hash as _,
+      Hasher as _,
+   },
+   time::Duration,
+};
+
+use cab::{
+   runtime::{
+      self,
+      Value,
+      value,
+   },
+   syntax,
+   util::suffix::Arc as _,
+};
+use dup::Dupe as _;
+use libfuzzer_sys::{
+   Corpus,
+   fuzz_target,
+};
+use ranged::Span;
+use rpds::ListSync as List;
+use ust::{
+   Display as _,
+   report,
+   terminal,
+};
+
+/// The operations every evaluation can execute before it is abandoned.
+const OPERATIONS_MAX: u64 = 100_000;
+
+/// The time every evaluation can take before it is abandoned.
+const DURATION_MAX: Duration = Duration::from_secs(1);
+
+/// Removes the traces of errors, as every path evaluates different code that
+/// points to different locations.
+fn untraced(value: Value) -> Value {
+   match value {
+      Value::Error(error) => Value::from(value::Error::new(untraced(error.value.dupe())).arc()),
+
+      Value::Attributes(attributes) => {
+         let mut untraced_attributes = value::attributes::new! {};
+
+         for (name, value) in &attributes.0 {
+            untraced_attributes = untraced_attributes.insert(name.dupe(), untraced(value.dupe()));
+         }
+
+         Value::from(untraced_attributes)
+      },
+
+      Value::Cons(cons) => {
+         let mut items = vec![cons.0.dupe()];
+
+         let mut tail = cons.1.dupe();
+         while let Value::Cons(cons) = tail {
+            items.push(cons.0.dupe());
+            tail = cons.1.dupe();
+         }
+
+         items.into_iter().rev().fold(untraced(tail), |tail, head| {
+            Value::from(value::Cons::new(untraced(head), tail).arc())
+         })
+      },
+
+      value => value,
+   }
+}
+
+fn display(value: &Value) -> String {
+   let mut display = String::new();
+
+   {
+      let writer = &mut terminal::writer(terminal::StyleChoice::Never, &mut display);
+      value
+         .display_styled(writer)
+         .expect("writing to a string must not fail");
+   }
+
+   display
+}
+
+/// Whether the code, or the code of a thunk it creates, contains an operation
+/// the virtual machine doesn't implement yet. Executing one panics, so inputs
+/// that compile to them can't be fuzzed until they are implemented. String and
+/// path interpolation compile to [`runtime::Operation::Interpolate`].
+fn is_unimplemented(code: &runtime::Code) -> bool {
+   let mut index = runtime::ByteIndex::zero();
+   while let Some((operation, argument, next)) = code.read(index) {
+      match (operation, argument) {
+         (
+            runtime::Operation::Interpolate | runtime::Operation::All | runtime::Operation::Any,
+            _,
+         ) => return true,
+
+         (runtime::Operation::Push, Some(runtime::Argument::ValueIndex(value_index))) => {
+            if let Value::NeedsArgumentToThunk(ref code) | Value::Thunkable(ref code) =
+               code[value_index]
+               && is_unimplemented(code)
+            {
+               return true;
+            }
+         },
+
+         _ => {},
+      }
+
+      index = next;
+   }
+
+   false
+}
+
+/// Evaluates the code deeply. Returns [`None`] if the evaluation was abandoned
+/// because it ran out of time or operations.
+async fn evaluate(code: runtime::Code, span: Span) -> Option<Value> {
+   let location = value::Location::new(code.path().dupe(), span);
+
+   let thunk = value::Thunk::forceable(code.arc())
+      .scopes(runtime::Scopes::new().push(runtime::Scope::from(&runtime::builtins())))
+      .location(location);
+
+   let state = runtime::State {
+      budget: Some(runtime::Budget::new(OPERATIONS_MAX)),
+      ..runtime::State::new()
+   };
+
+   let value = tokio::time::timeout(DURATION_MAX, async {
+      thunk.force(&state).await;
+
+      let (_, value) = thunk.get().await;
+      value.forced_deep(&state).await
+   })
+   .await
+   .ok()?;
+
+   if state.budget.as_ref().is_some_and(runtime::Budget::is_spent) {
+      return None;
+   }
+
+   Some(value)
+}
+
+fuzz_target!(|source: &str| -> Corpus {
+   let save_crashes = env::var_os("FUZZ_EVAL_SAVE_CRASHES").is_some_and(|value| value != "0");
+
+   let name = {
+      let mut hasher = hash::DefaultHasher::new();
+      source.hash(&mut hasher);
+      format!("{hash:016x}", hash = hasher.finish())
+   };
+
+   let path = value::Path::rootless(List::new_sync().push_front(value::SString::from(&*name)));
+   let source = report::PositionStr::new(source);
+
+   // The reports are written the same way `cab-task check eval` writes them,
+   // so the expected result of a mismatch can be checked with it.
+   let mut expected = String::new();
+   let mut writer = terminal::writer(terminal::StyleChoice::Never, &mut expected);
+
+   let parse = syntax::ParseOracle::new().parse(syntax::tokenize(&source));
+   let Ok(expression) = parse.extractlnln(&mut writer, &path, &source) else {
+      return Corpus::Reject;
+   };
+
+   let lower = syntax::LowerOracle::new().lower(expression.as_ref());
+   let Ok(expression) = lower.extractlnln(&mut writer, &path, &source) else {
+      return Corpus::Reject;
+   };
+
+   drop(writer);
+
+   let code = runtime::CompileOracle::new()
+      .compile(expression)
+      .path(path.dupe())
+      .code;
+   let code_optimized = code.optimized();
+
+   if is_unimplemented(&code) || is_unimplemented(&code_optimized) {
+      return Corpus::Reject;
+   }
+
+   let code_assembled =
+      runtime::Code::assemble(&code_optimized.assembly().to_string(), path.dupe())
+         .expect("assembly of compiled code must assemble and be valid");
+
+   // Save the source before evaluating, so it is still there if the
+   // evaluation panics. It's removed again if all paths agree.
+   let (source_file, expected_file) = {
+      let root = env::current_dir().unwrap();
+      let root = root.parent().unwrap().join("target").join("cab-eval-fuzz");
+
+      (
+         root.join(name.clone() + ".cab"),
+         root.join(name.clone() + ".eval.expect"),
+      )
+   };
+
+   if save_crashes {
+      fs::create_dir_all(source_file.parent().unwrap()).unwrap();
+      fs::write(&source_file, *source).unwrap();
+   }
+
+   let runtime = tokio::runtime::Builder::new_current_thread()
+      .enable_time()
+      .build()
+      .unwrap();
+
+   let span = Span::at(0_u32, source.len());
+
+   let mut values = Vec::new();
+   for (path_name, code) in [
+      ("unoptimized", code),
+      ("optimized", code_optimized),
+      ("assembled", code_assembled),
+   ] {
+      let Some(value) = runtime.block_on(evaluate(code, span)) else {
+         if save_crashes {
+            fs::remove_file(&source_file).unwrap();
+         }
+
+         return Corpus::Keep;
+      };
+
+      values.push((path_name, value));
+   }
+
+   let (_, ref value_expected) = values[0];
+   let display_expected = display(&untraced(value_expected.dupe()));
+
+   for &(path_name, ref value) in &values[1..] {
+      let display_actual = display(&untraced(value.dupe()));
+
+      if display_actual == display_expected {
+         continue;
+      }
+
+      if save_crashes {
+         expected.push_str(&display(value_expected));
+         expected.push('\n');
+
+         fs::write(&expected_file, &expected).unwrap();
+      }
+
+      panic!(
+         "{path_name} evaluation disagrees with unoptimized evaluation:\n\
+          expected: {display_expected}\n\
+          actual:   {display_actual}"
+      );
+   }
+
+   if save_crashes {
+      fs::remove_file(&source_file).unwrap();
+   }
+
+   Corpus::Keep
+});
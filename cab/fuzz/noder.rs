@@ -73,7 +73,7 @@ fuzz_target!(|source: &str| -> Corpus {
 
       (
          root.join(base_file.clone() + ".cab"),
-         root.join(base_file.clone() + ".expect"),
+         root.join(base_file.clone() + ".syntax.expect"),
       )
    };
 
@@ -1,14 +1,24 @@
-use std::fmt::Write as _;
+use std::{
+   fmt::Write as _,
+   fs,
+   path::{
+      Component,
+      Path,
+      PathBuf,
+   },
+};
 
 use cab::{
+   Stage,
    runtime,
    syntax,
    util::suffix::Arc as _,
 };
 use clap::Parser as _;
-use cyn::ResultExt as _;
-use dup::Dupe as _;
-use ranged::Span;
+use cyn::{
+   OptionExt as _,
+   ResultExt as _,
+};
 use rpds::ListSync as List;
 use runtime::{
    Value,
@@ -17,15 +27,99 @@ use runtime::{
 use ust::{
    COLORS,
    Display as _,
-   report,
+   Write,
    style::StyledExt as _,
    terminal,
    write,
 };
 
+const EXIT_CODES: &str = "\
+Exit codes:
+  1  The source couldn't be read, or something else failed
+  2  The command line arguments are invalid
+  3  The source couldn't be parsed
+  4  The source couldn't be compiled
+  5  The evaluation resulted in an error";
+
 #[derive(clap::Parser)]
-#[command(version, about)]
+#[command(version, about, after_help = EXIT_CODES, arg_required_else_help = true)]
 struct Cli {
+   #[command(subcommand)]
+   command: Command,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+   /// Evaluate the expression given as arguments.
+   Eval {
+      #[command(flatten)]
+      options: Options,
+
+      /// The expression to evaluate.
+      #[arg(required = true)]
+      expression: Vec<String>,
+
+      /// The arguments `args` returns in impure evaluations.
+      #[arg(last = true)]
+      arguments: Vec<String>,
+   },
+
+   /// Evaluate a file.
+   Run {
+      #[command(flatten)]
+      options: Options,
+
+      /// The file to evaluate.
+      path: PathBuf,
+
+      /// The arguments `args` returns in impure evaluations.
+      #[arg(last = true)]
+      arguments: Vec<String>,
+   },
+
+   /// Evaluate standard in.
+   #[command(name = "-")]
+   Standard {
+      #[command(flatten)]
+      options: Options,
+
+      /// The arguments `args` returns in impure evaluations.
+      #[arg(last = true)]
+      arguments: Vec<String>,
+   },
+
+   /// Render the documented members of the attributes a file evaluates to.
+   Doc {
+      /// The format to render the documentation in.
+      #[arg(long, default_value = "markdown")]
+      format: DocFormat,
+
+      /// The file to document.
+      path: PathBuf,
+   },
+
+   /// Print the files with their syntax highlighted.
+   Highlight {
+      /// The format to highlight the files in.
+      #[arg(long, default_value = "ansi")]
+      format: HighlightFormat,
+
+      /// The files to highlight.
+      #[arg(required = true)]
+      paths: Vec<PathBuf>,
+   },
+}
+
+#[derive(clap::Args)]
+struct Options {
+   /// Bind the name to the result of the expression in the top scope.
+   #[arg(long, num_args = 2, value_names = ["NAME", "EXPRESSION"])]
+   arg: Vec<String>,
+
+   /// Bind the name to the string in the top scope.
+   #[arg(long, num_args = 2, value_names = ["NAME", "STRING"])]
+   argstr: Vec<String>,
+
    /// Print the result of every `Language.tokenize` call.
    #[arg(long, default_value = "false")]
    dump_token: DumpToken,
@@ -34,12 +128,52 @@ struct Cli {
    #[arg(long, default_value = "false")]
    dump_syntax: bool,
 
-   /// Print the result of every `Language.compile` call.
+   /// Print the result of every `Language.compile` call, before and after
+   /// optimization, in the textual assembly format.
    #[arg(long, default_value = "false")]
    dump_code: bool,
 
-   /// The expression to `evaluate`.
-   expression: Vec<String>,
+   /// Print the time spent, forces, operations and allocations per span after
+   /// evaluating.
+   #[arg(long, default_value = "false")]
+   profile: bool,
+
+   /// Write the evaluation stacks in the folded flamegraph format to the
+   /// given file.
+   #[arg(long, value_name = "PATH")]
+   profile_folded: Option<PathBuf>,
+
+   /// The format to print the result in.
+   #[arg(long, default_value = "display")]
+   output: Output,
+
+   /// Let `getEnv`, `currentSystem`, `currentTime` and `args` read the
+   /// environment. Evaluations are pure otherwise, where they return the
+   /// values recorded in the lock.
+   #[arg(long, default_value = "false")]
+   impure: bool,
+
+   /// The lock to read the values of pure evaluations from, or to record the
+   /// values read by impure evaluations to.
+   #[arg(long, value_name = "PATH")]
+   lock: Option<PathBuf>,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy)]
+enum DocFormat {
+   Markdown,
+   Html,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum HighlightFormat {
+   /// Terminal escape sequences.
+   Ansi,
+   /// HTML, with CSS classes for the kind and context of every token.
+   Html,
+   /// LaTeX, wrapping every token in `\CabToken{classes}{text}` inside a
+   /// `Verbatim` environment.
+   Latex,
 }
 
 #[derive(clap::ValueEnum, Debug, Clone, Copy)]
@@ -49,116 +183,437 @@ enum DumpToken {
    Color,
 }
 
-#[tokio::main]
-async fn main() -> cyn::Termination {
-   let cli = Cli::parse();
+#[derive(clap::ValueEnum, Debug, Clone, Copy)]
+enum Output {
+   /// Display the value as is, without forcing it further.
+   Display,
+   /// Force the value deeply and serialize it as JSON.
+   Json,
+}
 
-   let out = &mut terminal::stdout();
-   let err = &mut terminal::stderr();
+/// A failure, with the code to exit with.
+struct Exit {
+   code:  u8,
+   chain: cyn::Chain,
+}
 
-   let expression = match &*cli.expression {
-      &[] => unimplemented!("repl"),
-      parts => parts.join(" "),
-   };
+impl From<cyn::Chain> for Exit {
+   fn from(chain: cyn::Chain) -> Self {
+      Self { code: 1, chain }
+   }
+}
+
+impl Exit {
+   fn new(stage: Stage, chain: cyn::Chain) -> Self {
+      Self {
+         code: match stage {
+            Stage::Read => 1,
+            Stage::Parse => 3,
+            Stage::Compile => 4,
+            Stage::Evaluate | Stage::Convert => 5,
+         },
+         chain,
+      }
+   }
+}
+
+trait StageExt<T> {
+   fn stage(self, stage: Stage) -> Result<T, Exit>;
+}
+
+impl<T> StageExt<T> for cyn::Result<T> {
+   fn stage(self, stage: Stage) -> Result<T, Exit> {
+      self.map_err(|chain| Exit::new(stage, chain))
+   }
+}
+
+async fn doc(
+   out: &mut impl Write,
+   err: &mut impl Write,
+   format: DocFormat,
+   file: &Path,
+) -> cyn::Result<()> {
+   let source = fs::read_to_string(file)
+      .chain_err_with(|| format!("failed to read '{file}'", file = file.display()))?;
 
    let path = value::Path::new()
-      .root(value::path::blob(Value::from(value::SString::from(&*expression))).arc())
+      .root(value::path::blob(Value::from(value::SString::from(&*source))).arc())
       .subpath(List::new_sync());
 
-   let source = path.read().await?.to_vec();
-   let source = String::from_utf8(source).expect("source was created from UTF-8 string");
-   let source = report::PositionStr::new(&source);
+   let evaluator = cab::Evaluator::new();
+   let compiled = compile(err, &evaluator, path)
+      .await
+      .map_err(|exit| exit.chain)?;
+
+   let state = evaluator.state();
+   let value = evaluator.run(&compiled, &state).await;
+
+   let Value::Attributes(ref attributes) = value else {
+      value
+         .display_styled(err)
+         .chain_err("failed to display value")?;
+      writeln!(err).chain_err("failed to display value")?;
+
+      cyn::bail!(
+         "'{file}' must evaluate to attributes to be documented",
+         file = file.display(),
+      );
+   };
+
+   let title = file.file_stem().map_or_else(
+      || file.display().to_string(),
+      |stem| stem.display().to_string(),
+   );
+
+   let rendered = match format {
+      DocFormat::Markdown => runtime::to_markdown(&title, attributes),
+      DocFormat::Html => runtime::to_html(&title, attributes),
+   };
+
+   write!(out, "{rendered}").chain_err("failed to write documentation")
+}
+
+fn highlight(out: &mut impl Write, format: HighlightFormat, files: &[PathBuf]) -> cyn::Result<()> {
+   for file in files {
+      let source = fs::read_to_string(file)
+         .chain_err_with(|| format!("failed to read '{file}'", file = file.display()))?;
+
+      let rendered = match format {
+         HighlightFormat::Ansi => syntax::to_ansi(&source),
+         HighlightFormat::Html => syntax::to_html(&source),
+         HighlightFormat::Latex => syntax::to_latex(&source),
+      };
+
+      write!(out, "{rendered}").chain_err("failed to write highlighted source")?;
+   }
+
+   Ok(())
+}
+
+/// Returns the evaluator for the options, with the bindings of `--arg` and
+/// `--argstr` added to the builtins. The expressions of `--arg` are evaluated
+/// with the same evaluator, so they can refer to the arguments before them.
+async fn evaluator(
+   err: &mut impl Write,
+   options: &Options,
+   arguments: Vec<String>,
+) -> Result<cab::Evaluator, Exit> {
+   let mut evaluator = cab::Evaluator::new()
+      .reproducibility(reproducibility(options, arguments)?)
+      .profile(options.profile || options.profile_folded.is_some());
+
+   for pair in options.arg.chunks_exact(2) {
+      let (name, expression) = (&pair[0], &pair[1]);
+
+      let value = match evaluator.evaluate(expression).await {
+         Ok(value) => value,
+
+         Err(failure) => {
+            failure
+               .write_reports(err)
+               .chain_err("failed to write reports")?;
+
+            return Err(Exit::new(
+               failure.stage,
+               failure
+                  .chain
+                  .push_front_display(format!("failed to evaluate the argument '{name}'")),
+            ));
+         },
+      };
+
+      evaluator = evaluator.bind(name, value);
+   }
+
+   for pair in options.argstr.chunks_exact(2) {
+      let (name, string) = (&pair[0], &pair[1]);
+
+      evaluator = evaluator.bind(name, value::SString::from(&**string));
+   }
+
+   Ok(evaluator)
+}
+
+/// Compiles the source at the path, writing the reports of parsing and
+/// lowering it.
+async fn compile(
+   err: &mut impl Write,
+   evaluator: &cab::Evaluator,
+   path: value::Path,
+) -> Result<cab::Compiled, Exit> {
+   match evaluator.compile_path(path).await {
+      Ok(compiled) => {
+         compiled
+            .write_reports(err)
+            .chain_err("failed to write reports")?;
+
+         Ok(compiled)
+      },
+
+      Err(failure) => {
+         failure
+            .write_reports(err)
+            .chain_err("failed to write reports")?;
+
+         Err(Exit::new(failure.stage, failure.chain))
+      },
+   }
+}
+
+/// Returns how builtins that read the environment behave, as given by
+/// `--impure` and `--lock`.
+fn reproducibility(
+   options: &Options,
+   arguments: Vec<String>,
+) -> cyn::Result<runtime::Reproducibility> {
+   if options.impure {
+      return Ok(runtime::Reproducibility::Impure {
+         lock: runtime::Lock::new(),
+         arguments,
+      });
+   }
+
+   let Some(lock) = &options.lock else {
+      return Ok(runtime::Reproducibility::pure());
+   };
+
+   let json = fs::read_to_string(lock)
+      .chain_err_with(|| format!("failed to read lock '{lock}'", lock = lock.display()))?;
+
+   Ok(runtime::Reproducibility::Pure(runtime::Lock::from_json(
+      &json,
+   )?))
+}
+
+/// Returns the path of the file in the `fs` root.
+fn file(file: &Path) -> cyn::Result<value::Path> {
+   let file = fs::canonicalize(file)
+      .chain_err_with(|| format!("failed to resolve '{file}'", file = file.display()))?;
+
+   let subpath = file
+      .components()
+      .filter_map(|component| {
+         match component {
+            Component::Normal(part) => Some(part),
+            _ => None,
+         }
+      })
+      .map(|part| {
+         part
+            .to_str()
+            .map(value::SString::from)
+            .ok_or_chain_with(|| {
+               format!(
+                  "'{file}' has a name that is not valid UTF-8",
+                  file = file.display(),
+               )
+            })
+      })
+      .collect::<cyn::Result<Vec<_>>>()?;
+
+   Ok(value::Path::new()
+      .root(value::path::fs().arc())
+      .subpath(subpath.into_iter().collect()))
+}
+
+/// Prints the tokens and the syntax tree of the source at the path, as asked
+/// for by `--dump-token` and `--dump-syntax`.
+async fn dump(out: &mut impl Write, options: &Options, path: &value::Path) -> Result<(), Exit> {
+   let source = path.read().await.stage(Stage::Read)?.to_vec();
+   let source = String::from_utf8(source)
+      .chain_err("source is not valid UTF-8")
+      .stage(Stage::Read)?;
 
    // SOURCE -> TOKENS
    let tokens = syntax::tokenize(&source);
 
-   match cli.dump_token {
+   match options.dump_token {
       DumpToken::False => {},
       DumpToken::True => {
          for (kind, slice) in tokens.clone() {
-            writeln!(out, "{kind:?} {slice:?}").expect("TODO move this inside the runtime");
+            writeln!(out, "{kind:?} {slice:?}").chain_err("failed to write tokens")?;
          }
-         writeln!(out).expect("TODO move inside the runtime");
+         writeln!(out).chain_err("failed to write tokens")?;
       },
       DumpToken::Color => {
          for (kind, slice) in tokens.clone() {
             let style = COLORS[kind as usize];
 
-            write(out, &slice.style(style)).expect("TODO move inside the runtime");
+            write(out, &slice.style(style)).chain_err("failed to write tokens")?;
          }
-         writeln!(out).expect("TODO move inside the runtime");
+         writeln!(out).chain_err("failed to write tokens")?;
       },
    }
 
    // TOKENS -> PARSE
-   let parse_oracle = syntax::ParseOracle::new();
-   let parse = parse_oracle.parse(tokens);
+   if options.dump_syntax {
+      let parse = syntax::ParseOracle::new().parse(tokens);
 
-   if cli.dump_syntax {
       // The Display of this already has a newline. So use write! instead.
-      write!(out, "{node:#?}", node = &parse.node).expect("TODO move inside the runtime");
-   }
-
-   // EXTRACT EXPRESSION
-   let expression = parse.extractlnln(err, &path, &source)?;
-
-   // EXPRESSION -> LOWERED EXPRESSION
-   let lower_oracle = syntax::LowerOracle::new();
-   let lower = lower_oracle.lower(expression.as_ref());
-
-   // TODO: Flag for displaying lower.
-
-   // EXTRACT EXPRESSION
-   let expression = lower.extractlnln(err, &path, &source)?;
-
-   // EXPRESSION -> CODE
-   let compile_oracle = runtime::CompileOracle::new();
-   let code = compile_oracle.compile(expression).path(path.dupe());
-
-   if cli.dump_code {
-      code
-         .display_styled(out)
-         .expect("TODO move inside the runtime");
-      writeln!(out).expect("TODO move inside the runtime");
-   }
-
-   // CODE -> THUNK
-   let thunk = value::Thunk::forceable(code.arc())
-      .scopes(
-         runtime::Scopes::new().push(runtime::Scope::from(&value::attributes::new! {
-            "foo": Value::from(value::string::new!("AAAA")),
-            "bar": Value::from(value::attributes::new! {
-               "baz": Value::Boolean(false),
-            }),
-            "true": Value::Boolean(true),
-            "false": Value::Boolean(false),
-            // "fee": Value::from(value::Thunk::forceable_native(|| {
-            //    eprintln!("[BACKGROUND PROCESS] Selling personal data to mastercard...");
-            //    Value::from(value::string::new!("Your transaction has been successful."))
-            // })),
-         })),
-      )
-      .location(value::Location::new(path, Span::at(0_u32, source.len())));
-
-   thunk
-      .force(&runtime::State {
-         parse_oracle,
-         compile_oracle,
-      })
-      .await;
+      write!(out, "{node:#?}", node = &parse.node).chain_err("failed to write syntax")?;
+   }
+
+   Ok(())
+}
+
+async fn evaluate(
+   out: &mut impl Write,
+   err: &mut impl Write,
+   options: &Options,
+   path: value::Path,
+   arguments: Vec<String>,
+) -> Result<(), Exit> {
+   let evaluator = evaluator(err, options, arguments).await?;
+
+   if !matches!(options.dump_token, DumpToken::False) || options.dump_syntax {
+      dump(out, options, &path).await?;
+   }
+
+   let compiled = compile(err, &evaluator, path).await?;
+
+   if options.dump_code {
+      for (header, code) in [
+         ("unoptimized", &compiled.code),
+         ("optimized", &*compiled.optimized),
+      ] {
+         write(out, &header.bold()).chain_err("failed to write code")?;
+         writeln!(out, ":").chain_err("failed to write code")?;
 
-   let (_, value) = thunk.get().await;
+         code.display_styled(out).chain_err("failed to write code")?;
+         writeln!(out).chain_err("failed to write code")?;
+      }
+   }
+
+   let state = evaluator.state();
+   let value = evaluator.run(&compiled, &state).await;
+
+   let result = match options.output {
+      Output::Display => {
+         if let Value::Error(_) = value {
+            Err(Exit::new(
+               Stage::Evaluate,
+               cyn::Chain::new().push_front_tags(&value),
+            ))
+         } else {
+            value
+               .display_styled(out)
+               .chain_err("failed to display value")?;
+
+            Ok(())
+         }
+      },
+
+      Output::Json => {
+         let value = value.forced_deep(&state).await;
+
+         match runtime::to_json(&value) {
+            Ok(json) => {
+               writeln!(out, "{json}").chain_err("failed to write JSON")?;
+
+               Ok(())
+            },
+
+            Err(error) => {
+               Err(Exit::new(
+                  Stage::Convert,
+                  cyn::Chain::new()
+                     .push_front_tags(&error)
+                     .push_front_display("failed to serialize value to JSON"),
+               ))
+            },
+         }
+      },
+   };
+
+   if let Some(profiler) = &state.profiler {
+      if options.profile {
+         writeln!(err).chain_err("failed to display profile")?;
+         profiler
+            .display_styled(err)
+            .chain_err("failed to display profile")?;
+      }
 
-   value
-      .display_styled(out)
-      .chain_err("failed to display value")?;
+      if let Some(folded) = &options.profile_folded {
+         fs::write(folded, profiler.folded()).chain_err_with(|| {
+            format!(
+               "failed to write folded profile to {folded}",
+               folded = folded.display(),
+            )
+         })?;
+      }
+   }
+
+   if options.impure
+      && let Some(lock) = &options.lock
+   {
+      fs::write(lock, state.reproducibility.lock().to_json())
+         .chain_err_with(|| format!("failed to write lock '{lock}'", lock = lock.display()))?;
+   }
 
-   cyn::Termination::success()
+   result
+}
+
+#[tokio::main]
+async fn main() -> cyn::Termination {
+   let cli = Cli::parse();
+
+   let out = &mut terminal::stdout();
+   let err = &mut terminal::stderr();
+
+   let (options, path, arguments) = match cli.command {
+      Command::Doc { format, path } => {
+         doc(out, err, format, &path).await?;
+
+         return cyn::Termination::success();
+      },
+
+      Command::Highlight { format, paths } => {
+         highlight(out, format, &paths)?;
+
+         return cyn::Termination::success();
+      },
+
+      Command::Eval {
+         options,
+         expression,
+         arguments,
+      } => {
+         let expression = expression.join(" ");
+
+         let path = value::Path::new()
+            .root(value::path::blob(Value::from(value::SString::from(&*expression))).arc())
+            .subpath(List::new_sync());
+
+         (options, path, arguments)
+      },
+
+      Command::Run {
+         options,
+         path,
+         arguments,
+      } => (options, file(&path)?, arguments),
+
+      Command::Standard { options, arguments } => {
+         let path = value::Path::new()
+            .root(value::path::standard().arc())
+            .subpath(List::new_sync());
+
+         (options, path, arguments)
+      },
+   };
+
+   match evaluate(out, err, &options, path, arguments).await {
+      Ok(()) => cyn::Termination::success(),
+      Err(exit) => cyn::Termination::error(exit.chain).code(exit.code),
+   }
 }
 
 #[cfg(test)]
 mod tests {
-   use clap::CommandFactory as _;
+   use clap::{
+      CommandFactory as _,
+      error::ErrorKind,
+   };
 
    use super::*;
 
@@ -166,4 +621,83 @@ mod tests {
    fn cli() {
       Cli::command().debug_assert();
    }
+
+   #[test]
+   fn no_command() {
+      let error = Cli::try_parse_from(["cab"]).expect_err("a command must be required");
+
+      assert_eq!(
+         error.kind(),
+         ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand,
+      );
+      assert_eq!(error.exit_code(), 2);
+   }
+
+   #[test]
+   fn arguments() {
+      let cli = Cli::try_parse_from([
+         "cab", "eval", "--arg", "a", "1", "--argstr", "b", "x", "--arg", "c", "a", "a",
+      ])
+      .expect("arguments must parse");
+
+      let Command::Eval {
+         options,
+         expression,
+         ..
+      } = cli.command
+      else {
+         panic!("eval must be parsed");
+      };
+
+      assert_eq!(options.arg, ["a", "1", "c", "a"]);
+      assert_eq!(options.argstr, ["b", "x"]);
+      assert_eq!(expression, ["a"]);
+
+      let cli =
+         Cli::try_parse_from(["cab", "-", "--argstr", "b", "x"]).expect("arguments must parse");
+      assert!(matches!(cli.command, Command::Standard { .. }));
+   }
+
+   #[test]
+   fn reproducibility_flags() {
+      let cli = Cli::try_parse_from([
+         "cab", "run", "--impure", "--lock", "x.lock", "f.cab", "--", "a", "--b",
+      ])
+      .expect("arguments must parse");
+
+      let Command::Run {
+         options,
+         path,
+         arguments,
+      } = cli.command
+      else {
+         panic!("run must be parsed");
+      };
+
+      assert!(options.impure);
+      assert_eq!(options.lock, Some(PathBuf::from("x.lock")));
+      assert_eq!(path, PathBuf::from("f.cab"));
+      assert_eq!(arguments, ["a", "--b"]);
+
+      assert!(matches!(
+         reproducibility(&options, arguments),
+         Ok(runtime::Reproducibility::Impure { .. }),
+      ));
+   }
+
+   #[test]
+   fn highlight_formats() {
+      let cli = Cli::try_parse_from(["cab", "highlight", "--format", "html", "a.cab", "b.cab"])
+         .expect("arguments must parse");
+
+      let Command::Highlight { format, paths } = cli.command else {
+         panic!("highlight must be parsed");
+      };
+
+      assert_eq!(format, HighlightFormat::Html);
+      assert_eq!(paths, [PathBuf::from("a.cab"), PathBuf::from("b.cab")]);
+
+      assert!(Cli::try_parse_from(["cab", "highlight", "--format", "pdf", "a.cab"]).is_err());
+      assert!(Cli::try_parse_from(["cab", "highlight"]).is_err());
+   }
 }
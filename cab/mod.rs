@@ -4,3 +4,11 @@
 #[doc(inline)] pub use cab_runtime as runtime;
 #[doc(inline)] pub use cab_syntax as syntax;
 #[doc(inline)] pub use cab_util as util;
+
+mod evaluator;
+pub use evaluator::{
+   Compiled,
+   Evaluator,
+   Failure,
+   Stage,
+};
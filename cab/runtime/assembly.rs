@@ -0,0 +1,1053 @@
+//! A textual assembly format for [`Code`], used to write virtual machine tests
+//! without going through the frontend.
+//!
+//! Every operation is written as its name followed by its argument, if it has
+//! one. Jumps refer to labels defined with `.name:` in the same block, and
+//! pushes are followed by the pushed value:
+//!
+//! ```txt
+//! Push true
+//! JumpIf .yes
+//! Push "no"
+//! Jump .end
+//! .yes:
+//! Push needs-argument {
+//!    Pop
+//!    Push 1
+//! }
+//! .end:
+//! ```
+//!
+//! Values are written as `true`, `false`, `[]`, `{}`, integers, floats (`1.5`,
+//! `inf`, `NaN`), strings (`"a\n"`), chars (`'a'`), references (`a`, `` `a b`
+//! ``), binds (`@a`, ``@`a b` ``), paths (`path "a" "b"`), errors (`error
+//! "message"`) and thunk codes (`thunkable { ... }`, `needs-argument { ...
+//! }`). `#12` pushes the value at index 12 without defining it and `0x1F`
+//! jumps to byte index 0x1F without a label, so invalid code can be written
+//! too, although [`Code::assemble`] rejects it. Comments start with `//`.
+
+use std::{
+   collections::BTreeMap,
+   fmt::{
+      self,
+      Write as _,
+   },
+   str::FromStr as _,
+};
+
+use cab_util::suffix::Arc as _;
+use cyn::{
+   OptionExt as _,
+   ResultExt as _,
+   bail,
+};
+use dup::Dupe as _;
+use ranged::Span;
+use rustc_hash::FxHashMap;
+
+use crate::{
+   Argument,
+   ByteIndex,
+   Code,
+   Operation,
+   Value,
+   value,
+};
+
+const THUNKABLE: &str = "thunkable";
+const NEEDS_ARGUMENT: &str = "needs-argument";
+
+/// Words that can't be used as plain references.
+const RESERVED: &[&str] = &[
+   "true",
+   "false",
+   "inf",
+   "NaN",
+   "path",
+   "error",
+   THUNKABLE,
+   NEEDS_ARGUMENT,
+];
+
+/// The code in the textual assembly format. Parse it back with
+/// [`Code::assemble`].
+///
+/// Values that can't be written in the format, such as thunks, are written as
+/// `<kind>` and fail to assemble.
+pub struct Assembly<'a>(&'a Code);
+
+impl fmt::Display for Assembly<'_> {
+   fn fmt(&self, writer: &mut fmt::Formatter<'_>) -> fmt::Result {
+      write_code(writer, self.0, 0)
+   }
+}
+
+impl Code {
+   #[must_use]
+   pub fn assembly(&self) -> Assembly<'_> {
+      Assembly(self)
+   }
+
+   /// Parses code in the textual assembly format. The spans of the operations
+   /// point into the source, and every code shares the given path.
+   ///
+   /// Fails if the code is not valid, see [`Code::verify`].
+   pub fn assemble(source: &str, path: value::Path) -> cyn::Result<Self> {
+      let code = Self::assemble_unverified(source, path)?;
+      code.verify()?;
+
+      Ok(code)
+   }
+
+   /// Parses code in the textual assembly format without verifying it, so
+   /// the virtual machine can be tested against invalid code.
+   pub(crate) fn assemble_unverified(source: &str, path: value::Path) -> cyn::Result<Self> {
+      let mut parser = Parser {
+         source,
+         offset: 0,
+         path,
+      };
+
+      let code = parser.code()?;
+
+      if parser.peek()?.is_some() {
+         bail!("unexpected '}}' at {position}", position = parser.position());
+      }
+
+      Ok(code)
+   }
+}
+
+fn is_plain(name: &str) -> bool {
+   name
+      .chars()
+      .next()
+      .is_some_and(|first| first.is_alphabetic() || first == '_')
+      && name
+         .chars()
+         .all(|char| char.is_alphanumeric() || char == '_' || char == '-')
+      && !RESERVED.contains(&name)
+}
+
+fn write_quoted(writer: &mut fmt::Formatter<'_>, string: &str, delimiter: char) -> fmt::Result {
+   writer.write_char(delimiter)?;
+
+   for char in string.chars() {
+      match char {
+         '\\' => writer.write_str("\\\\")?,
+         '\n' => writer.write_str("\\n")?,
+         '\r' => writer.write_str("\\r")?,
+         '\t' => writer.write_str("\\t")?,
+
+         char if char == delimiter => write!(writer, "\\{delimiter}")?,
+         char if char.is_control() => write!(writer, "\\u{{{code:X}}}", code = u32::from(char))?,
+
+         char => writer.write_char(char)?,
+      }
+   }
+
+   writer.write_char(delimiter)
+}
+
+fn write_identifier(writer: &mut fmt::Formatter<'_>, identifier: &str) -> fmt::Result {
+   if is_plain(identifier) {
+      writer.write_str(identifier)
+   } else {
+      write_quoted(writer, identifier, '`')
+   }
+}
+
+fn write_indent(writer: &mut fmt::Formatter<'_>, indent: usize) -> fmt::Result {
+   write!(writer, "{:indent$}", "", indent = indent * 3)
+}
+
+#[stacksafe::stacksafe]
+fn write_code(writer: &mut fmt::Formatter<'_>, code: &Code, indent: usize) -> fmt::Result {
+   let mut operations = Vec::new();
+
+   let mut index = ByteIndex::zero();
+   while let Some((operation, argument, next)) = code.read(index) {
+      operations.push((index, operation, argument));
+      index = next;
+   }
+
+   // Only jumps to the start of an operation or the end can have labels.
+   let mut labels = BTreeMap::<usize, usize>::new();
+   for &(_, _, argument) in &operations {
+      if let Some(target) = argument.as_ref().and_then(Argument::as_byte_index)
+         && (*target == *index
+            || operations
+               .binary_search_by_key(&*target, |&(index, ..)| *index)
+               .is_ok())
+      {
+         labels.insert(*target, 0);
+      }
+   }
+   for (number, label) in labels.values_mut().enumerate() {
+      *label = number;
+   }
+
+   // The index the next value that isn't pushed by index will be at.
+   let mut value_next = 0;
+
+   for (index, operation, argument) in operations {
+      if let Some(label) = labels.get(&*index) {
+         write_indent(writer, indent)?;
+         writeln!(writer, ".L{label}:")?;
+      }
+
+      write_indent(writer, indent)?;
+      write!(writer, "{operation:?}")?;
+
+      match argument {
+         None => {},
+
+         Some(Argument::ValueIndex(value_index)) => {
+            writer.write_char(' ')?;
+
+            match code.values().get(*value_index) {
+               Some(value) if *value_index == value_next => {
+                  value_next += 1;
+                  write_value(writer, value, indent)?;
+               },
+
+               _ => write!(writer, "#{value_index}", value_index = *value_index)?,
+            }
+         },
+
+         Some(Argument::ByteIndex(target)) => {
+            match labels.get(&*target) {
+               Some(label) => write!(writer, " .L{label}")?,
+               None => write!(writer, " {target:#X}", target = *target)?,
+            }
+         },
+
+         Some(Argument::U64(u64)) => write!(writer, " {u64}")?,
+
+         Some(Argument::U16(u16)) => write!(writer, " {u16}")?,
+      }
+
+      writeln!(writer)?;
+   }
+
+   if let Some(label) = labels.get(&*index) {
+      write_indent(writer, indent)?;
+      writeln!(writer, ".L{label}:")?;
+   }
+
+   Ok(())
+}
+
+fn write_value(writer: &mut fmt::Formatter<'_>, value: &Value, indent: usize) -> fmt::Result {
+   match *value {
+      Value::Boolean(boolean) => write!(writer, "{boolean}"),
+
+      Value::Null(_) => writer.write_str("null"),
+
+      Value::Nil(_) => writer.write_str("[]"),
+
+      Value::Attributes(ref attributes) if attributes.is_empty() => writer.write_str("{}"),
+
+      Value::Integer(ref integer) => write!(writer, "{integer}", integer = **integer),
+
+      Value::Float(float) => write!(writer, "{float:?}"),
+
+      Value::String(ref string) => write_quoted(writer, string, '"'),
+
+      Value::Char(char) => write_quoted(writer, char.encode_utf8(&mut [0; 4]), '\''),
+
+      Value::Reference(ref identifier) => write_identifier(writer, identifier),
+
+      Value::Bind(ref identifier) => {
+         writer.write_char('@')?;
+         write_identifier(writer, identifier)
+      },
+
+      Value::Path(ref path) if path.root().is_none() => {
+         writer.write_str("path")?;
+
+         for part in path.subpath() {
+            writer.write_char(' ')?;
+            write_quoted(writer, part, '"')?;
+         }
+
+         Ok(())
+      },
+
+      Value::Error(ref error) if matches!(error.trace, Value::Nil(_)) => {
+         writer.write_str("error ")?;
+         write_value(writer, &error.value, indent)
+      },
+
+      Value::Thunkable(ref code) | Value::NeedsArgumentToThunk(ref code) => {
+         let type_ = if matches!(*value, Value::Thunkable(_)) {
+            THUNKABLE
+         } else {
+            NEEDS_ARGUMENT
+         };
+
+         writeln!(writer, "{type_} {{")?;
+         write_code(writer, code, indent + 1)?;
+         write_indent(writer, indent)?;
+         writer.write_char('}')
+      },
+
+      ref value => write!(writer, "<{kind}>", kind = value.kind()),
+   }
+}
+
+enum Token<'a> {
+   Word(&'a str),
+   String(String),
+   Char(char),
+   /// A backtick quoted identifier.
+   Identifier(String),
+   /// A backtick quoted identifier prefixed with `@`.
+   Bind(String),
+   Colon,
+   Open,
+   Close,
+}
+
+struct Parser<'a> {
+   source: &'a str,
+   offset: usize,
+
+   path: value::Path,
+}
+
+impl<'a> Parser<'a> {
+   fn rest(&self) -> &'a str {
+      &self.source[self.offset..]
+   }
+
+   /// Returns the line and column of the current offset, both one-based.
+   fn position(&self) -> String {
+      let before = &self.source[..self.offset];
+
+      let line = before.matches('\n').count() + 1;
+      let column = before.len() - before.rfind('\n').map_or(0, |newline| newline + 1) + 1;
+
+      format!("{line}:{column}")
+   }
+
+   /// Skips whitespace and comments.
+   fn skip(&mut self) {
+      loop {
+         let rest = self.rest();
+         let trimmed = rest.trim_start();
+         self.offset += rest.len() - trimmed.len();
+
+         if !trimmed.starts_with("//") {
+            break;
+         }
+
+         self.offset += trimmed.find('\n').unwrap_or(trimmed.len());
+      }
+   }
+
+   /// Returns the next token without consuming it.
+   fn peek(&mut self) -> cyn::Result<Option<Token<'a>>> {
+      let offset = self.offset;
+      let token = self.next();
+      self.offset = offset;
+      token
+   }
+
+   fn next(&mut self) -> cyn::Result<Option<Token<'a>>> {
+      self.skip();
+
+      let rest = self.rest();
+      let Some(first) = rest.chars().next() else {
+         return Ok(None);
+      };
+
+      let token = match first {
+         ':' => {
+            self.offset += 1;
+            Token::Colon
+         },
+         '{' => {
+            self.offset += 1;
+            Token::Open
+         },
+         '}' => {
+            self.offset += 1;
+            Token::Close
+         },
+
+         '"' => Token::String(self.quoted('"')?),
+
+         '\'' => {
+            let position = self.position();
+            let string = self.quoted('\'')?;
+
+            let mut chars = string.chars();
+            let (Some(char), None) = (chars.next(), chars.next()) else {
+               bail!("char at {position} must contain exactly one char");
+            };
+
+            Token::Char(char)
+         },
+
+         '`' => Token::Identifier(self.quoted('`')?),
+
+         '@' if rest[1..].starts_with('`') => {
+            self.offset += 1;
+            Token::Bind(self.quoted('`')?)
+         },
+
+         _ => {
+            let len = rest
+               .find(|char: char| char.is_whitespace() || "{}:\"'`".contains(char))
+               .unwrap_or(rest.len());
+
+            self.offset += len;
+            Token::Word(&rest[..len])
+         },
+      };
+
+      Ok(Some(token))
+   }
+
+   /// Parses a string delimited by the delimiter, resolving escapes.
+   fn quoted(&mut self, delimiter: char) -> cyn::Result<String> {
+      let position = self.position();
+      let mut chars = self.rest().char_indices().skip(1);
+      let mut string = String::new();
+
+      loop {
+         let Some((offset, char)) = chars.next() else {
+            bail!("unterminated {delimiter} at {position}");
+         };
+
+         match char {
+            char if char == delimiter => {
+               self.offset += offset + char.len_utf8();
+               return Ok(string);
+            },
+
+            '\\' => {
+               let escaped = match chars.next() {
+                  Some((_, 'n')) => '\n',
+                  Some((_, 'r')) => '\r',
+                  Some((_, 't')) => '\t',
+                  Some((_, '\\')) => '\\',
+                  Some((_, char)) if char == delimiter => delimiter,
+
+                  Some((_, 'u')) => {
+                     let mut code = String::new();
+
+                     if !matches!(chars.next(), Some((_, '{'))) {
+                        bail!("expected '{{' after unicode escape in {delimiter} at {position}");
+                     }
+
+                     loop {
+                        match chars.next() {
+                           Some((_, '}')) => break,
+                           Some((_, char)) => code.push(char),
+                           None => {
+                              bail!("unterminated unicode escape in {delimiter} at {position}")
+                           },
+                        }
+                     }
+
+                     u32::from_str_radix(&code, 16)
+                        .ok()
+                        .and_then(char::from_u32)
+                        .ok_or_chain_with(|| {
+                           format!("invalid unicode escape {code:?} in {delimiter} at {position}")
+                        })?
+                  },
+
+                  _ => bail!("invalid escape in {delimiter} at {position}"),
+               };
+
+               string.push(escaped);
+            },
+
+            char => string.push(char),
+         }
+      }
+   }
+
+   fn expect_next(&mut self, what: &str) -> cyn::Result<Token<'a>> {
+      let position = self.position();
+
+      self
+         .next()?
+         .ok_or_chain_with(|| format!("expected {what} at {position}, got end of input"))
+   }
+
+   /// Parses operations until the end of the source or a closing brace, which
+   /// is not consumed.
+   #[stacksafe::stacksafe]
+   fn code(&mut self) -> cyn::Result<Code> {
+      let mut code = Code::new(self.path.dupe());
+
+      let mut labels = FxHashMap::<&'a str, ByteIndex>::default();
+      let mut jumps = Vec::<(ByteIndex, &'a str, String)>::new();
+
+      loop {
+         self.skip();
+         let start = self.offset;
+         let position = self.position();
+
+         let word = match self.peek()? {
+            None | Some(Token::Close) => break,
+
+            Some(Token::Word(word)) => {
+               self.next()?;
+               word
+            },
+
+            Some(_) => bail!("expected operation or label at {position}"),
+         };
+
+         if let Some(label) = word.strip_prefix('.') {
+            if !matches!(self.next()?, Some(Token::Colon)) {
+               bail!("expected ':' after label definition at {position}");
+            }
+
+            if labels.insert(label, code.here()).is_some() {
+               bail!("label {label:?} at {position} is already defined in this block");
+            }
+
+            continue;
+         }
+
+         let operation = (0..=u8::MAX)
+            .filter_map(|byte| Operation::try_from(byte).ok())
+            .find(|operation| format!("{operation:?}") == word)
+            .ok_or_chain_with(|| format!("unknown operation {word:?} at {position}"))?;
+
+         code.push_operation(Span::at(start, word.len()), operation);
+
+         match operation {
+            Operation::Push => {
+               let position = self.position();
+
+               match self.expect_next("value")? {
+                  Token::Word(index) if let Some(index) = index.strip_prefix('#') => {
+                     let index = index
+                        .parse::<u64>()
+                        .chain_err_with(|| format!("invalid value index at {position}"))?;
+
+                     code.push_u64(index);
+                  },
+
+                  token => {
+                     let value = self.value(token, &position)?;
+
+                     let index = code.value(value);
+                     code.push_u64(*index as _);
+                  },
+               }
+            },
+
+            Operation::Jump | Operation::JumpIf | Operation::JumpIfError => {
+               let position = self.position();
+
+               match self.expect_next("jump target")? {
+                  Token::Word(label) if let Some(label) = label.strip_prefix('.') => {
+                     jumps.push((code.push_u16(u16::default()), label, position));
+                  },
+
+                  Token::Word(target) if let Some(target) = target.strip_prefix("0x") => {
+                     let target = u16::from_str_radix(target, 16)
+                        .chain_err_with(|| format!("invalid jump target at {position}"))?;
+
+                     code.push_u16(target);
+                  },
+
+                  _ => bail!("expected label or byte index at {position}"),
+               }
+            },
+
+            Operation::Interpolate => {
+               let position = self.position();
+
+               let Token::Word(count) = self.expect_next("count")? else {
+                  bail!("expected count at {position}");
+               };
+
+               let count = count
+                  .parse::<u64>()
+                  .chain_err_with(|| format!("invalid count at {position}"))?;
+
+               code.push_u64(count);
+            },
+
+            Operation::ResolveFrom => {
+               let position = self.position();
+
+               let Token::Word(depth) = self.expect_next("depth")? else {
+                  bail!("expected depth at {position}");
+               };
+
+               let depth = depth
+                  .parse::<u16>()
+                  .chain_err_with(|| format!("invalid depth at {position}"))?;
+
+               code.push_u16(depth);
+            },
+
+            _ => {},
+         }
+      }
+
+      for (index, label, position) in jumps {
+         let target = labels
+            .get(label)
+            .ok_or_chain_with(|| format!("undefined label {label:?} at {position}"))?;
+
+         code.point(index, *target);
+      }
+
+      Ok(code)
+   }
+
+   fn value(&mut self, token: Token<'a>, position: &str) -> cyn::Result<Value> {
+      let word = match token {
+         Token::String(string) => return Ok(Value::from(value::SString::from(&*string))),
+         Token::Char(char) => return Ok(Value::Char(char)),
+         Token::Identifier(identifier) => {
+            return Ok(Value::Reference(value::SString::from(&*identifier)));
+         },
+         Token::Bind(identifier) => return Ok(Value::Bind(value::SString::from(&*identifier))),
+
+         Token::Open => {
+            if !matches!(self.next()?, Some(Token::Close)) {
+               bail!("only empty attributes can be assembled, at {position}");
+            }
+
+            return Ok(Value::from(value::attributes::new! {}));
+         },
+
+         Token::Colon | Token::Close => bail!("expected value at {position}"),
+
+         Token::Word(word) => word,
+      };
+
+      Ok(match word {
+         "true" => Value::Boolean(true),
+         "false" => Value::Boolean(false),
+
+         "null" => Value::from(value::Null),
+
+         "[]" => Value::from(value::Nil),
+
+         "inf" | "-inf" | "NaN" => {
+            Value::Float(f64::from_str(word).expect("word is a valid float"))
+         },
+
+         "path" => {
+            let mut parts = Vec::new();
+
+            while let Some(Token::String(part)) = self.peek()? {
+               self.next()?;
+               parts.push(value::SString::from(&*part));
+            }
+
+            Value::from(value::Path::rootless(parts.into_iter().collect()))
+         },
+
+         "error" => {
+            let position = self.position();
+            let token = self.expect_next("value")?;
+
+            Value::from(value::Error::new(self.value(token, &position)?).arc())
+         },
+
+         THUNKABLE | NEEDS_ARGUMENT => {
+            if !matches!(self.next()?, Some(Token::Open)) {
+               bail!("expected '{{' after {word} at {position}");
+            }
+
+            let code = self.code()?.arc();
+
+            if !matches!(self.next()?, Some(Token::Close)) {
+               bail!("unclosed {word} at {position}");
+            }
+
+            if word == THUNKABLE {
+               Value::Thunkable(code)
+            } else {
+               Value::NeedsArgumentToThunk(code)
+            }
+         },
+
+         word
+            if let Some(identifier) = word.strip_prefix('@')
+               && is_plain(identifier) =>
+         {
+            Value::Bind(value::SString::from(identifier))
+         },
+
+         word if is_plain(word) => Value::Reference(value::SString::from(word)),
+
+         word if let Ok(integer) = num::BigInt::from_str(word) => {
+            Value::from(value::Integer::from(integer))
+         },
+
+         word if let Ok(float) = f64::from_str(word) => Value::Float(float),
+
+         word => bail!("invalid value {word:?} at {position}"),
+      })
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use proptest::prelude::*;
+   use rpds::ListSync as List;
+
+   use super::*;
+   use crate::builtin::evaluate_code;
+
+   fn assemble(source: &str) -> Code {
+      Code::assemble_unverified(source, value::Path::rootless(List::new_sync()))
+         .expect("source must assemble")
+   }
+
+   /// Asserts that assembling the assembly of the code gives back the same
+   /// code.
+   #[track_caller]
+   fn assert_round_trip(code: &Code) {
+      let assembly = code.assembly().to_string();
+      let reassembled = assemble(&assembly);
+
+      assert_eq!(code.bytes(), reassembled.bytes(), "{assembly}");
+      assert_eq!(assembly, reassembled.assembly().to_string());
+   }
+
+   #[test]
+   fn values() {
+      let source = r#"
+         Push true
+         Push false
+         Push []
+         Push {}
+         Push 42
+         Push -123456789012345678901234
+         Push 1.5
+         Push -inf
+         Push NaN
+         Push "a \"b\"\n\u{1}"
+         Push '\''
+         Push a
+         Push `a b`
+         Push `true`
+         Push @a
+         Push @`a b`
+         Push path "a" "b"
+         Push path
+         Push error "message"
+         Push #100
+      "#;
+
+      assert_round_trip(&assemble(source));
+   }
+
+   #[test]
+   fn labels_and_blocks() {
+      let code = assemble(
+         "
+         // Comments are ignored.
+         .start:
+         Push true
+         JumpIf .end
+         Jump .start
+         Push thunkable {
+            .loop:
+            Jump .loop
+         }
+         Push needs-argument {
+            Jump .end // Labels are scoped to their block.
+            .end:
+         }
+         JumpIfError 0x1
+         .end:
+         ",
+      );
+
+      assert_round_trip(&code);
+
+      let assembly = code.assembly().to_string();
+      assert!(assembly.contains("JumpIf .L1\n"), "{assembly}");
+      assert!(assembly.contains("JumpIfError 0x1\n"), "{assembly}");
+   }
+
+   #[test]
+   fn errors() {
+      for source in [
+         "Push",
+         "Push <thunk>",
+         "Frobnicate",
+         "Jump .nowhere",
+         ".a:\n.a:",
+         "Push thunkable { Push 1",
+         "Push 1 }",
+         "Push \"unterminated",
+         "Push 'ab'",
+         "Push { Push 1 }",
+         "Interpolate many",
+      ] {
+         assert!(
+            Code::assemble(source, value::Path::rootless(List::new_sync())).is_err(),
+            "{source:?} must fail to assemble",
+         );
+      }
+   }
+
+   #[test]
+   fn invalid() {
+      for source in [
+         // Out of range jump.
+         "Push true\nJumpIf 0xFF",
+         // Stack underflow.
+         "Pop",
+         // Undefined value.
+         "Push #100",
+         // Unbalanced scope.
+         "ScopeStart\nPush 1",
+         // Invalid code of a thunk.
+         "Push thunkable { Pop }",
+      ] {
+         assert!(
+            Code::assemble_unverified(source, value::Path::rootless(List::new_sync())).is_ok(),
+            "{source:?} must parse",
+         );
+         assert!(
+            Code::assemble(source, value::Path::rootless(List::new_sync())).is_err(),
+            "{source:?} must fail to assemble",
+         );
+      }
+   }
+
+   #[test]
+   fn compiled() {
+      use cab_syntax as syntax;
+
+      for source in [
+         "[ 1, 2.5, 'c', \"s\\n\", ./path, foo ]",
+         "if true then { @a = 1; @b = a } else @x => x",
+         "(@x => @y => x) 1 2",
+         "{ @a = 1 }.a",
+         "\"a\\(1)b\"",
+      ] {
+         let parse = syntax::ParseOracle::new().parse(syntax::tokenize(source));
+         let lower = syntax::LowerOracle::new().lower(parse.expression.as_ref());
+
+         let code = crate::CompileOracle::new()
+            .compile(lower.expression())
+            .path(value::Path::rootless(List::new_sync()))
+            .code;
+
+         assert_round_trip(&code);
+         assert_round_trip(&code.optimized());
+      }
+   }
+
+   #[tokio::test]
+   async fn evaluate() {
+      let code = Code::assemble(
+         r#"
+         ScopeStart
+         Push false
+         JumpIf .yes
+         Pop
+         Push "no"
+         Jump .end
+         .yes:
+         Pop
+         Push "yes"
+         .end:
+         ScopeEnd
+         "#,
+         value::Path::rootless(List::new_sync()),
+      )
+      .expect("source must assemble");
+
+      let value = evaluate_code(code, Span::dummy()).await;
+      assert!(matches!(value, Value::String(ref string) if &**string == "no"));
+   }
+
+   fn arbitrary_value() -> impl Strategy<Value = Value> {
+      prop_oneof![
+         any::<bool>().prop_map(Value::Boolean),
+         Just(Value::from(value::Null)),
+         Just(Value::from(value::Nil)),
+         any::<i128>()
+            .prop_map(|integer| Value::from(value::Integer::from(num::BigInt::from(integer)))),
+         any::<f64>().prop_map(Value::Float),
+         any::<char>().prop_map(Value::Char),
+         any::<String>().prop_map(|string| Value::from(value::SString::from(&*string))),
+         any::<String>().prop_map(|string| Value::Reference(value::SString::from(&*string))),
+         any::<String>().prop_map(|string| Value::Bind(value::SString::from(&*string))),
+         prop::collection::vec(any::<String>(), 0..3).prop_map(|parts| {
+            Value::from(value::Path::rootless(
+               parts
+                  .iter()
+                  .map(|part| value::SString::from(&**part))
+                  .collect(),
+            ))
+         }),
+      ]
+   }
+
+   /// An operation and what to use for its argument, if it has one.
+   #[derive(Debug, Clone)]
+   enum Item {
+      Operation(u8),
+      Value(Arbitrary),
+      RawValue(u64),
+      /// Jumps to the start of the operation at this index, modulo the
+      /// operation count.
+      Jump(u8, usize),
+      /// Jumps to an arbitrary byte index.
+      RawJump(u8, u16),
+      Interpolate(u64),
+      ResolveFrom(u16),
+      Code(bool, Vec<Item>),
+   }
+
+   /// A value that is debug formatted in the assembly format.
+   #[derive(Clone)]
+   struct Arbitrary(Value);
+
+   impl fmt::Debug for Arbitrary {
+      fn fmt(&self, writer: &mut fmt::Formatter<'_>) -> fmt::Result {
+         write_value(writer, &self.0, 0)
+      }
+   }
+
+   fn arbitrary_items() -> impl Strategy<Value = Vec<Item>> {
+      let jump = prop::sample::select(vec![
+         Operation::Jump as u8,
+         Operation::JumpIf as u8,
+         Operation::JumpIfError as u8,
+      ]);
+
+      let leaf = prop_oneof![
+         prop::sample::select(
+            (0..=u8::MAX)
+               .filter_map(|byte| Operation::try_from(byte).ok())
+               .filter(|&operation| {
+                  !matches!(
+                     operation,
+                     Operation::Push
+                        | Operation::Jump
+                        | Operation::JumpIf
+                        | Operation::JumpIfError
+                        | Operation::Interpolate
+                        | Operation::ResolveFrom
+                  )
+               })
+               .map(|operation| operation as u8)
+               .collect::<Vec<_>>(),
+         )
+         .prop_map(Item::Operation),
+         arbitrary_value().prop_map(|value| Item::Value(Arbitrary(value))),
+         (1000_u64..).prop_map(Item::RawValue),
+         (jump.clone(), any::<usize>()).prop_map(|(operation, index)| Item::Jump(operation, index)),
+         (jump, any::<u16>()).prop_map(|(operation, target)| Item::RawJump(operation, target)),
+         any::<u64>().prop_map(Item::Interpolate),
+         any::<u16>().prop_map(Item::ResolveFrom),
+      ];
+
+      let item = leaf.prop_recursive(3, 32, 8, |item| {
+         (any::<bool>(), prop::collection::vec(item, 0..8))
+            .prop_map(|(thunkable, items)| Item::Code(thunkable, items))
+      });
+
+      prop::collection::vec(item, 1..16)
+   }
+
+   fn build(items: Vec<Item>) -> Code {
+      let mut code = Code::new(value::Path::rootless(List::new_sync()));
+      let mut starts = Vec::new();
+      let mut jumps = Vec::new();
+
+      for item in items {
+         let span = Span::dummy();
+
+         match item {
+            Item::Operation(operation) => {
+               let operation = Operation::try_from(operation).expect("operation must be valid");
+               starts.push(code.push_operation(span, operation));
+            },
+
+            Item::Value(Arbitrary(value)) => {
+               starts.push(code.push_operation(span, Operation::Push));
+               let index = code.value(value);
+               code.push_u64(*index as _);
+            },
+
+            Item::RawValue(index) => {
+               starts.push(code.push_operation(span, Operation::Push));
+               code.push_u64(index);
+            },
+
+            Item::Code(thunkable, items) => {
+               let nested = build(items).arc();
+
+               starts.push(code.push_operation(span, Operation::Push));
+               let index = code.value(if thunkable {
+                  Value::Thunkable(nested)
+               } else {
+                  Value::NeedsArgumentToThunk(nested)
+               });
+               code.push_u64(*index as _);
+            },
+
+            Item::Jump(operation, target) => {
+               let operation = Operation::try_from(operation).expect("operation must be valid");
+               starts.push(code.push_operation(span, operation));
+               jumps.push((code.push_u16(u16::default()), target));
+            },
+
+            Item::RawJump(operation, target) => {
+               let operation = Operation::try_from(operation).expect("operation must be valid");
+               starts.push(code.push_operation(span, operation));
+               code.push_u16(target);
+            },
+
+            Item::Interpolate(count) => {
+               starts.push(code.push_operation(span, Operation::Interpolate));
+               code.push_u64(count);
+            },
+
+            Item::ResolveFrom(depth) => {
+               starts.push(code.push_operation(span, Operation::ResolveFrom));
+               code.push_u16(depth);
+            },
+         }
+      }
+
+      starts.push(code.here());
+
+      for (index, target) in jumps {
+         code.point(index, starts[target % starts.len()]);
+      }
+
+      code
+   }
+
+   proptest! {
+      #[test]
+      fn round_trip(items in arbitrary_items()) {
+         let code = build(items);
+
+         let assembly = code.assembly().to_string();
+         let reassembled =
+            Code::assemble_unverified(&assembly, value::Path::rootless(List::new_sync()));
+
+         prop_assert!(reassembled.is_ok(), "{assembly}");
+         let reassembled = reassembled.expect("reassembling was checked to succeed");
+
+         prop_assert_eq!(code.bytes(), reassembled.bytes());
+         prop_assert_eq!(assembly, reassembled.assembly().to_string());
+      }
+   }
+}
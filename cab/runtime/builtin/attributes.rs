@@ -0,0 +1,218 @@
+use dup::Dupe as _;
+
+use super::{
+   apply,
+   error,
+   forced,
+   list,
+   list_from,
+};
+use crate::{
+   State,
+   Value,
+   value,
+};
+
+/// Returns the names of the attributes, sorted.
+pub async fn names(state: &State, [attributes]: [Value; 1]) -> Result<Value, Value> {
+   let attributes = forced::<value::Attributes>(state, attributes).await?;
+
+   Ok(list_from(
+      attributes.iter().map(|(name, _)| Value::from(name.dupe())),
+   ))
+}
+
+/// Returns the values of the attributes, sorted by their names.
+pub async fn values(state: &State, [attributes]: [Value; 1]) -> Result<Value, Value> {
+   let attributes = forced::<value::Attributes>(state, attributes).await?;
+
+   Ok(list_from(attributes.iter().map(|(_, value)| value.dupe())))
+}
+
+pub async fn has(state: &State, [name, attributes]: [Value; 2]) -> Result<Value, Value> {
+   let name = forced::<value::SString>(state, name).await?;
+   let attributes = forced::<value::Attributes>(state, attributes).await?;
+
+   Ok(Value::Boolean(attributes.get(&name).is_some()))
+}
+
+/// Returns the attribute with the given name, or the default if there is none.
+pub async fn get(state: &State, [name, default, attributes]: [Value; 3]) -> Result<Value, Value> {
+   let name = forced::<value::SString>(state, name).await?;
+   let attributes = forced::<value::Attributes>(state, attributes).await?;
+
+   Ok(attributes.get(&name).map_or(default, |value| value.dupe()))
+}
+
+/// Removes every attribute in the list of names. Names that aren't in the
+/// attributes are ignored.
+pub async fn remove(state: &State, [names, attributes]: [Value; 2]) -> Result<Value, Value> {
+   let mut attributes = forced::<value::Attributes>(state, attributes).await?;
+
+   for name in list(state, names).await? {
+      attributes = attributes.remove(&forced::<value::SString>(state, name).await?);
+   }
+
+   Ok(Value::from(attributes))
+}
+
+/// Calls the function with the name and the value of every attribute. The
+/// calls are not forced.
+pub async fn map(state: &State, [function, attributes]: [Value; 2]) -> Result<Value, Value> {
+   let attributes = forced::<value::Attributes>(state, attributes).await?;
+
+   let mut mapped = value::attributes::new! {};
+
+   for (name, value) in &attributes {
+      let value = apply(state, function.dupe(), [
+         Value::from(name.dupe()),
+         value.dupe(),
+      ])
+      .await?;
+
+      mapped = mapped.insert(name.dupe(), value);
+   }
+
+   Ok(Value::from(mapped))
+}
+
+/// Keeps the attributes the predicate returns true for when called with their
+/// name and value.
+pub async fn filter(state: &State, [predicate, attributes]: [Value; 2]) -> Result<Value, Value> {
+   let attributes = forced::<value::Attributes>(state, attributes).await?;
+
+   let mut filtered = attributes.dupe();
+
+   for (name, value) in &attributes {
+      let keep = apply(state, predicate.dupe(), [
+         Value::from(name.dupe()),
+         value.dupe(),
+      ])
+      .await?;
+
+      if !forced::<bool>(state, keep).await? {
+         filtered = filtered.remove(name);
+      }
+   }
+
+   Ok(Value::from(filtered))
+}
+
+/// Creates attributes from a list of attributes with `name` and `value`
+/// attributes. The first occurrence of a name takes precedence.
+pub async fn from_list(state: &State, [entries]: [Value; 1]) -> Result<Value, Value> {
+   let mut attributes = value::attributes::new! {};
+
+   for entry in list(state, entries).await? {
+      let entry = forced::<value::Attributes>(state, entry).await?;
+
+      let (Some(name), Some(value)) = (
+         entry.get(&value::string::new!("name")),
+         entry.get(&value::string::new!("value")),
+      ) else {
+         return Err(error("expected attributes with a name and a value"));
+      };
+
+      let name = forced::<value::SString>(state, name.dupe()).await?;
+
+      if attributes.get(&name).is_none() {
+         attributes = attributes.insert(name, value.dupe());
+      }
+   }
+
+   Ok(Value::from(attributes))
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::builtin::{
+      evaluate,
+      string,
+   };
+
+   #[tokio::test]
+   async fn names_and_values_are_sorted() {
+      assert_eq!(
+         string(&evaluate("toJSON (attrNames { @c = 1; @a = 2; @b = 3 })").await),
+         r#"["a","b","c"]"#,
+      );
+      assert_eq!(
+         string(&evaluate("toJSON (attrValues { @c = 1; @a = 2; @b = 3 })").await),
+         "[2,3,1]",
+      );
+   }
+
+   #[tokio::test]
+   async fn has_and_get() {
+      assert_eq!(
+         string(&evaluate(r#"toJSON [ hasAttr "a" { @a = 1 }, hasAttr "b" { @a = 1 } ]"#).await),
+         "[true,false]",
+      );
+      assert_eq!(
+         string(&evaluate(r#"getAttr "a" "default" { @a = "x" }"#).await),
+         "x",
+      );
+      assert_eq!(
+         string(&evaluate(r#"getAttr "b" "default" { @a = "x" }"#).await),
+         "default",
+      );
+   }
+
+   #[tokio::test]
+   async fn remove() {
+      assert_eq!(
+         string(&evaluate(r#"toJSON (removeAttrs [ "a", "c" ] { @a = 1; @b = 2 })"#).await),
+         r#"{"b":2}"#,
+      );
+   }
+
+   #[tokio::test]
+   async fn map_and_filter() {
+      assert_eq!(
+         string(&evaluate("toJSON (mapAttrs (@name => @value => name) { @a = 1; @b = 2 })").await),
+         r#"{"a":"a","b":"b"}"#,
+      );
+      assert_eq!(
+         string(
+            &evaluate("toJSON (filterAttrs (@name => @value => value) { @a = true; @b = false })")
+               .await
+         ),
+         r#"{"a":true}"#,
+      );
+      assert!(matches!(
+         evaluate("filterAttrs (@name => @value => 1) { @a = true }").await,
+         Value::Error(_),
+      ));
+   }
+
+   #[tokio::test]
+   async fn from_list() {
+      assert_eq!(
+         string(
+            &evaluate(
+               "toJSON (listToAttrs [ { @name = \"a\"; @value = 1 }, { @name = \"a\"; @value = \
+                2 } ])"
+            )
+            .await
+         ),
+         r#"{"a":1}"#,
+      );
+      assert!(matches!(
+         evaluate(r#"listToAttrs [ { @name = "a" } ]"#).await,
+         Value::Error(_),
+      ));
+   }
+
+   #[tokio::test]
+   async fn recursive() {
+      assert_eq!(
+         string(&evaluate("toJSON { @a = b; @b = 1 }").await),
+         r#"{"a":1,"b":1}"#,
+      );
+      assert_eq!(
+         string(&evaluate("toJSON { @a = b; @x = { @y = 2 }; @c = x.y; @b = [ 1, c ] }").await),
+         r#"{"a":[1,2],"b":[1,2],"c":2,"x":{"y":2}}"#,
+      );
+   }
+}
@@ -0,0 +1,485 @@
+use std::{
+   fmt::Write as _,
+   io,
+   path::{
+      self,
+      PathBuf,
+   },
+   pin::Pin,
+   process::{
+      self,
+      Stdio,
+   },
+   sync::atomic::{
+      self,
+      AtomicU64,
+   },
+};
+
+use bytes::Bytes;
+use cab_util::suffix::Arc as _;
+use dup::Dupe as _;
+use rpds::ListSync as List;
+use sha2::{
+   Digest as _,
+   Sha256,
+};
+use tokio::{
+   fs,
+   process::Command,
+};
+
+use super::{
+   error,
+   forced,
+   list,
+};
+use crate::{
+   State,
+   Value,
+   value,
+};
+
+/// The number of scratch directories created by this process, to keep the
+/// scratch directories of concurrent commands apart.
+static SCRATCHES: AtomicU64 = AtomicU64::new(0);
+
+/// The contents of an input, read before the command is run.
+enum Input {
+   File(Bytes),
+   Directory(Vec<(value::SString, Input)>),
+}
+
+impl Input {
+   /// Reads the path. It is read as a directory if it can be listed, and as a
+   /// file otherwise.
+   fn read(path: &value::Path) -> Pin<Box<dyn Future<Output = Option<Self>> + '_>> {
+      Box::pin(async move {
+         if let Ok(subpaths) = path.list().await {
+            let mut entries = Vec::new();
+
+            for subpath in &subpaths {
+               let name = subpath.last()?.dupe();
+               let input = Self::read(&path.get(name.dupe())).await?;
+
+               entries.push((name, input));
+            }
+
+            return Some(Self::Directory(entries));
+         }
+
+         path.read().await.ok().map(Self::File)
+      })
+   }
+
+   fn hash(&self, hasher: &mut Sha256) {
+      match *self {
+         Self::File(ref content) => {
+            hasher.update(b"f");
+            update(hasher, content);
+         },
+
+         Self::Directory(ref entries) => {
+            hasher.update(b"d");
+            hasher.update((entries.len() as u64).to_le_bytes());
+
+            for &(ref name, ref input) in entries {
+               update(hasher, name.as_bytes());
+               input.hash(hasher);
+            }
+         },
+      }
+   }
+
+   fn write<'a>(
+      &'a self,
+      path: &'a path::Path,
+   ) -> Pin<Box<dyn Future<Output = io::Result<()>> + 'a>> {
+      Box::pin(async move {
+         match *self {
+            Self::File(ref content) => fs::write(path, content).await,
+
+            Self::Directory(ref entries) => {
+               fs::create_dir(path).await?;
+
+               for &(ref name, ref input) in entries {
+                  input.write(&path.join(&***name)).await?;
+               }
+
+               Ok(())
+            },
+         }
+      })
+   }
+}
+
+/// Feeds the bytes to the hasher, prefixed with their length so consecutive
+/// fields can't run into each other.
+fn update(hasher: &mut Sha256, bytes: &[u8]) {
+   hasher.update((bytes.len() as u64).to_le_bytes());
+   hasher.update(bytes);
+}
+
+/// Finds the program in the `PATH` of the environment, unless it is a path
+/// already. The `PATH` cab runs with is never searched.
+async fn resolve(
+   program: &str,
+   environment: &[(value::SString, value::SString)],
+) -> Result<PathBuf, Value> {
+   if program.contains(path::MAIN_SEPARATOR) {
+      return Ok(PathBuf::from(program));
+   }
+
+   let search = environment
+      .iter()
+      .find(|&&(ref name, _)| &***name == "PATH")
+      .map(|&(_, ref value)| value);
+
+   for directory in search.into_iter().flat_map(|search| search.split(':')) {
+      if directory.is_empty() {
+         continue;
+      }
+
+      let candidate = path::Path::new(directory).join(program);
+
+      if fs::metadata(&candidate)
+         .await
+         .is_ok_and(|metadata| metadata.is_file())
+      {
+         return Ok(candidate);
+      }
+   }
+
+   Err(error(format!(
+      "'{program}' was not found in the PATH of the environment"
+   )))
+}
+
+/// Runs the command in the scratch directory and moves its output to the
+/// path it is cached at.
+async fn execute(
+   program: &str,
+   arguments: &[value::SString],
+   environment: &[(value::SString, value::SString)],
+   inputs: &[(value::SString, Input)],
+   scratch: &path::Path,
+   output: &path::Path,
+) -> Result<(), Value> {
+   let program = resolve(program, environment).await?;
+
+   let directory = scratch.join("work");
+   let out = scratch.join("out");
+
+   fs::create_dir_all(&directory).await.map_err(|io| {
+      error(format!(
+         "failed to create '{directory}': {io}",
+         directory = directory.display(),
+      ))
+   })?;
+
+   for &(ref name, ref input) in inputs {
+      input.write(&directory.join(&***name)).await.map_err(|io| {
+         error(format!(
+            "failed to copy input '{name}': {io}",
+            name = &***name,
+         ))
+      })?;
+   }
+
+   let ran = Command::new(&program)
+      .args(arguments.iter().map(|argument| &***argument))
+      .env_clear()
+      .envs(
+         environment
+            .iter()
+            .map(|&(ref name, ref value)| (&***name, &***value)),
+      )
+      .env("out", &out)
+      .current_dir(&directory)
+      .stdin(Stdio::null())
+      .output()
+      .await
+      .map_err(|io| {
+         error(format!(
+            "failed to run '{program}': {io}",
+            program = program.display(),
+         ))
+      })?;
+
+   if !ran.status.success() {
+      return Err(error(format!(
+         "'{program}' failed with {status}:\n{stderr}",
+         program = program.display(),
+         status = ran.status,
+         stderr = String::from_utf8_lossy(&ran.stderr).trim_end(),
+      )));
+   }
+
+   if fs::symlink_metadata(&out).await.is_err() {
+      return Err(error(format!(
+         "'{program}' did not write its output to the path in 'out'",
+         program = program.display(),
+      )));
+   }
+
+   match fs::rename(&out, output).await {
+      Ok(()) => Ok(()),
+
+      // Another command with the same hash finished first.
+      Err(_) if fs::try_exists(output).await.unwrap_or(false) => Ok(()),
+
+      Err(io) => Err(error(format!("failed to cache the output: {io}"))),
+   }
+}
+
+/// Runs a command and returns the path of its output. The output is cached by
+/// the command, its environment and the contents of its inputs, and the command
+/// is only run if there is no cached output.
+///
+/// The command is described by attributes with a `program` and optionally
+/// `arguments`, an `environment` and `inputs`:
+///
+/// - The command doesn't inherit any environment variables, it only gets the
+///   ones in `environment`, plus `out`.
+/// - It runs in an empty working directory, that only has the `inputs` copied
+///   into it by their names.
+/// - It must write its output, which can be a file or a directory, to the
+///   path in `out`.
+pub async fn run(state: &State, [command]: [Value; 1]) -> Result<Value, Value> {
+   let command = forced::<value::Attributes>(state, command).await?;
+
+   let program = forced::<value::SString>(
+      state,
+      command
+         .get(&value::string::new!("program"))
+         .ok_or_else(|| error("command must have a program"))?
+         .dupe(),
+   )
+   .await?;
+
+   let mut arguments = Vec::new();
+   if let Some(value) = command.get(&value::string::new!("arguments")) {
+      for argument in list(state, value.dupe()).await? {
+         arguments.push(forced::<value::SString>(state, argument).await?);
+      }
+   }
+
+   let mut environment = Vec::new();
+   if let Some(value) = command.get(&value::string::new!("environment")) {
+      for (name, value) in &forced::<value::Attributes>(state, value.dupe()).await? {
+         if &***name == "out" {
+            return Err(error(
+               "environment must not contain 'out', it is set to the path of the output",
+            ));
+         }
+
+         environment.push((
+            name.dupe(),
+            forced::<value::SString>(state, value.dupe()).await?,
+         ));
+      }
+   }
+
+   let mut inputs = Vec::new();
+   if let Some(value) = command.get(&value::string::new!("inputs")) {
+      for (name, value) in &forced::<value::Attributes>(state, value.dupe()).await? {
+         if matches!(&***name, "" | "." | "..") || name.contains(path::MAIN_SEPARATOR) {
+            return Err(error(format!(
+               "input name '{name}' is not a valid file name",
+               name = &***name,
+            )));
+         }
+
+         let path = forced::<value::Path>(state, value.dupe()).await?;
+         let input = Input::read(&path)
+            .await
+            .ok_or_else(|| error(format!("failed to read input '{name}'", name = &***name)))?;
+
+         inputs.push((name.dupe(), input));
+      }
+   }
+
+   let Some(ref cache) = state.cache else {
+      return Err(error("cannot run commands without a cache directory"));
+   };
+
+   let hash = {
+      let mut hasher = Sha256::new();
+
+      update(&mut hasher, program.as_bytes());
+
+      for strings in [
+         arguments
+            .iter()
+            .map(|argument| argument.as_bytes())
+            .collect::<Vec<_>>(),
+         environment
+            .iter()
+            .flat_map(|&(ref name, ref value)| [name.as_bytes(), value.as_bytes()])
+            .collect(),
+      ] {
+         hasher.update((strings.len() as u64).to_le_bytes());
+
+         for string in strings {
+            update(&mut hasher, string);
+         }
+      }
+
+      hasher.update((inputs.len() as u64).to_le_bytes());
+
+      for &(ref name, ref input) in &inputs {
+         update(&mut hasher, name.as_bytes());
+         input.hash(&mut hasher);
+      }
+
+      hasher
+         .finalize()
+         .iter()
+         .fold(String::new(), |mut hash, byte| {
+            let _ = write!(hash, "{byte:02x}");
+            hash
+         })
+   };
+
+   let output = cache.join(&hash);
+
+   if !fs::try_exists(&output).await.unwrap_or(false) {
+      let scratch = cache.join(format!(
+         ".{hash}.{process}.{count}",
+         process = process::id(),
+         count = SCRATCHES.fetch_add(1, atomic::Ordering::Relaxed),
+      ));
+
+      let result = execute(
+         &program,
+         &arguments,
+         &environment,
+         &inputs,
+         &scratch,
+         &output,
+      )
+      .await;
+
+      let _ = fs::remove_dir_all(&scratch).await;
+
+      result?;
+   }
+
+   Ok(Value::from(
+      value::Path::new()
+         .root(value::path::cache(cache.clone()).arc())
+         .subpath(List::new_sync().push_front(value::SString::from(&*hash))),
+   ))
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::builtin::{
+      TemporaryDirectory,
+      evaluate_in,
+   };
+
+   /// Returns a command that runs the script with `sh`, with the rest of the
+   /// attributes appended.
+   fn sh(script: &str, rest: &str) -> String {
+      format!(
+         r#"command {{
+            @program = "sh";
+            @arguments = [ "-c", "{script}" ];
+            @environment = {{ @PATH = "/bin:/usr/bin" }};
+            {rest}
+         }}"#
+      )
+   }
+
+   async fn output(source: &str, cache: &TemporaryDirectory) -> String {
+      let Value::Path(path) = evaluate_in(source, cache).await else {
+         panic!("command must evaluate to a path");
+      };
+
+      let content = path.read().await.expect("output must be readable");
+
+      String::from_utf8(content.to_vec()).expect("output must be valid UTF-8")
+   }
+
+   #[tokio::test]
+   async fn writes_output() {
+      let cache = TemporaryDirectory::new();
+
+      assert_eq!(
+         output(&sh("echo hello > $out", ""), &cache).await,
+         "hello\n",
+      );
+   }
+
+   #[tokio::test]
+   async fn copies_inputs() {
+      let cache = TemporaryDirectory::new();
+
+      let inputs = format!(
+         "@inputs = {{ @first = {first}; @nested = {nested} }}",
+         first = sh("echo first > $out", ""),
+         nested = sh("mkdir $out && echo second > $out/second", ""),
+      );
+
+      assert_eq!(
+         output(&sh("cat first nested/second > $out", &inputs), &cache).await,
+         "first\nsecond\n",
+      );
+   }
+
+   #[tokio::test]
+   async fn is_hermetic() {
+      let cache = TemporaryDirectory::new();
+
+      assert_eq!(
+         output(&sh("echo [$HOME] > $out; ls -A >> $out", ""), &cache).await,
+         "[]\n",
+      );
+   }
+
+   #[tokio::test]
+   async fn caches_output() {
+      let cache = TemporaryDirectory::new();
+      let markers = TemporaryDirectory::new();
+
+      let marker = markers.0.join("marker");
+
+      let source = sh(
+         &format!(
+            "echo ran >> {marker}; echo cached > $out",
+            marker = marker.display(),
+         ),
+         "",
+      );
+
+      assert_eq!(output(&source, &cache).await, "cached\n");
+      assert_eq!(output(&source, &cache).await, "cached\n");
+
+      assert_eq!(
+         fs::read_to_string(&marker)
+            .await
+            .expect("marker must be written"),
+         "ran\n",
+      );
+   }
+
+   #[tokio::test]
+   async fn errors() {
+      let cache = TemporaryDirectory::new();
+
+      for source in [
+         sh("exit 3", ""),
+         sh("true", ""),
+         r#"command { @program = "sh"; @environment = { @out = "/" } }"#.to_owned(),
+         r#"command { @program = "sh"; @arguments = [ "-c", "true > $out" ] }"#.to_owned(),
+         "command { @arguments = [] }".to_owned(),
+      ] {
+         assert!(
+            matches!(evaluate_in(&source, &cache).await, Value::Error(_)),
+            "{source}",
+         );
+      }
+   }
+}
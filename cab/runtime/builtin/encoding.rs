@@ -0,0 +1,251 @@
+use multibase::Base;
+
+use super::{
+   bytes_from,
+   error,
+   forced,
+};
+use crate::{
+   State,
+   Value,
+   value,
+};
+
+/// The bases `toMultibase` takes, by their names in the multibase table.
+const BASES: &[(&str, Base)] = &[
+   ("base16", Base::Base16Lower),
+   ("base16upper", Base::Base16Upper),
+   ("base32", Base::Base32Lower),
+   ("base32upper", Base::Base32Upper),
+   ("base32pad", Base::Base32PadLower),
+   ("base32padupper", Base::Base32PadUpper),
+   ("base58btc", Base::Base58Btc),
+   ("base64", Base::Base64),
+   ("base64pad", Base::Base64Pad),
+   ("base64url", Base::Base64Url),
+   ("base64urlpad", Base::Base64UrlPad),
+];
+
+/// Returns the base with the name in the multibase table.
+pub fn base(name: &str) -> Result<Base, Value> {
+   let Some(&(_, base)) = BASES.iter().find(|&&(base, _)| base == name) else {
+      return Err(error(format!(
+         "unknown multibase base '{name}', expected one of {bases}",
+         bases = BASES
+            .iter()
+            .map(|&(base, _)| base)
+            .collect::<Vec<_>>()
+            .join(", "),
+      )));
+   };
+
+   Ok(base)
+}
+
+/// Creates a string value out of decoded bytes.
+fn decoded(bytes: &[u8]) -> Result<Value, Value> {
+   let string = str::from_utf8(bytes)
+      .map_err(|utf8| error(format!("decoded bytes are not valid UTF-8: {utf8}")))?;
+
+   Ok(Value::from(value::SString::from(string)))
+}
+
+/// Encodes the string or list of bytes in the base, without a multibase
+/// prefix.
+async fn encode(state: &State, base: Base, bytes: Value) -> Result<Value, Value> {
+   let bytes = super::bytes(state, bytes).await?;
+
+   Ok(Value::from(value::SString::from(&*base.encode(bytes))))
+}
+
+/// Decodes the string from the base, without a multibase prefix.
+async fn decode(state: &State, base: Base, string: Value) -> Result<Vec<u8>, Value> {
+   let string = forced::<value::SString>(state, string).await?;
+
+   base
+      .decode(&**string)
+      .map_err(|decode| error(format!("invalid encoded string: {decode}")))
+}
+
+/// Encodes the string or list of bytes in lowercase base16.
+pub async fn to_base16(state: &State, [bytes]: [Value; 1]) -> Result<Value, Value> {
+   encode(state, Base::Base16Lower, bytes).await
+}
+
+/// Decodes the string from lowercase base16 into a string.
+pub async fn from_base16(state: &State, [string]: [Value; 1]) -> Result<Value, Value> {
+   decoded(&decode(state, Base::Base16Lower, string).await?)
+}
+
+/// Decodes the string from lowercase base16 into a list of bytes.
+pub async fn from_base16_bytes(state: &State, [string]: [Value; 1]) -> Result<Value, Value> {
+   Ok(bytes_from(&decode(state, Base::Base16Lower, string).await?))
+}
+
+/// Encodes the string or list of bytes in padded uppercase base32, as
+/// specified in RFC 4648.
+pub async fn to_base32(state: &State, [bytes]: [Value; 1]) -> Result<Value, Value> {
+   encode(state, Base::Base32PadUpper, bytes).await
+}
+
+/// Decodes the string from padded uppercase base32, as specified in RFC 4648,
+/// into a string.
+pub async fn from_base32(state: &State, [string]: [Value; 1]) -> Result<Value, Value> {
+   decoded(&decode(state, Base::Base32PadUpper, string).await?)
+}
+
+/// Decodes the string from padded uppercase base32, as specified in RFC 4648,
+/// into a list of bytes.
+pub async fn from_base32_bytes(state: &State, [string]: [Value; 1]) -> Result<Value, Value> {
+   Ok(bytes_from(
+      &decode(state, Base::Base32PadUpper, string).await?,
+   ))
+}
+
+/// Encodes the string or list of bytes in padded base64, as specified in
+/// RFC 4648.
+pub async fn to_base64(state: &State, [bytes]: [Value; 1]) -> Result<Value, Value> {
+   encode(state, Base::Base64Pad, bytes).await
+}
+
+/// Decodes the string from padded base64, as specified in RFC 4648, into a
+/// string.
+pub async fn from_base64(state: &State, [string]: [Value; 1]) -> Result<Value, Value> {
+   decoded(&decode(state, Base::Base64Pad, string).await?)
+}
+
+/// Decodes the string from padded base64, as specified in RFC 4648, into a
+/// list of bytes.
+pub async fn from_base64_bytes(state: &State, [string]: [Value; 1]) -> Result<Value, Value> {
+   Ok(bytes_from(&decode(state, Base::Base64Pad, string).await?))
+}
+
+/// Encodes the string or list of bytes in the named base, prefixed with the
+/// multibase code of the base.
+pub async fn to_multibase(state: &State, [base, bytes]: [Value; 2]) -> Result<Value, Value> {
+   let name = forced::<value::SString>(state, base).await?;
+   let base = self::base(&name)?;
+
+   let bytes = super::bytes(state, bytes).await?;
+
+   Ok(Value::from(value::SString::from(&*multibase::encode(
+      base, bytes,
+   ))))
+}
+
+/// Decodes the string from the base its multibase prefix names.
+async fn decode_multibase(state: &State, string: Value) -> Result<Vec<u8>, Value> {
+   let string = forced::<value::SString>(state, string).await?;
+
+   let (_, bytes) = multibase::decode(&**string)
+      .map_err(|decode| error(format!("invalid multibase string: {decode}")))?;
+
+   Ok(bytes)
+}
+
+/// Decodes the string from the base its multibase prefix names into a string.
+pub async fn from_multibase(state: &State, [string]: [Value; 1]) -> Result<Value, Value> {
+   decoded(&decode_multibase(state, string).await?)
+}
+
+/// Decodes the string from the base its multibase prefix names into a list of
+/// bytes.
+pub async fn from_multibase_bytes(state: &State, [string]: [Value; 1]) -> Result<Value, Value> {
+   Ok(bytes_from(&decode_multibase(state, string).await?))
+}
+
+#[cfg(test)]
+mod tests {
+   use crate::{
+      Value,
+      builtin::{
+         evaluate,
+         string,
+      },
+   };
+
+   #[tokio::test]
+   async fn round_trips() {
+      for (encode, decode, encoded) in [
+         ("toBase16", "fromBase16", "68656c6c6f"),
+         ("toBase32", "fromBase32", "NBSWY3DP"),
+         ("toBase64", "fromBase64", "aGVsbG8="),
+      ] {
+         assert_eq!(
+            string(&evaluate(&format!("{encode} \"hello\"")).await),
+            encoded,
+            "{encode}",
+         );
+         assert_eq!(
+            string(&evaluate(&format!("{decode} \"{encoded}\"")).await),
+            "hello",
+            "{decode}",
+         );
+      }
+
+      assert!(matches!(
+         evaluate("fromBase64 \"not base64\"").await,
+         Value::Error(_),
+      ));
+   }
+
+   #[tokio::test]
+   async fn bytes() {
+      for (encode, decode, encoded) in [
+         ("toBase16", "fromBase16Bytes", "00ff"),
+         ("toBase32", "fromBase32Bytes", "AD7Q===="),
+         ("toBase64", "fromBase64Bytes", "AP8="),
+      ] {
+         assert_eq!(
+            string(&evaluate(&format!("{encode} [ 0, 255 ]")).await),
+            encoded,
+            "{encode}",
+         );
+         assert_eq!(
+            string(&evaluate(&format!("toJSON ({decode} \"{encoded}\")")).await),
+            "[0,255]",
+            "{decode}",
+         );
+      }
+
+      assert_eq!(
+         string(&evaluate("toJSON (fromMultibaseBytes (toMultibase \"base58btc\" [ 255 ]))").await),
+         "[255]",
+      );
+
+      assert!(matches!(
+         evaluate("toBase16 [ 256 ]").await,
+         Value::Error(_),
+      ));
+   }
+
+   #[tokio::test]
+   async fn multibase() {
+      assert_eq!(
+         string(&evaluate("toMultibase \"base58btc\" \"hello\"").await),
+         "zCn8eVZg",
+      );
+      assert_eq!(
+         string(&evaluate("toMultibase \"base64\" \"hello\"").await),
+         "maGVsbG8",
+      );
+
+      assert_eq!(
+         string(&evaluate("fromMultibase \"zCn8eVZg\"").await),
+         "hello",
+      );
+      assert_eq!(
+         string(&evaluate("fromMultibase (toMultibase \"base32\" \"hello\")").await),
+         "hello",
+      );
+
+      assert!(matches!(
+         evaluate("toMultibase \"base7\" \"hello\"").await,
+         Value::Error(_),
+      ));
+      assert!(matches!(
+         evaluate("fromMultibase \"!hello\"").await,
+         Value::Error(_),
+      ));
+   }
+}
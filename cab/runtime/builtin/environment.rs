@@ -0,0 +1,188 @@
+use std::{
+   env,
+   time,
+};
+
+use super::{
+   error,
+   forced,
+   serial,
+};
+use crate::{
+   Reproducibility,
+   State,
+   Value,
+   value,
+};
+
+/// Reads the environment in impure evaluations and records what was read in
+/// the lock under the key. Pure evaluations return the recorded value instead,
+/// and so do impure ones that already read it, so every read of the key agrees
+/// with the lock.
+fn impure(
+   state: &State,
+   key: &str,
+   read: impl FnOnce(&[String]) -> serde_json::Value,
+) -> Result<Value, Value> {
+   let json = match state.reproducibility {
+      Reproducibility::Pure(ref lock) => {
+         lock.get(key).ok_or_else(|| {
+            error(format!(
+               "'{key}' is not recorded in the lock, it can only be read in impure evaluations"
+            ))
+         })?
+      },
+
+      Reproducibility::Impure {
+         ref lock,
+         ref arguments,
+      } => {
+         lock.get(key).unwrap_or_else(|| {
+            let json = read(arguments);
+            lock.record(key, json.clone());
+            json
+         })
+      },
+   };
+
+   Ok(serial::from_json_value(json))
+}
+
+/// Returns the value of the environment variable, or null if it isn't set.
+pub async fn get(state: &State, [name]: [Value; 1]) -> Result<Value, Value> {
+   let name = forced::<value::SString>(state, name).await?;
+
+   impure(state, &format!("getEnv {name}", name = &**name), |_| {
+      env::var(&**name).map_or(serde_json::Value::Null, serde_json::Value::String)
+   })
+}
+
+/// Returns attributes of the architecture and the operating system the
+/// evaluation runs on, like `{ @arch = "x86_64"; @os = "linux" }`.
+pub fn system(state: &State) -> Result<Value, Value> {
+   impure(state, "currentSystem", |_| {
+      serde_json::json!({
+         "arch": env::consts::ARCH,
+         "os": env::consts::OS,
+      })
+   })
+}
+
+/// Returns the seconds since the UNIX epoch.
+pub fn time(state: &State) -> Result<Value, Value> {
+   impure(state, "currentTime", |_| {
+      let seconds = time::SystemTime::now()
+         .duration_since(time::UNIX_EPOCH)
+         .map_or(0, |duration| duration.as_secs());
+
+      serde_json::Value::from(seconds)
+   })
+}
+
+/// Returns the arguments passed to the evaluation as a list of strings.
+pub fn arguments(state: &State) -> Result<Value, Value> {
+   impure(state, "args", |arguments| {
+      serde_json::Value::from(arguments.to_vec())
+   })
+}
+
+#[cfg(test)]
+mod tests {
+   use dup::{
+      Dupe as _,
+      OptionDupedExt as _,
+   };
+
+   use super::*;
+   use crate::{
+      Lock,
+      builtin::{
+         builtins,
+         evaluate,
+         serial::to_json_builtin,
+         string,
+      },
+   };
+
+   fn state(reproducibility: Reproducibility) -> State {
+      State {
+         reproducibility,
+         ..State::new()
+      }
+   }
+
+   /// Returns the result of the builtin as JSON.
+   async fn json(state: &State, result: Result<Value, Value>) -> String {
+      let value = result.expect("builtin must succeed");
+
+      string(
+         &to_json_builtin(state, [value])
+            .await
+            .expect("value must be JSON"),
+      )
+      .to_owned()
+   }
+
+   #[tokio::test]
+   async fn pure_fails() {
+      for source in ["getEnv \"HOME\"", "currentSystem", "currentTime", "args"] {
+         assert!(
+            matches!(evaluate(source).await, Value::Error(_)),
+            "{source}",
+         );
+      }
+   }
+
+   #[tokio::test]
+   async fn records_and_replays() {
+      let lock = Lock::new();
+      let impure = state(Reproducibility::Impure {
+         lock:      lock.dupe(),
+         arguments: vec!["a".to_owned(), "b".to_owned()],
+      });
+
+      assert_eq!(
+         json(&impure, system(&impure)).await,
+         format!(
+            r#"{{"arch":"{arch}","os":"{os}"}}"#,
+            arch = env::consts::ARCH,
+            os = env::consts::OS,
+         ),
+      );
+      assert_eq!(json(&impure, arguments(&impure)).await, r#"["a","b"]"#);
+
+      let unset = Value::from(value::string::new!("CAB_TEST_UNSET_VARIABLE"));
+      assert!(matches!(get(&impure, [unset]).await, Ok(Value::Null(_))));
+
+      let recorded = lock.to_json();
+      assert!(recorded.contains("\"args\""));
+      assert!(recorded.contains("\"getEnv CAB_TEST_UNSET_VARIABLE\": null"));
+
+      let pure = state(Reproducibility::Pure(
+         Lock::from_json(&recorded).expect("lock must round trip"),
+      ));
+
+      assert_eq!(json(&pure, arguments(&pure)).await, r#"["a","b"]"#);
+      assert!(time(&pure).is_err());
+   }
+
+   #[tokio::test]
+   async fn every_evaluation_reads_again() {
+      let arguments = builtins()
+         .get(&value::string::new!("args"))
+         .duped()
+         .expect("args must be a builtin");
+
+      for argument in ["a", "b"] {
+         let lock = Lock::new();
+         let impure = state(Reproducibility::Impure {
+            lock:      lock.dupe(),
+            arguments: vec![argument.to_owned()],
+         });
+
+         let value = arguments.dupe().forced(&impure).await;
+         assert_eq!(json(&impure, Ok(value)).await, format!(r#"["{argument}"]"#));
+         assert!(lock.to_json().contains(&format!(r#""{argument}""#)));
+      }
+   }
+}
@@ -0,0 +1,126 @@
+use sha2::{
+   Digest as _,
+   Sha256,
+   Sha512,
+};
+
+use super::{
+   bytes_from,
+   encoding,
+   error,
+   forced,
+   path,
+};
+use crate::{
+   State,
+   Value,
+   value,
+};
+
+/// Hashes the bytes with the algorithm, which is either `"sha256"` or
+/// `"sha512"`. The hash is returned as a list of bytes if the encoding is
+/// `"bytes"`, and as a string in the base the encoding names in the multibase
+/// table otherwise, without a multibase prefix.
+fn hash(algorithm: &str, encoding: &str, bytes: &[u8]) -> Result<Value, Value> {
+   let hash = match algorithm {
+      "sha256" => Sha256::digest(bytes).to_vec(),
+      "sha512" => Sha512::digest(bytes).to_vec(),
+
+      other => {
+         return Err(error(format!(
+            "unknown hash algorithm '{other}', expected 'sha256' or 'sha512'"
+         )));
+      },
+   };
+
+   if encoding == "bytes" {
+      return Ok(bytes_from(&hash));
+   }
+
+   let base = encoding::base(encoding)?;
+
+   Ok(Value::from(value::SString::from(&*base.encode(hash))))
+}
+
+/// Returns the hash of the string.
+pub async fn string(
+   state: &State,
+   [algorithm, encoding, string]: [Value; 3],
+) -> Result<Value, Value> {
+   let algorithm = forced::<value::SString>(state, algorithm).await?;
+   let encoding = forced::<value::SString>(state, encoding).await?;
+   let string = forced::<value::SString>(state, string).await?;
+
+   hash(&algorithm, &encoding, string.as_bytes())
+}
+
+/// Returns the hash of the contents of the file.
+pub async fn file(
+   state: &State,
+   [algorithm, encoding, path]: [Value; 3],
+) -> Result<Value, Value> {
+   let algorithm = forced::<value::SString>(state, algorithm).await?;
+   let encoding = forced::<value::SString>(state, encoding).await?;
+   let path = forced::<value::Path>(state, path).await?;
+
+   let content = path.read().await.map_err(|chain| path::failed(&chain))?;
+
+   hash(&algorithm, &encoding, &content)
+}
+
+#[cfg(test)]
+mod tests {
+   use crate::builtin::{
+      TemporaryDirectory,
+      evaluate,
+      evaluate_in,
+      string,
+   };
+
+   #[tokio::test]
+   async fn hashes() {
+      assert_eq!(
+         string(&evaluate("hashString \"sha256\" \"base16\" \"abc\"").await),
+         "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+      );
+      assert_eq!(
+         string(&evaluate("hashString \"sha512\" \"base16\" \"abc\"").await),
+         "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f",
+      );
+
+      let cache = TemporaryDirectory::new();
+
+      let file = r#"(command {
+         @program = "sh";
+         @arguments = [ "-c", "printf abc > $out" ];
+         @environment = { @PATH = "/bin:/usr/bin" };
+      })"#;
+
+      assert_eq!(
+         string(&evaluate_in(&format!("hashFile \"sha256\" \"base16\" {file}"), &cache).await),
+         "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+      );
+
+      assert_eq!(
+         string(&evaluate("hashString \"sha256\" \"base64\" \"abc\"").await),
+         "ungWv48Bz+pBQUDeXa4iI7ADYaOWF3qctBD/YfIAFa0",
+      );
+      assert_eq!(
+         string(&evaluate("toBase16 (hashString \"sha256\" \"bytes\" \"abc\")").await),
+         "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+      );
+
+      assert!(matches!(
+         evaluate("hashString \"sha256\" \"base7\" \"abc\"").await,
+         crate::Value::Error(_),
+      ));
+      assert!(matches!(
+         evaluate("hashString \"md5\" \"base16\" \"abc\"").await,
+         crate::Value::Error(_),
+      ));
+      assert!(matches!(
+         evaluate("hashFile \"sha256\" \"base16\" ./missing").await,
+         crate::Value::Error(_),
+      ));
+   }
+}
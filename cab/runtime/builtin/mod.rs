@@ -0,0 +1,436 @@
+use std::{
+   fmt,
+   sync::Arc,
+};
+
+use cab_util::suffix::Arc as _;
+use dup::Dupe;
+use num::ToPrimitive as _;
+use ranged::Span;
+
+use crate::{
+   State,
+   Value,
+   value,
+};
+
+mod attributes;
+
+mod command;
+
+mod encoding;
+
+mod environment;
+
+mod hash;
+
+mod path;
+
+mod serial;
+pub use serial::to_json;
+
+mod string;
+
+type Code = Arc<dyn for<'a> Fn(&'a State, Vec<Value>) -> value::NativeFuture<'a> + Send + Sync>;
+
+fn code(
+   code: impl for<'a> Fn(&'a State, Vec<Value>) -> value::NativeFuture<'a> + Send + Sync + 'static,
+) -> Code {
+   code.arc()
+}
+
+/// Returns the attributes that contain every builtin.
+#[must_use]
+pub fn builtins() -> value::Attributes {
+   value::attributes::new! {
+      "true": Value::Boolean(true),
+      "false": Value::Boolean(false),
+      "null": Value::from(value::Null),
+
+      "attrNames": function("attrNames", attributes::names),
+      "attrValues": function("attrValues", attributes::values),
+      "hasAttr": function("hasAttr", attributes::has),
+      "getAttr": function("getAttr", attributes::get),
+      "removeAttrs": function("removeAttrs", attributes::remove),
+      "mapAttrs": function("mapAttrs", attributes::map),
+      "filterAttrs": function("filterAttrs", attributes::filter),
+      "listToAttrs": function("listToAttrs", attributes::from_list),
+
+      "fromJSON": function("fromJSON", serial::from_json),
+      "toJSON": function("toJSON", serial::to_json_builtin),
+      "fromTOML": function("fromTOML", serial::from_toml),
+      "toTOML": function("toTOML", serial::to_toml),
+      "fromYAML": function("fromYAML", serial::from_yaml),
+      "toYAML": function("toYAML", serial::to_yaml),
+
+      "stringLength": function("stringLength", string::length),
+      "substring": function("substring", string::substring),
+      "split": function("split", string::split),
+      "join": function("join", string::join),
+      "replace": function("replace", string::replace),
+      "trim": function("trim", string::trim),
+      "trimStart": function("trimStart", string::trim_start),
+      "trimEnd": function("trimEnd", string::trim_end),
+      "toUpper": function("toUpper", string::to_upper),
+      "toLower": function("toLower", string::to_lower),
+      "startsWith": function("startsWith", string::starts_with),
+      "endsWith": function("endsWith", string::ends_with),
+      "stringToChars": function("stringToChars", string::to_chars),
+      "charsToString": function("charsToString", string::from_chars),
+      "parseInteger": function("parseInteger", string::parse_integer),
+      "formatInteger": function("formatInteger", string::format_integer),
+
+      "readFile": function("readFile", path::read),
+      "readFileBytes": function("readFileBytes", path::read_bytes),
+      "readDir": function("readDir", path::read_directory),
+      "pathExists": function("pathExists", path::exists),
+      "glob": function("glob", path::glob),
+
+      "hashString": function("hashString", hash::string),
+      "hashFile": function("hashFile", hash::file),
+
+      "toBase16": function("toBase16", encoding::to_base16),
+      "fromBase16": function("fromBase16", encoding::from_base16),
+      "fromBase16Bytes": function("fromBase16Bytes", encoding::from_base16_bytes),
+      "toBase32": function("toBase32", encoding::to_base32),
+      "fromBase32": function("fromBase32", encoding::from_base32),
+      "fromBase32Bytes": function("fromBase32Bytes", encoding::from_base32_bytes),
+      "toBase64": function("toBase64", encoding::to_base64),
+      "fromBase64": function("fromBase64", encoding::from_base64),
+      "fromBase64Bytes": function("fromBase64Bytes", encoding::from_base64_bytes),
+      "toMultibase": function("toMultibase", encoding::to_multibase),
+      "fromMultibase": function("fromMultibase", encoding::from_multibase),
+      "fromMultibaseBytes": function("fromMultibaseBytes", encoding::from_multibase_bytes),
+
+      "command": function("command", command::run),
+
+      "getEnv": function("getEnv", environment::get),
+      "currentSystem": volatile("currentSystem", environment::system),
+      "currentTime": volatile("currentTime", environment::time),
+      "args": volatile("args", environment::arguments),
+   }
+}
+
+/// Creates a curried native function that takes `N` arguments.
+///
+/// Errors are values too, but the implementation returns them through [`Err`]
+/// so it can use `?`.
+fn function<const N: usize>(
+   name: &'static str,
+   code: impl AsyncFn(&State, [Value; N]) -> Result<Value, Value> + Send + Sync + 'static,
+) -> Value {
+   let code = code.arc();
+
+   curry(
+      location(name),
+      N,
+      Vec::new(),
+      self::code(move |state, arguments| {
+         let code = code.dupe();
+
+         Box::pin(async move {
+            let arguments = <[Value; N]>::try_from(arguments)
+               .unwrap_or_else(|_| unreachable!("curry must collect exactly {N} arguments"));
+
+            code(state, arguments).await.unwrap_or_else(|error| error)
+         })
+      }),
+   )
+}
+
+/// Creates a native thunk that evaluates to the result of the code every time
+/// it is forced. The result isn't kept, as it depends on the state of the
+/// evaluation that forces it.
+fn volatile(
+   name: &'static str,
+   code: impl Fn(&State) -> Result<Value, Value> + Send + Sync + 'static,
+) -> Value {
+   Value::from(
+      value::Thunk::volatile_native(move |state| {
+         let value = code(state).unwrap_or_else(|error| error);
+
+         Box::pin(async move { value })
+      })
+      .location(location(name)),
+   )
+}
+
+fn curry(location: value::Location, arity: usize, arguments: Vec<Value>, code: Code) -> Value {
+   let thunk_location = location.dupe();
+
+   Value::from(
+      value::Thunk::needs_argument_native(move |state, argument| {
+         let mut arguments = arguments.clone();
+         arguments.push(argument);
+
+         if arguments.len() == arity {
+            return code(state, arguments);
+         }
+
+         let location = location.dupe();
+         let code = code.dupe();
+         Box::pin(async move { curry(location, arity, arguments, code) })
+      })
+      .location(thunk_location),
+   )
+}
+
+fn location(name: &'static str) -> value::Location {
+   value::Location::new(
+      value::Path::rootless(
+         [value::string::new!("builtin"), value::SString::from(name)]
+            .into_iter()
+            .collect(),
+      ),
+      Span::empty(0_u32),
+   )
+}
+
+/// Creates an error value with the given message.
+fn error(message: impl fmt::Display) -> Value {
+   Value::from(value::Error::new(value::SString::from(&*message.to_string())).arc())
+}
+
+/// Forces the value and returns it as `T`. Errors are propagated as-is.
+async fn forced<T: Dupe + value::Kind>(state: &State, value: Value) -> Result<T, Value>
+where
+   Value: TryInto<T>,
+{
+   let value = value.forced(state).await;
+
+   if let Value::Error(_) = value {
+      return Err(value);
+   }
+
+   value.typed::<T>().must()
+}
+
+/// Forces the spine of the list and returns its items, which aren't forced.
+async fn list(state: &State, value: Value) -> Result<Vec<Value>, Value> {
+   let mut items = Vec::new();
+
+   let mut value = value.forced(state).await;
+   loop {
+      match value {
+         Value::Cons(cons) => {
+            items.push(cons.0.dupe());
+            value = cons.1.dupe().forced(state).await;
+         },
+
+         Value::Nil(_) => return Ok(items),
+
+         value @ Value::Error(_) => return Err(value),
+
+         other => {
+            return Err(error(format!(
+               "expected list, got {kind}",
+               kind = other.kind(),
+            )));
+         },
+      }
+   }
+}
+
+/// Forces the value and returns its bytes. The value is either a string or a
+/// list of integers from 0 to 255, like `readFileBytes` returns.
+async fn bytes(state: &State, value: Value) -> Result<Vec<u8>, Value> {
+   let value = value.forced(state).await;
+
+   if let Value::String(ref string) = value {
+      return Ok(string.as_bytes().to_vec());
+   }
+
+   let mut bytes = Vec::new();
+
+   for item in list(state, value).await? {
+      let integer = forced::<value::Integer>(state, item).await?;
+
+      bytes.push(integer.to_u8().ok_or_else(|| {
+         error(format!(
+            "bytes must be integers from 0 to 255, got {integer}",
+            integer = **integer,
+         ))
+      })?);
+   }
+
+   Ok(bytes)
+}
+
+/// Creates a list of the bytes as integers, like `readFileBytes` returns.
+fn bytes_from(bytes: &[u8]) -> Value {
+   list_from(
+      bytes
+         .iter()
+         .map(|&byte| Value::from(value::Integer::from(num::BigInt::from(byte)))),
+   )
+}
+
+/// Applies the function to the arguments one by one. The result is not forced.
+async fn apply(
+   state: &State,
+   function: Value,
+   arguments: impl IntoIterator<Item = Value>,
+) -> Result<Value, Value> {
+   let mut function = function;
+
+   for argument in arguments {
+      function = match function.forced(state).await {
+         Value::Thunk(thunk) if let Some(thunk) = thunk.argument(argument).await => {
+            Value::from(thunk)
+         },
+
+         value @ Value::Error(_) => return Err(value),
+
+         other => {
+            return Err(error(format!(
+               "expected lambda, got {kind}",
+               kind = other.kind(),
+            )));
+         },
+      };
+   }
+
+   Ok(function)
+}
+
+/// Creates a list from the given items.
+fn list_from(items: impl DoubleEndedIterator<Item = Value>) -> Value {
+   items.rev().fold(Value::from(value::Nil), |tail, head| {
+      Value::from(value::Cons::new(head, tail).arc())
+   })
+}
+
+#[cfg(test)]
+async fn evaluate(source: &str) -> Value {
+   evaluate_with(source, true).await
+}
+
+/// A new directory in the temporary directory, which is removed with its
+/// contents when dropped.
+#[cfg(test)]
+pub(crate) struct TemporaryDirectory(pub(crate) std::path::PathBuf);
+
+#[cfg(test)]
+impl TemporaryDirectory {
+   pub(crate) fn new() -> Self {
+      use std::{
+         env,
+         fs,
+         process,
+         sync::atomic,
+      };
+
+      static COUNT: atomic::AtomicUsize = atomic::AtomicUsize::new(0);
+
+      let path = env::temp_dir().join(format!(
+         "cab-test-{process}-{count}",
+         process = process::id(),
+         count = COUNT.fetch_add(1, atomic::Ordering::Relaxed),
+      ));
+
+      fs::create_dir_all(&path).expect("temporary directory must be creatable");
+
+      Self(path)
+   }
+}
+
+#[cfg(test)]
+impl Drop for TemporaryDirectory {
+   fn drop(&mut self) {
+      let _ = std::fs::remove_dir_all(&self.0);
+   }
+}
+
+/// Evaluates the source with the builtins in scope and forces it deeply.
+/// Commands cache their outputs in the directory.
+#[cfg(test)]
+async fn evaluate_in(source: &str, cache: &TemporaryDirectory) -> Value {
+   let (code, span) = compile(source, &crate::CompileOracle::new(), true);
+
+   let state = State {
+      cache: Some(cache.0.clone()),
+      ..State::new()
+   };
+
+   evaluate_code_in(code, span, &state).await
+}
+
+/// Evaluates the source with the builtins in scope and forces it deeply.
+#[cfg(test)]
+pub(crate) async fn evaluate_with(source: &str, optimize: bool) -> Value {
+   evaluate_compiled(source, &crate::CompileOracle::new(), optimize).await
+}
+
+/// Evaluates the source compiled by the oracle with the builtins in scope and
+/// forces it deeply.
+#[cfg(test)]
+pub(crate) async fn evaluate_compiled(
+   source: &str,
+   compile_oracle: &crate::CompileOracle,
+   optimize: bool,
+) -> Value {
+   let (code, span) = compile(source, compile_oracle, optimize);
+
+   evaluate_code(code, span).await
+}
+
+/// Compiles the source for the evaluation helpers.
+#[cfg(test)]
+pub(crate) fn compile(
+   source: &str,
+   compile_oracle: &crate::CompileOracle,
+   optimize: bool,
+) -> (crate::Code, Span) {
+   use cab_syntax as syntax;
+   use rpds::ListSync as List;
+
+   let parse = syntax::ParseOracle::new().parse(syntax::tokenize(source));
+   assert!(parse.reports.is_empty(), "source must parse cleanly");
+
+   let lower = syntax::LowerOracle::new().lower(parse.expression.as_ref());
+   assert!(lower.reports.is_empty(), "source must lower cleanly");
+
+   let mut code = compile_oracle
+      .compile(lower.expression())
+      .path(value::Path::rootless(List::new_sync()))
+      .code;
+
+   if optimize {
+      code = code.optimized();
+   }
+
+   (code, Span::at(0_u32, source.len()))
+}
+
+/// Evaluates the code with the builtins in scope and forces it deeply.
+/// Commands can't be run, as there is no cache.
+#[cfg(test)]
+pub(crate) async fn evaluate_code(code: crate::Code, span: Span) -> Value {
+   evaluate_code_in(code, span, &State::new()).await
+}
+
+/// Evaluates the code with the builtins in scope and forces it deeply in the
+/// state.
+#[cfg(test)]
+pub(crate) async fn evaluate_code_in(code: crate::Code, span: Span, state: &State) -> Value {
+   let location = value::Location::new(code.path().dupe(), span);
+
+   let thunk = value::Thunk::forceable(code.arc())
+      .scopes(crate::Scopes::new().push(crate::Scope::from(&builtins())))
+      .location(location);
+
+   thunk.force(state).await;
+
+   let (_, value) = thunk.get().await;
+   value.forced_deep(state).await
+}
+
+#[cfg(test)]
+#[track_caller]
+fn string(value: &Value) -> &str {
+   let &Value::String(ref string) = value else {
+      panic!("expected string, got {kind}", kind = value.kind());
+   };
+
+   string
+}
@@ -0,0 +1,346 @@
+use std::pin::Pin;
+
+use dup::Dupe as _;
+use ust::{
+   Display as _,
+   terminal,
+};
+
+use super::{
+   bytes_from,
+   error,
+   forced,
+   list_from,
+};
+use crate::{
+   State,
+   Value,
+   value,
+};
+
+/// Creates an error value from the chain of a failed path operation. The chain
+/// renders the path with its display tags.
+pub fn failed(chain: &cyn::Chain) -> Value {
+   let mut message = String::new();
+
+   {
+      let writer = &mut terminal::writer(terminal::StyleChoice::Never, &mut message);
+      let _ = chain.display_styled(writer);
+   }
+
+   error(message.trim_end())
+}
+
+/// Returns the children of the path, by their names.
+async fn children(path: &value::Path) -> Result<Vec<(value::SString, value::Path)>, Value> {
+   let subpaths = path.list().await.map_err(|chain| failed(&chain))?;
+
+   Ok(subpaths
+      .iter()
+      .filter_map(|subpath| subpath.last())
+      .map(|name| (name.dupe(), path.get(name.dupe())))
+      .collect())
+}
+
+/// Whether the path can be listed. Paths that can't be listed are files.
+async fn is_directory(path: &value::Path) -> bool {
+   path.list().await.is_ok()
+}
+
+/// Returns the contents of the file as a string.
+pub async fn read(state: &State, [path]: [Value; 1]) -> Result<Value, Value> {
+   let path = forced::<value::Path>(state, path).await?;
+
+   let content = path.read().await.map_err(|chain| failed(&chain))?;
+
+   let content = str::from_utf8(&content)
+      .map_err(|utf8| error(format!("contents of the file are not valid UTF-8: {utf8}")))?;
+
+   Ok(Value::from(value::SString::from(content)))
+}
+
+/// Returns the contents of the file as a list of bytes.
+pub async fn read_bytes(state: &State, [path]: [Value; 1]) -> Result<Value, Value> {
+   let path = forced::<value::Path>(state, path).await?;
+
+   let content = path.read().await.map_err(|chain| failed(&chain))?;
+
+   Ok(bytes_from(&content))
+}
+
+/// Returns attributes of the names of the entries in the directory to their
+/// kinds, which are either `"directory"` or `"file"`.
+pub async fn read_directory(state: &State, [path]: [Value; 1]) -> Result<Value, Value> {
+   let path = forced::<value::Path>(state, path).await?;
+
+   let mut entries = value::attributes::new! {};
+
+   for (name, child) in children(&path).await? {
+      let kind = if is_directory(&child).await {
+         value::string::new!("directory")
+      } else {
+         value::string::new!("file")
+      };
+
+      entries = entries.insert(name, Value::from(kind));
+   }
+
+   Ok(Value::from(entries))
+}
+
+/// Whether the path exists. Files aren't read to find out.
+pub async fn exists(state: &State, [path]: [Value; 1]) -> Result<Value, Value> {
+   let path = forced::<value::Path>(state, path).await?;
+
+   let exists = path.exists().await.map_err(|chain| failed(&chain))?;
+
+   Ok(Value::Boolean(exists))
+}
+
+/// Whether the name matches the part of a pattern, where `*` matches any
+/// characters and `?` matches a single one.
+///
+/// Only the last `*` is backtracked to, as a later `*` can match anything an
+/// earlier one would have. So this takes `O(pattern * name)` time at worst.
+fn matches(pattern: &[char], name: &[char]) -> bool {
+   let (mut pattern_index, mut name_index) = (0, 0);
+
+   // The index after the last `*` and the index of the name it resumes at.
+   let mut backtrack = None;
+
+   while name_index < name.len() {
+      match pattern.get(pattern_index) {
+         Some(&'*') => {
+            pattern_index += 1;
+            backtrack = Some((pattern_index, name_index));
+         },
+
+         Some(&expected) if expected == '?' || expected == name[name_index] => {
+            pattern_index += 1;
+            name_index += 1;
+         },
+
+         _ => {
+            let Some((star, resume)) = backtrack else {
+               return false;
+            };
+
+            pattern_index = star;
+            name_index = resume + 1;
+            backtrack = Some((star, resume + 1));
+         },
+      }
+   }
+
+   pattern[pattern_index..]
+      .iter()
+      .all(|&character| character == '*')
+}
+
+/// Collects the paths under the path that match the parts of the pattern,
+/// where `**` matches any amount of directories.
+fn walk<'a>(
+   path: &'a value::Path,
+   parts: &'a [Vec<char>],
+   matched: &'a mut Vec<value::Path>,
+) -> Pin<Box<dyn Future<Output = Result<(), Value>> + 'a>> {
+   Box::pin(async move {
+      let Some((part, rest)) = parts.split_first() else {
+         matched.push(path.dupe());
+         return Ok(());
+      };
+
+      if **part == ['*', '*'] {
+         walk(path, rest, matched).await?;
+
+         for (_, child) in children(path).await? {
+            if is_directory(&child).await {
+               walk(&child, parts, matched).await?;
+            }
+         }
+
+         return Ok(());
+      }
+
+      for (name, child) in children(path).await? {
+         if !matches(part, &name.chars().collect::<Vec<_>>()) {
+            continue;
+         }
+
+         if rest.is_empty() || is_directory(&child).await {
+            walk(&child, rest, matched).await?;
+         }
+      }
+
+      Ok(())
+   })
+}
+
+/// Returns the paths under the directory that match the pattern. The parts of
+/// the pattern are separated by `/`, `*` matches any characters in a name, `?`
+/// matches a single character and `**` matches any amount of directories.
+pub async fn glob(state: &State, [pattern, path]: [Value; 2]) -> Result<Value, Value> {
+   let pattern = forced::<value::SString>(state, pattern).await?;
+   let path = forced::<value::Path>(state, path).await?;
+
+   let parts = pattern
+      .split(value::path::SEPARATOR)
+      .filter(|part| !part.is_empty())
+      .map(|part| part.chars().collect::<Vec<_>>())
+      .collect::<Vec<_>>();
+
+   let mut matched = Vec::new();
+   walk(&path, &parts, &mut matched).await?;
+
+   Ok(list_from(matched.into_iter().map(Value::from)))
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::builtin::{
+      TemporaryDirectory,
+      evaluate,
+      evaluate_in,
+   };
+
+   /// Returns a command that creates the directory tree with `sh`.
+   fn tree(script: &str) -> String {
+      format!(
+         r#"(command {{
+            @program = "sh";
+            @arguments = [ "-c", "mkdir $out && cd $out && {script}" ];
+            @environment = {{ @PATH = "/bin:/usr/bin" }};
+         }})"#
+      )
+   }
+
+   async fn strings(value: Value) -> Vec<String> {
+      let mut strings = Vec::new();
+
+      let mut value = value;
+      while let Value::Cons(cons) = value {
+         let Value::Path(ref path) = cons.0 else {
+            panic!("glob must return paths");
+         };
+
+         let content = path.read().await.expect("matched path must be readable");
+         strings.push(String::from_utf8(content.to_vec()).expect("content must be UTF-8"));
+
+         value = cons.1.dupe();
+      }
+
+      strings
+   }
+
+   #[tokio::test]
+   async fn reads() {
+      let cache = TemporaryDirectory::new();
+
+      let file = r#"(command {
+         @program = "sh";
+         @arguments = [ "-c", "printf hi > $out" ];
+         @environment = { @PATH = "/bin:/usr/bin" };
+      })"#;
+
+      assert!(matches!(
+         evaluate_in(&format!("readFile {file}"), &cache).await,
+         Value::String(ref string) if &***string == "hi",
+      ));
+
+      assert!(matches!(
+         evaluate_in(&format!("toJSON (readFileBytes {file})"), &cache).await,
+         Value::String(ref string) if &***string == "[104,105]",
+      ));
+
+      assert!(matches!(
+         evaluate_in(&format!("pathExists {file}"), &cache).await,
+         Value::Boolean(true),
+      ));
+      assert!(matches!(
+         evaluate("pathExists ./missing").await,
+         Value::Boolean(false),
+      ));
+
+      assert!(matches!(
+         evaluate("readFile ./missing").await,
+         Value::Error(_),
+      ));
+   }
+
+   #[tokio::test]
+   async fn reads_directory() {
+      let cache = TemporaryDirectory::new();
+
+      let directory = tree("mkdir nested && echo > file");
+
+      assert!(matches!(
+         evaluate_in(&format!("toJSON (readDir {directory})"), &cache).await,
+         Value::String(ref string) if &***string == r#"{"file":"file","nested":"directory"}"#,
+      ));
+
+      assert!(matches!(
+         evaluate("readDir ./missing").await,
+         Value::Error(_),
+      ));
+   }
+
+   #[tokio::test]
+   async fn globs() {
+      let cache = TemporaryDirectory::new();
+
+      let directory = tree(
+         "mkdir -p a/b && echo 1 > x.cab && echo 2 > a/y.cab && echo 3 > a/b/z.cab && echo 4 > \
+          a/z.txt",
+      );
+
+      for (pattern, expected) in [
+         ("*.cab", &["1\n"][..]),
+         ("a/?.cab", &["2\n"]),
+         ("**/*.cab", &["1\n", "2\n", "3\n"]),
+         ("a/*.*", &["2\n", "4\n"]),
+         ("*.nix", &[]),
+      ] {
+         let value = evaluate_in(&format!("glob \"{pattern}\" {directory}"), &cache).await;
+
+         let mut strings = strings(value).await;
+         strings.sort();
+
+         let mut expected = expected.to_vec();
+         expected.sort_unstable();
+
+         assert_eq!(strings, expected, "{pattern}");
+      }
+   }
+
+   #[test]
+   fn matches_names() {
+      for (pattern, name, expected) in [
+         ("*", "anything", true),
+         ("*.cab", "a.cab", true),
+         ("*.cab", "a.nix", false),
+         ("a?c", "abc", true),
+         ("a?c", "ac", false),
+         ("a*b*c", "aXbYc", true),
+         ("", "a", false),
+         ("", "", true),
+         ("**", "", true),
+         ("a*", "b", false),
+         ("*a*b", "xaybzb", true),
+         ("*a*b", "xaybzc", false),
+      ] {
+         let pattern = pattern.chars().collect::<Vec<_>>();
+         let name = name.chars().collect::<Vec<_>>();
+
+         assert_eq!(matches(&pattern, &name), expected, "{pattern:?} {name:?}");
+      }
+   }
+
+   #[test]
+   fn matches_without_backtracking_exponentially() {
+      let pattern = "*a".repeat(32).chars().collect::<Vec<_>>();
+      let name = "a".repeat(64).chars().chain(['b']).collect::<Vec<_>>();
+
+      assert!(!matches(&pattern, &name));
+   }
+}
@@ -0,0 +1,344 @@
+use dup::Dupe as _;
+use num::ToPrimitive as _;
+
+use super::{
+   error,
+   forced,
+   list_from,
+};
+use crate::{
+   State,
+   Value,
+   value,
+};
+
+/// Collects the items of an already forced list, failing on improper lists.
+fn items(value: &Value) -> Result<Vec<&Value>, Value> {
+   let mut items = Vec::new();
+
+   let mut value = value;
+   loop {
+      match *value {
+         Value::Cons(ref cons) => {
+            items.push(&cons.0);
+            value = &cons.1;
+         },
+
+         Value::Nil(_) => return Ok(items),
+
+         Value::Error(_) => return Err(value.dupe()),
+
+         ref other => {
+            return Err(error(format!(
+               "expected list to end with nil, got {kind}",
+               kind = other.kind(),
+            )));
+         },
+      }
+   }
+}
+
+fn unrepresentable(value: &Value, format: &str) -> Value {
+   if let Value::Error(_) = *value {
+      return value.dupe();
+   }
+
+   error(format!(
+      "cannot represent {kind} in {format}",
+      kind = value.kind(),
+   ))
+}
+
+// JSON
+
+/// Converts a deeply forced value into JSON.
+fn json(value: &Value) -> Result<serde_json::Value, Value> {
+   Ok(match *value {
+      Value::Null(_) => serde_json::Value::Null,
+
+      Value::Boolean(boolean) => serde_json::Value::Bool(boolean),
+
+      Value::Cons(_) | Value::Nil(_) => {
+         serde_json::Value::Array(
+            items(value)?
+               .into_iter()
+               .map(json)
+               .collect::<Result<_, _>>()?,
+         )
+      },
+
+      Value::Attributes(ref attributes) => {
+         serde_json::Value::Object(
+            attributes
+               .iter()
+               .map(|(name, value)| Ok(((***name).to_owned(), json(value)?)))
+               .collect::<Result<_, _>>()?,
+         )
+      },
+
+      Value::String(ref string) => serde_json::Value::String((***string).to_owned()),
+      Value::Char(char) => serde_json::Value::String(char.to_string()),
+
+      Value::Integer(ref integer) => {
+         if let Some(integer) = integer.to_i64() {
+            serde_json::Value::from(integer)
+         } else if let Some(integer) = integer.to_u64() {
+            serde_json::Value::from(integer)
+         } else {
+            return Err(error(format!(
+               "integer {integer} is out of the range of JSON numbers",
+               integer = **integer,
+            )));
+         }
+      },
+
+      Value::Float(float) => {
+         serde_json::Number::from_f64(float)
+            .map(serde_json::Value::Number)
+            .ok_or_else(|| error(format!("cannot represent float {float} in JSON")))?
+      },
+
+      ref other => return Err(unrepresentable(other, "JSON")),
+   })
+}
+
+/// Converts JSON into a value.
+pub fn from_json_value(json: serde_json::Value) -> Value {
+   match json {
+      serde_json::Value::Null => Value::from(value::Null),
+
+      serde_json::Value::Bool(boolean) => Value::Boolean(boolean),
+
+      serde_json::Value::Number(number) => {
+         if let Some(integer) = number.as_i64() {
+            Value::from(value::Integer::from(num::BigInt::from(integer)))
+         } else if let Some(integer) = number.as_u64() {
+            Value::from(value::Integer::from(num::BigInt::from(integer)))
+         } else {
+            Value::Float(
+               number
+                  .as_f64()
+                  .expect("number must be representable as float if not an integer"),
+            )
+         }
+      },
+
+      serde_json::Value::String(string) => Value::from(value::SString::from(&*string)),
+
+      serde_json::Value::Array(items) => list_from(items.into_iter().map(from_json_value)),
+
+      serde_json::Value::Object(object) => {
+         Value::from(
+            object
+               .into_iter()
+               .fold(value::attributes::new! {}, |attributes, (name, value)| {
+                  attributes.insert(value::SString::from(&*name), from_json_value(value))
+               }),
+         )
+      },
+   }
+}
+
+/// Converts a deeply forced value into pretty printed JSON.
+pub fn to_json(value: &Value) -> Result<String, Value> {
+   Ok(serde_json::to_string_pretty(&json(value)?).expect("JSON values must serialize"))
+}
+
+pub async fn to_json_builtin(state: &State, [value]: [Value; 1]) -> Result<Value, Value> {
+   let value = value.forced_deep(state).await;
+
+   let json = serde_json::to_string(&json(&value)?).expect("JSON values must serialize");
+
+   Ok(Value::from(value::SString::from(&*json)))
+}
+
+pub async fn from_json(state: &State, [string]: [Value; 1]) -> Result<Value, Value> {
+   let string = forced::<value::SString>(state, string).await?;
+
+   let json = serde_json::from_str::<serde_json::Value>(&string)
+      .map_err(|json_error| error(format!("failed to parse JSON: {json_error}")))?;
+
+   Ok(from_json_value(json))
+}
+
+// YAML
+
+// YAML is converted through JSON values, as the values both can represent are
+// the same apart from non-string keys, which are rejected.
+
+pub async fn to_yaml(state: &State, [value]: [Value; 1]) -> Result<Value, Value> {
+   let value = value.forced_deep(state).await;
+
+   let yaml = serde_yaml_ng::to_string(&json(&value)?)
+      .map_err(|yaml_error| error(format!("failed to convert to YAML: {yaml_error}")))?;
+
+   Ok(Value::from(value::SString::from(&*yaml)))
+}
+
+pub async fn from_yaml(state: &State, [string]: [Value; 1]) -> Result<Value, Value> {
+   let string = forced::<value::SString>(state, string).await?;
+
+   let json = serde_yaml_ng::from_str::<serde_json::Value>(&string)
+      .map_err(|yaml_error| error(format!("failed to parse YAML: {yaml_error}")))?;
+
+   Ok(from_json_value(json))
+}
+
+// TOML
+
+/// Converts a deeply forced value into TOML.
+fn toml(value: &Value) -> Result<toml::Value, Value> {
+   Ok(match *value {
+      Value::Boolean(boolean) => toml::Value::Boolean(boolean),
+
+      Value::Cons(_) | Value::Nil(_) => {
+         toml::Value::Array(
+            items(value)?
+               .into_iter()
+               .map(toml)
+               .collect::<Result<_, _>>()?,
+         )
+      },
+
+      Value::Attributes(ref attributes) => {
+         toml::Value::Table(
+            attributes
+               .iter()
+               .map(|(name, value)| Ok(((***name).to_owned(), toml(value)?)))
+               .collect::<Result<_, _>>()?,
+         )
+      },
+
+      Value::String(ref string) => toml::Value::String((***string).to_owned()),
+      Value::Char(char) => toml::Value::String(char.to_string()),
+
+      Value::Integer(ref integer) => {
+         toml::Value::Integer(integer.to_i64().ok_or_else(|| {
+            error(format!(
+               "integer {integer} is out of the range of TOML integers",
+               integer = **integer,
+            ))
+         })?)
+      },
+
+      Value::Float(float) => toml::Value::Float(float),
+
+      ref other => return Err(unrepresentable(other, "TOML")),
+   })
+}
+
+/// Converts TOML into a value. Datetimes are converted into strings.
+fn from_toml_value(toml: toml::Value) -> Value {
+   match toml {
+      toml::Value::Boolean(boolean) => Value::Boolean(boolean),
+
+      toml::Value::Integer(integer) => {
+         Value::from(value::Integer::from(num::BigInt::from(integer)))
+      },
+      toml::Value::Float(float) => Value::Float(float),
+
+      toml::Value::String(string) => Value::from(value::SString::from(&*string)),
+      toml::Value::Datetime(datetime) => Value::from(value::SString::from(&*datetime.to_string())),
+
+      toml::Value::Array(items) => list_from(items.into_iter().map(from_toml_value)),
+
+      toml::Value::Table(table) => {
+         Value::from(
+            table
+               .into_iter()
+               .fold(value::attributes::new! {}, |attributes, (name, value)| {
+                  attributes.insert(value::SString::from(&*name), from_toml_value(value))
+               }),
+         )
+      },
+   }
+}
+
+pub async fn to_toml(state: &State, [value]: [Value; 1]) -> Result<Value, Value> {
+   let value = value.forced_deep(state).await;
+
+   let toml::Value::Table(table) = toml(&value)? else {
+      return Err(error(format!(
+         "expected attributes to convert to TOML, got {kind}",
+         kind = value.kind(),
+      )));
+   };
+
+   let toml = toml::to_string(&table)
+      .map_err(|toml_error| error(format!("failed to convert to TOML: {toml_error}")))?;
+
+   Ok(Value::from(value::SString::from(&*toml)))
+}
+
+pub async fn from_toml(state: &State, [string]: [Value; 1]) -> Result<Value, Value> {
+   let string = forced::<value::SString>(state, string).await?;
+
+   let table = toml::from_str::<toml::Table>(&string)
+      .map_err(|toml_error| error(format!("failed to parse TOML: {toml_error}")))?;
+
+   Ok(from_toml_value(toml::Value::Table(table)))
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::builtin::{
+      evaluate,
+      string,
+   };
+
+   #[tokio::test]
+   async fn to_json_sorts_attributes() {
+      assert_eq!(
+         string(&evaluate(r#"toJSON { @b = [ true, "x", 'y' ]; @a = 1.5 }"#).await),
+         r#"{"a":1.5,"b":[true,"x","y"]}"#,
+      );
+   }
+
+   #[tokio::test]
+   async fn json_round_trip() {
+      assert_eq!(
+         string(&evaluate(r#"toJSON (fromJSON "{ \"a\": [ 1, -2, null ], \"b\": {} }")"#).await),
+         r#"{"a":[1,-2,null],"b":{}}"#,
+      );
+
+      assert_eq!(string(&evaluate("toJSON [ null, [] ]").await), "[null,[]]");
+   }
+
+   #[tokio::test]
+   async fn yaml_round_trip() {
+      assert_eq!(
+         string(&evaluate(r#"toYAML (fromYAML "b:\n- 1\n- null\na: x\n")"#).await),
+         "a: x\nb:\n- 1\n- null\n",
+      );
+
+      assert!(matches!(
+         evaluate(r#"fromYAML "a: [""#).await,
+         Value::Error(_),
+      ));
+      assert!(matches!(evaluate("toYAML ./path").await, Value::Error(_)));
+   }
+
+   #[tokio::test]
+   async fn json_unrepresentable() {
+      assert!(matches!(evaluate("toJSON (@x => x)").await, Value::Error(_)));
+      assert!(matches!(evaluate(r#"fromJSON "{""#).await, Value::Error(_)));
+   }
+
+   #[tokio::test]
+   async fn toml_round_trip() {
+      assert_eq!(
+         string(&evaluate(r#"toTOML (fromTOML "a = 1\n[b]\nc = [ true ]\n")"#).await),
+         "a = 1\n\n[b]\nc = [true]\n",
+      );
+   }
+
+   #[tokio::test]
+   async fn toml_integer_range() {
+      assert!(matches!(
+         evaluate("toTOML { @a = 100000000000000000000 }").await,
+         Value::Error(_),
+      ));
+      assert!(matches!(evaluate("toTOML [ 1 ]").await, Value::Error(_)));
+   }
+}
@@ -0,0 +1,317 @@
+use std::str::FromStr as _;
+
+use num::ToPrimitive as _;
+use unicode_segmentation::UnicodeSegmentation as _;
+
+use super::{
+   error,
+   forced,
+   list,
+   list_from,
+};
+use crate::{
+   State,
+   Value,
+   value,
+};
+
+/// Creates a string value that shares storage with `string` if possible.
+fn slice(string: &value::SString, slice: &str) -> Value {
+   Value::from(value::SString(string.0.substr_from(slice)))
+}
+
+fn integer(integer: usize) -> Value {
+   Value::from(value::Integer::from(num::BigInt::from(integer)))
+}
+
+/// Converts the integer into a grapheme cluster index or count.
+fn index(integer: &value::Integer, what: &str) -> Result<usize, Value> {
+   integer.to_usize().ok_or_else(|| {
+      error(format!(
+         "{what} must be a non-negative integer that fits in memory, got {integer}",
+         integer = **integer,
+      ))
+   })
+}
+
+/// Returns the byte offset of the grapheme cluster at the given index, or the
+/// length of the string if there are fewer grapheme clusters.
+fn grapheme_offset(string: &str, index: usize) -> usize {
+   string
+      .grapheme_indices(true)
+      .nth(index)
+      .map_or(string.len(), |(offset, _)| offset)
+}
+
+fn not_empty(string: value::SString, what: &str) -> Result<value::SString, Value> {
+   if string.is_empty() {
+      return Err(error(format!("{what} must not be empty")));
+   }
+
+   Ok(string)
+}
+
+/// Returns the amount of extended grapheme clusters in the string.
+pub async fn length(state: &State, [string]: [Value; 1]) -> Result<Value, Value> {
+   let string = forced::<value::SString>(state, string).await?;
+
+   Ok(integer(string.graphemes(true).count()))
+}
+
+/// Returns at most `length` grapheme clusters of the string, starting from
+/// the grapheme cluster at `start`.
+pub async fn substring(state: &State, [start, length, string]: [Value; 3]) -> Result<Value, Value> {
+   let start = index(&forced::<value::Integer>(state, start).await?, "start")?;
+   let length = index(&forced::<value::Integer>(state, length).await?, "length")?;
+   let string = forced::<value::SString>(state, string).await?;
+
+   let start_offset = grapheme_offset(&string, start);
+   let end_offset = start_offset + grapheme_offset(&string[start_offset..], length);
+
+   Ok(slice(&string, &string[start_offset..end_offset]))
+}
+
+pub async fn split(state: &State, [separator, string]: [Value; 2]) -> Result<Value, Value> {
+   let separator = not_empty(
+      forced::<value::SString>(state, separator).await?,
+      "separator",
+   )?;
+   let string = forced::<value::SString>(state, string).await?;
+
+   Ok(list_from(
+      string
+         .split(&**separator)
+         .map(|part| slice(&string, part))
+         .collect::<Vec<_>>()
+         .into_iter(),
+   ))
+}
+
+pub async fn join(state: &State, [separator, strings]: [Value; 2]) -> Result<Value, Value> {
+   let separator = forced::<value::SString>(state, separator).await?;
+
+   let mut joined = String::new();
+
+   for (index, string) in list(state, strings).await?.into_iter().enumerate() {
+      if index != 0 {
+         joined.push_str(&separator);
+      }
+
+      joined.push_str(&forced::<value::SString>(state, string).await?);
+   }
+
+   Ok(Value::from(value::SString::from(&*joined)))
+}
+
+/// Replaces every occurrence of `from` with `to` in the string.
+pub async fn replace(state: &State, [from, to, string]: [Value; 3]) -> Result<Value, Value> {
+   let from = not_empty(forced::<value::SString>(state, from).await?, "pattern")?;
+   let to = forced::<value::SString>(state, to).await?;
+   let string = forced::<value::SString>(state, string).await?;
+
+   Ok(Value::from(value::SString::from(
+      &*string.replace(&**from, &to),
+   )))
+}
+
+pub async fn trim(state: &State, [string]: [Value; 1]) -> Result<Value, Value> {
+   let string = forced::<value::SString>(state, string).await?;
+
+   Ok(slice(&string, string.trim()))
+}
+
+pub async fn trim_start(state: &State, [string]: [Value; 1]) -> Result<Value, Value> {
+   let string = forced::<value::SString>(state, string).await?;
+
+   Ok(slice(&string, string.trim_start()))
+}
+
+pub async fn trim_end(state: &State, [string]: [Value; 1]) -> Result<Value, Value> {
+   let string = forced::<value::SString>(state, string).await?;
+
+   Ok(slice(&string, string.trim_end()))
+}
+
+pub async fn to_upper(state: &State, [string]: [Value; 1]) -> Result<Value, Value> {
+   let string = forced::<value::SString>(state, string).await?;
+
+   Ok(Value::from(value::SString::from(&*string.to_uppercase())))
+}
+
+pub async fn to_lower(state: &State, [string]: [Value; 1]) -> Result<Value, Value> {
+   let string = forced::<value::SString>(state, string).await?;
+
+   Ok(Value::from(value::SString::from(&*string.to_lowercase())))
+}
+
+pub async fn starts_with(state: &State, [prefix, string]: [Value; 2]) -> Result<Value, Value> {
+   let prefix = forced::<value::SString>(state, prefix).await?;
+   let string = forced::<value::SString>(state, string).await?;
+
+   Ok(Value::Boolean(string.starts_with(&**prefix)))
+}
+
+pub async fn ends_with(state: &State, [suffix, string]: [Value; 2]) -> Result<Value, Value> {
+   let suffix = forced::<value::SString>(state, suffix).await?;
+   let string = forced::<value::SString>(state, string).await?;
+
+   Ok(Value::Boolean(string.ends_with(&**suffix)))
+}
+
+/// Splits the string into a list of its Unicode scalar values.
+pub async fn to_chars(state: &State, [string]: [Value; 1]) -> Result<Value, Value> {
+   let string = forced::<value::SString>(state, string).await?;
+
+   Ok(list_from(string.chars().map(Value::Char)))
+}
+
+/// Collects a list of chars into a string.
+pub async fn from_chars(state: &State, [chars]: [Value; 1]) -> Result<Value, Value> {
+   let mut string = String::new();
+
+   for char in list(state, chars).await? {
+      string.push(forced::<char>(state, char).await?);
+   }
+
+   Ok(Value::from(value::SString::from(&*string)))
+}
+
+/// Parses a decimal integer with an optional sign.
+pub async fn parse_integer(state: &State, [string]: [Value; 1]) -> Result<Value, Value> {
+   let string = forced::<value::SString>(state, string).await?;
+
+   let integer = num::BigInt::from_str(&string)
+      .map_err(|_| error(format!("invalid integer {string:?}", string = &**string)))?;
+
+   Ok(Value::from(value::Integer::from(integer)))
+}
+
+/// Formats an integer in decimal.
+pub async fn format_integer(state: &State, [integer]: [Value; 1]) -> Result<Value, Value> {
+   let integer = forced::<value::Integer>(state, integer).await?;
+
+   Ok(Value::from(value::SString::from(&*integer.to_string())))
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::builtin::{
+      evaluate,
+      string,
+   };
+
+   #[tokio::test]
+   async fn length_counts_graphemes() {
+      assert_eq!(
+         string(&evaluate("formatInteger (stringLength \"e\u{301}👍🏽!\")").await),
+         "3",
+      );
+      assert_eq!(
+         string(&evaluate(r#"formatInteger (stringLength "")"#).await),
+         "0",
+      );
+   }
+
+   #[tokio::test]
+   async fn substring_clamps() {
+      assert_eq!(string(&evaluate(r#"substring 1 2 "a👍🏽bc""#).await), "👍🏽b");
+      assert_eq!(string(&evaluate(r#"substring 2 10 "abc""#).await), "c");
+      assert_eq!(string(&evaluate(r#"substring 5 1 "abc""#).await), "");
+      assert!(matches!(
+         evaluate(r#"substring (parseInteger "-1") 1 "abc""#).await,
+         Value::Error(_),
+      ));
+   }
+
+   #[tokio::test]
+   async fn split_and_join() {
+      assert_eq!(
+         string(&evaluate(r#"toJSON (split ", " "a, b,, c")"#).await),
+         r#"["a","b,","c"]"#,
+      );
+      assert_eq!(
+         string(&evaluate(r#"join "-" (split "," "a,b,c")"#).await),
+         "a-b-c",
+      );
+      assert_eq!(string(&evaluate(r#"join "-" []"#).await), "");
+      assert!(matches!(
+         evaluate(r#"split "" "abc""#).await,
+         Value::Error(_),
+      ));
+      assert!(matches!(
+         evaluate(r#"join "-" [ 1 ]"#).await,
+         Value::Error(_),
+      ));
+   }
+
+   #[tokio::test]
+   async fn replace_all() {
+      assert_eq!(
+         string(&evaluate(r#"replace "o" "0" "foo boo""#).await),
+         "f00 b00",
+      );
+      assert!(matches!(
+         evaluate(r#"replace "" "x" "abc""#).await,
+         Value::Error(_),
+      ));
+   }
+
+   #[tokio::test]
+   async fn trims() {
+      assert_eq!(string(&evaluate(r#"trim "  a b \n""#).await), "a b");
+      assert_eq!(string(&evaluate(r#"trimStart "  a ""#).await), "a ");
+      assert_eq!(string(&evaluate(r#"trimEnd "  a ""#).await), "  a");
+   }
+
+   #[tokio::test]
+   async fn case_conversion() {
+      assert_eq!(string(&evaluate(r#"toUpper "straße""#).await), "STRASSE");
+      assert_eq!(string(&evaluate(r#"toLower "ÀB""#).await), "àb");
+   }
+
+   #[tokio::test]
+   async fn affixes() {
+      assert_eq!(
+         string(&evaluate(r#"toJSON [ startsWith "ab" "abc", startsWith "b" "abc" ]"#).await),
+         "[true,false]",
+      );
+      assert_eq!(
+         string(&evaluate(r#"toJSON [ endsWith "bc" "abc", endsWith "" "abc" ]"#).await),
+         "[true,true]",
+      );
+   }
+
+   #[tokio::test]
+   async fn chars() {
+      assert_eq!(
+         string(&evaluate(r#"toJSON (stringToChars "ab")"#).await),
+         r#"["a","b"]"#,
+      );
+      assert!(matches!(
+         evaluate(r#"stringToChars "a""#).await,
+         Value::Cons(ref cons) if matches!(cons.0, Value::Char('a')),
+      ));
+      assert_eq!(string(&evaluate("charsToString [ 'a', 'b' ]").await), "ab");
+      assert!(matches!(
+         evaluate(r#"charsToString [ "a" ]"#).await,
+         Value::Error(_),
+      ));
+   }
+
+   #[tokio::test]
+   async fn integers() {
+      assert_eq!(
+         string(&evaluate(r#"formatInteger (parseInteger "-123456789012345678901234")"#).await),
+         "-123456789012345678901234",
+      );
+      assert!(matches!(
+         evaluate(r#"parseInteger "12a""#).await,
+         Value::Error(_),
+      ));
+      assert!(matches!(
+         evaluate("formatInteger 1.5").await,
+         Value::Error(_),
+      ));
+   }
+}
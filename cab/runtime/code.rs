@@ -1,41 +1,22 @@
 use std::{
-   cell::RefCell,
-   collections::VecDeque,
    fmt::{
       self,
       Write as _,
    },
-   ops::{
-      self,
-      Add as _,
-   },
+   ops,
+   sync::OnceLock,
 };
 
 use derive_more::{
    Deref,
    DerefMut,
 };
-use dup::Dupe as _;
+use dup::Dupe;
 use ranged::Span;
+use rustc_hash::FxHashSet;
 use ust::{
-   COLORS,
    Display,
-   INDENT_WIDTH,
-   STYLE_GUTTER,
    Write,
-   style::{
-      self,
-      StyledExt as _,
-   },
-   terminal::{
-      self,
-      DOT,
-      LEFT_TO_RIGHT,
-      RIGHT_TO_BOTTOM,
-      TOP_TO_BOTTOM,
-   },
-   with,
-   write,
 };
 
 use crate::{
@@ -53,6 +34,11 @@ const ENCODED_OPERATION_LEN: usize = 1;
 pub struct ByteIndex(usize);
 
 impl ByteIndex {
+   #[must_use]
+   pub fn zero() -> Self {
+      Self(0)
+   }
+
    #[must_use]
    pub fn dummy() -> Self {
       Self(usize::MAX)
@@ -76,191 +62,16 @@ pub struct Code {
    spans: Vec<(ByteIndex, Span)>,
 
    values: Vec<Value>,
+
+   /// The names the code refers to, computed once the code is forced.
+   references: OnceLock<Option<FxHashSet<value::SString>>>,
 }
 
+/// Writes the code in the textual assembly format, which
+/// [`Code::assemble`] parses back.
 impl Display for Code {
    fn display_styled(&self, writer: &mut dyn Write) -> fmt::Result {
-      const STYLE_JUMP_ADDRESS: style::Style = style::Color::BrightYellow.fg().bold().underline();
-
-      enum CodeType {
-         NeedsArgument,
-         Thunkable,
-      }
-
-      let mut codes = VecDeque::from([(0_usize, CodeType::NeedsArgument, self)]);
-
-      while let Some((code_index, code_type, code)) = codes.pop_back() {
-         let highlighted = RefCell::new(Vec::<ByteIndex>::new());
-
-         // INDENT: "0x123 | "
-         let index_width = 2 + terminal::number_hex_width(code.bytes.len() - 1);
-         let indent_index = RefCell::new(ByteIndex(0));
-         let mut index_previous = None::<usize>;
-         terminal::indent!(writer, index_width + 3, |writer| {
-            let index = *indent_index.borrow();
-
-            let style = if highlighted.borrow().contains(&index) {
-               STYLE_JUMP_ADDRESS
-            } else {
-               STYLE_GUTTER
-            };
-
-            let index = *index;
-
-            with(writer, style, |writer| {
-               if index_previous == Some(index) {
-                  let dot_width = 2 + terminal::number_hex_width(index);
-                  let space_width = index_width - dot_width;
-
-                  write!(writer, "{:>space_width$}", "")?;
-
-                  for _ in 0..dot_width {
-                     write!(writer, "{DOT}")?;
-                  }
-               } else {
-                  write!(writer, "{index:>#index_width$X}")?;
-               }
-
-               write!(writer, " {TOP_TO_BOTTOM}")
-            })?;
-
-            index_previous.replace(index);
-            Ok(index_width + 2)
-         });
-
-         if **indent_index.borrow() < code.bytes.len() {
-            // DEDENT: "| "
-            terminal::dedent!(writer, 2);
-
-            // INDENT: "┏━━━ ".
-            terminal::indent!(
-               writer,
-               header =
-                  const_str::concat!(RIGHT_TO_BOTTOM, LEFT_TO_RIGHT, LEFT_TO_RIGHT, LEFT_TO_RIGHT)
-                     .style(STYLE_GUTTER)
-            );
-
-            with(writer, style::Color::Red.fg().bold(), |writer| {
-               write!(writer, "{code_index:#X} ")
-            })?;
-
-            match code_type {
-               CodeType::NeedsArgument => write(writer, &"(thunkable)".cyan().bold())?,
-               CodeType::Thunkable => write(writer, &"(needs-argument)".magenta().bold())?,
-            }
-         }
-
-         let mut indent: usize = 0;
-
-         let mut items = code.iter().peekable();
-         while let Some((index, item)) = items.next() {
-            *indent_index.borrow_mut() = index;
-
-            match item {
-               CodeItem::Operation(operation) => {
-                  terminal::indent!(
-                     writer,
-                     (indent - usize::from(operation == Operation::ScopeEnd))
-                        * INDENT_WIDTH as usize,
-                  );
-
-                  writeln!(writer)?;
-
-                  if operation == Operation::ScopeEnd {
-                     indent -= 1;
-                     write(writer, &"}".style(COLORS[indent % COLORS.len()]))?;
-                     write!(writer, " ")?;
-                  }
-
-                  with(writer, style::Color::Yellow.fg(), |writer| {
-                     write!(writer, "{operation:?}")
-                  })?;
-
-                  if operation == Operation::ScopeStart {
-                     write!(writer, " ")?;
-                     write(writer, &"{".style(COLORS[indent % COLORS.len()]))?;
-                     indent += 1;
-                  }
-
-                  if let Some(&(_, CodeItem::Argument(_))) = items.peek() {
-                     write(writer, &'('.bright_black().bold())?;
-                  }
-               },
-
-               CodeItem::Argument(argument) => {
-                  match argument {
-                     Argument::U16(u16) => write(writer, &u16.magenta())?,
-
-                     Argument::U64(u64) => write(writer, &u64.blue())?,
-
-                     Argument::ValueIndex(value_index) => {
-                        let value_index_unique = code_index.add(2) * value_index.add(2);
-
-                        with(writer, style::Color::Blue.fg().bold(), |writer| {
-                           write!(writer, "{value_index:#X} ", value_index = *value_index)
-                        })?;
-
-                        match code[value_index] {
-                           ref value @ (Value::NeedsArgumentToThunk(ref code)
-                           | Value::Thunkable(ref code)) => {
-                              codes.push_front((
-                                 value_index_unique,
-                                 match value {
-                                    &Value::NeedsArgumentToThunk(_) => CodeType::Thunkable,
-                                    &Value::Thunkable(_) => CodeType::NeedsArgument,
-                                    _ => unreachable!(),
-                                 },
-                                 code,
-                              ));
-
-                              write(writer, &"-> ".bright_black().bold())?;
-                              with(writer, style::Color::Red.fg().bold(), |writer| {
-                                 write!(writer, "{value_index_unique:#X}")
-                              })?;
-                           },
-
-                           ref value => {
-                              // TODO: This isn't indented as our indenter:
-                              //
-                              // 1. Doesn't support dynamic indent widths. This is easily fixed by
-                              //    storing an indent (like iter::continuations) and measuring the
-                              //    length in IndentWrite::width.
-                              //
-                              // 2. Doesn't support specifying `Place`.
-                              //
-                              // Fix this soon, maybe --RGB.
-                              write(writer, &":: ".bright_black().bold())?;
-                              value.display_styled(writer)?;
-                           },
-                        }
-                     },
-
-                     Argument::ByteIndex(byte_index) => {
-                        highlighted.borrow_mut().push(byte_index);
-
-                        with(writer, STYLE_JUMP_ADDRESS, |writer| {
-                           write!(writer, "{byte_index:#X}", byte_index = *byte_index)
-                        })?;
-                     },
-                  }
-
-                  let delimiter = match items.peek() {
-                     Some(&(_, CodeItem::Argument(_))) => ", ",
-                     _ => ")",
-                  };
-
-                  write(writer, &delimiter.bright_black().bold())?;
-               },
-            }
-         }
-
-         if !codes.is_empty() {
-            writeln!(writer)?;
-            writeln!(writer)?;
-         }
-      }
-
-      Ok(())
+      write!(writer, "{assembly}", assembly = self.assembly())
    }
 }
 
@@ -305,42 +116,68 @@ impl Code {
             yield (index, CodeItem::Operation(operation));
             *index += size;
 
-            match operation {
-               Operation::Push => {
-                  let (value, size) = self.read_u64(index);
+            if let Some((argument, size)) = self.read_argument(index, operation) {
+               yield (index, CodeItem::Argument(argument));
+               *index += size;
+            }
+         }
+      }
+   }
+
+   /// Reads the operation at the given index and its argument, if it has one.
+   /// Returns the index of the next operation alongside them.
+   ///
+   /// Returns [`None`] if the index is at the end of the code.
+   #[must_use]
+   pub fn read(&self, index: ByteIndex) -> Option<(Operation, Option<Argument>, ByteIndex)> {
+      let &byte = self.bytes.get(*index)?;
 
-                  yield (
-                     index,
-                     CodeItem::Argument(Argument::ValueIndex(ValueIndex(
-                        usize::try_from(value).expect("value index must be valid"),
-                     ))),
-                  );
+      let operation = Operation::try_from(byte).expect("byte index must be valid");
+      let mut next = ByteIndex(*index + ENCODED_OPERATION_LEN);
 
-                  *index += size;
-               },
+      let argument = self
+         .read_argument(next, operation)
+         .map(|(argument, size)| {
+            *next += size;
+            argument
+         });
 
-               Operation::Jump | Operation::JumpIf | Operation::JumpIfError => {
-                  let (value, size) = self.read_u16(index);
+      Some((operation, argument, next))
+   }
 
-                  yield (
-                     index,
-                     CodeItem::Argument(Argument::ByteIndex(ByteIndex(usize::from(value)))),
-                  );
+   #[must_use]
+   fn read_argument(&self, index: ByteIndex, operation: Operation) -> Option<(Argument, usize)> {
+      match operation {
+         Operation::Push => {
+            let (value, size) = self.read_u64(index);
+
+            Some((
+               Argument::ValueIndex(ValueIndex(
+                  usize::try_from(value).expect("value index must be valid"),
+               )),
+               size,
+            ))
+         },
 
-                  *index += size;
-               },
+         Operation::Jump | Operation::JumpIf | Operation::JumpIfError => {
+            let (value, size) = self.read_u16(index);
 
-               Operation::Interpolate => {
-                  let (value, size) = self.read_u64(index);
+            Some((Argument::ByteIndex(ByteIndex(usize::from(value))), size))
+         },
 
-                  yield (index, CodeItem::Argument(Argument::U64(value)));
+         Operation::Interpolate => {
+            let (value, size) = self.read_u64(index);
 
-                  *index += size;
-               },
+            Some((Argument::U64(value), size))
+         },
 
-               _ => {},
-            }
-         }
+         Operation::ResolveFrom => {
+            let (value, size) = self.read_u16(index);
+
+            Some((Argument::U16(value), size))
+         },
+
+         _ => None,
       }
    }
 }
@@ -363,6 +200,8 @@ impl Code {
          spans: Vec::new(),
 
          values: Vec::new(),
+
+         references: OnceLock::new(),
       }
    }
 
@@ -371,6 +210,54 @@ impl Code {
       &self.path
    }
 
+   #[must_use]
+   pub fn bytes(&self) -> &[u8] {
+      &self.bytes
+   }
+
+   #[must_use]
+   pub fn values(&self) -> &[Value] {
+      &self.values
+   }
+
+   /// Returns the names the code and the codes nested in it refer to, or
+   /// [`None`] if they interpolate names that are only known at runtime.
+   ///
+   /// Must only be called once the code is done being built.
+   #[must_use]
+   pub fn references(&self) -> Option<&FxHashSet<value::SString>> {
+      self
+         .references
+         .get_or_init(|| {
+            let interpolates = self
+               .iter()
+               .any(|(_, item)| item == CodeItem::Operation(Operation::Interpolate));
+
+            if interpolates {
+               return None;
+            }
+
+            let mut references = FxHashSet::default();
+
+            for value in &self.values {
+               match *value {
+                  Value::Reference(ref name) => {
+                     references.insert(name.dupe());
+                  },
+
+                  Value::Thunkable(ref code) | Value::NeedsArgumentToThunk(ref code) => {
+                     references.extend(code.references()?.iter().map(Dupe::dupe));
+                  },
+
+                  _ => {},
+               }
+            }
+
+            Some(references)
+         })
+         .as_ref()
+   }
+
    pub fn push_u64(&mut self, data: u64) -> ByteIndex {
       let mut encoded = [0; ENCODED_U64_LEN_MAX];
       let len = vu128::encode_u64(&mut encoded, data);
@@ -448,9 +335,20 @@ impl Code {
       )
    }
 
-   pub fn point_here(&mut self, index: ByteIndex) {
-      let here = u16::try_from(self.bytes.len()).expect("bytes len must fit in u16");
+   /// Returns the index the next pushed operation or argument will be at.
+   #[must_use]
+   pub fn here(&self) -> ByteIndex {
+      ByteIndex(self.bytes.len())
+   }
 
-      self.bytes[*index..*index + ENCODED_U16_LEN_MAX].copy_from_slice(&here.to_le_bytes());
+   /// Points the jump argument at the given index to the target.
+   pub fn point(&mut self, index: ByteIndex, target: ByteIndex) {
+      let target = u16::try_from(*target).expect("byte index must fit in u16");
+
+      self.bytes[*index..*index + ENCODED_U16_LEN_MAX].copy_from_slice(&target.to_le_bytes());
+   }
+
+   pub fn point_here(&mut self, index: ByteIndex) {
+      self.point(index, self.here());
    }
 }
@@ -1,4 +1,8 @@
-use std::ops;
+use std::{
+   mem,
+   ops,
+   sync::Arc,
+};
 
 use cab_syntax::lode;
 use cab_util::{
@@ -11,7 +15,11 @@ use ranged::{
    Span,
    Spanned,
 };
-use smallvec::SmallVec;
+use smallvec::{
+   SmallVec,
+   smallvec,
+};
+use ust::report::Report;
 
 use crate::{
    Code,
@@ -20,39 +28,128 @@ use crate::{
    value,
 };
 
+mod scope;
+use scope::{
+   LocalName,
+   LocalPosition,
+   Scope,
+};
+
 const EXPECT_CODE: &str = "emitter must have at least one code at all times";
+const EXPECT_SCOPE: &str = "emitter must be in at least one scope when emitting";
+
+/// The result of compiling an expression.
+pub struct Compile {
+   /// The code the expression compiled to.
+   pub code:    Code,
+   /// The reports of compiling the expression, which are all warnings.
+   pub reports: Arc<[Report]>,
+}
 
+#[derive(Clone, Copy)]
 pub struct CompileOracle {
-   _reserved: (),
+   resolve_statically: bool,
 }
 
 #[bon::bon]
 impl CompileOracle {
    #[must_use]
    pub fn new() -> Self {
-      Self { _reserved: () }
+      Self {
+         resolve_statically: true,
+      }
+   }
+
+   /// Makes every reference resolve by walking every scope, rather than
+   /// skipping the scopes that can't bind it. Only useful to measure against.
+   #[must_use]
+   pub fn dynamic(mut self) -> Self {
+      self.resolve_statically = false;
+      self
    }
 
-   #[expect(clippy::unused_self)]
    #[builder(finish_fn(name = "path"))]
    #[must_use]
    pub fn compile(
       &self,
       #[builder(start_fn)] expression: lode::Resolved<'_, &lode::Expression>,
       #[builder(finish_fn)] path: value::Path,
-   ) -> Code {
-      let mut emitter = Emitter::new(path);
+   ) -> Compile {
+      let mut emitter = Emitter::new(path, self.resolve_statically);
 
-      emitter.emit_scope(expression.span(), |this| {
+      emitter.emit_scope(expression.span(), Scope::of(expression), |this| {
          this.emit_force(expression);
       });
 
-      emitter.codes.pop().expect(EXPECT_CODE)
+      let code = emitter.codes.pop().expect(EXPECT_CODE);
+      code.verify_debug();
+
+      Compile {
+         code,
+         reports: Arc::from(emitter.reports),
+      }
    }
 }
 
+/// Returns the shape the expression is annotated with, if it is a bind.
+fn shape<'arena>(
+   expression: lode::Resolved<'arena, &'arena lode::Expression>,
+) -> Option<&'arena Spanned<lode::Shape>> {
+   match expression.propagate() {
+      lode::ExpressionPropagated::Bind(bind) => bind.shape(),
+      _ => None,
+   }
+}
+
+/// Returns the pattern of the match arm the if was lowered from, if it was.
+fn match_pattern<'arena>(
+   if_: lode::Resolved<'arena, Spanned<&'arena lode::If>>,
+) -> Option<lode::Resolved<'arena, &'arena lode::Expression>> {
+   let lode::ExpressionPropagated::Equal(equal) = if_.condition().propagate() else {
+      return None;
+   };
+
+   let lode::ExpressionPropagated::Identifier(identifier) = equal.left().propagate() else {
+      return None;
+   };
+
+   (scope::plain(identifier.segments()).as_deref() == Some(cab_syntax::MATCH_SCRUTINEE))
+      .then(|| equal.right())
+}
+
+/// Returns the match arm the expression is, looking through the parenthesis
+/// every arm is lowered in.
+fn match_arm<'arena>(
+   expression: lode::Resolved<'arena, &'arena lode::Expression>,
+) -> Option<lode::Resolved<'arena, Spanned<&'arena lode::If>>> {
+   let lode::ExpressionPropagated::Parenthesis(parenthesis) = expression.propagate() else {
+      return None;
+   };
+
+   let lode::ExpressionPropagated::If(if_) = parenthesis.expression().propagate() else {
+      return None;
+   };
+
+   match_pattern(if_).is_some().then_some(if_)
+}
+
+/// Whether the pattern matches every value, which binds without a shape do.
+fn matches_everything<'arena>(pattern: lode::Resolved<'arena, &'arena lode::Expression>) -> bool {
+   matches!(
+      pattern.propagate(),
+      lode::ExpressionPropagated::Bind(bind) if bind.shape().is_none(),
+   )
+}
+
 struct Emitter {
-   codes: Vec<Code>,
+   codes:   Vec<Code>,
+   reports: Vec<Report>,
+
+   /// The scopes the code being emitted is evaluated in at runtime, from the
+   /// outermost to the innermost. Thunks capture the scopes they are created
+   /// in, so these stay the same across thunk boundaries.
+   scopes:             Vec<Scope<'static>>,
+   resolve_statically: bool,
 }
 
 impl ops::Deref for Emitter {
@@ -70,9 +167,13 @@ impl ops::DerefMut for Emitter {
 }
 
 impl Emitter {
-   fn new(path: value::Path) -> Self {
+   fn new(path: value::Path, resolve_statically: bool) -> Self {
       Self {
-         codes: vec![Code::new(path)],
+         codes:   vec![Code::new(path)],
+         reports: Vec::new(),
+
+         scopes: Vec::new(),
+         resolve_statically,
       }
    }
 }
@@ -88,10 +189,14 @@ impl Emitter {
       self.push_u64(*index as _);
    }
 
-   fn emit_scope(&mut self, span: Span, with: impl FnOnce(&mut Self)) {
+   fn emit_scope(&mut self, span: Span, scope: Scope<'static>, with: impl FnOnce(&mut Self)) {
+      self.scopes.push(scope);
+
       self.push_operation(span, Operation::ScopeStart);
       with(self);
       self.push_operation(span, Operation::ScopeEnd);
+
+      self.scopes.pop().expect(EXPECT_SCOPE);
    }
 
    fn emit_thunk_start(&mut self) {
@@ -138,9 +243,13 @@ impl Emitter {
       &mut self,
       parenthesis: lode::Resolved<'arena, Spanned<&'arena lode::Parenthesis>>,
    ) {
-      self.emit_scope(parenthesis.span(), |this| {
-         this.emit(parenthesis.expression());
-      });
+      self.emit_scope(
+         parenthesis.span(),
+         Scope::of(parenthesis.expression()),
+         |this| {
+            this.emit(parenthesis.expression());
+         },
+      );
    }
 
    fn emit_attributes<'arena>(
@@ -150,7 +259,7 @@ impl Emitter {
       match attributes.expression() {
          Some(expression) => {
             self.emit_thunk(attributes.span()).with(|this| {
-               this.emit_scope(attributes.span(), |this| {
+               this.emit_scope(attributes.span(), Scope::of(expression), |this| {
                   this.emit_force(expression);
                   let to_end = {
                      this.push_operation(expression.span(), Operation::JumpIfError);
@@ -193,9 +302,18 @@ impl Emitter {
          self.push_u16(u16::default())
       };
 
+      // The scope is swapped with the attributes of the left side, which can
+      // bind anything.
+      let scope = mem::replace(
+         self.scopes.last_mut().expect(EXPECT_SCOPE),
+         Scope::wildcard(),
+      );
+
       emit_right(self);
       self.push_operation(right_span, Operation::Force);
 
+      *self.scopes.last_mut().expect(EXPECT_SCOPE) = scope;
+
       self.push_operation(span, Operation::Swap);
       self.push_operation(span, Operation::ScopeSwap);
       self.push_operation(span, Operation::Pop);
@@ -265,15 +383,47 @@ impl Emitter {
          .emit_select(select.span())
          .left((scope.span(), |this| this.emit_force(scope)))
          .right((expression.span(), |this| {
-            this.emit_scope(expression.span(), |this| {
+            this.emit_scope(expression.span(), Scope::of(expression), |this| {
                this.emit(expression);
             });
          }));
    }
 
+   fn emit_assert_shape(&mut self, shape: &Spanned<lode::Shape>) {
+      self.emit_push(shape.span(), value::SString::from(shape.name()));
+      self.push_operation(shape.span(), Operation::AssertShape);
+   }
+
+   /// Emits the expression, checking its value against the shape of the bind
+   /// it is bound to, if the bind is annotated with one.
+   fn emit_shaped<'arena>(
+      &mut self,
+      expression: lode::Resolved<'arena, &'arena lode::Expression>,
+      bind: lode::Resolved<'arena, &'arena lode::Expression>,
+   ) {
+      let Some(shape) = shape(bind) else {
+         self.emit(expression);
+         return;
+      };
+
+      self.emit_thunk(expression.span()).with(|this| {
+         this.emit_force(expression);
+         this.emit_assert_shape(shape);
+      });
+   }
+
    fn emit_equal<'arena>(&mut self, equal: lode::Resolved<'arena, Spanned<&'arena lode::Equal>>) {
-      self.emit(equal.left());
-      self.emit(equal.right());
+      let left = equal.left();
+      let right = equal.right();
+
+      for (expression, other) in [(left, right), (right, left)] {
+         if scope::is_literal(other) {
+            self.emit_force(expression);
+         } else {
+            self.emit_shaped(expression, other);
+         }
+      }
+
       self.push_operation(equal.span(), Operation::Equal);
    }
 
@@ -340,7 +490,17 @@ impl Emitter {
          .emit_thunk(lambda.span())
          .needs_argument(true)
          .with(|this| {
-            this.emit_scope(lambda.span(), |this| {
+            // Arguments can be binds themselves, which bind their name in the
+            // scope of the lambda when equated with the parameter.
+            this.emit_scope(lambda.span(), Scope::wildcard(), |this| {
+               let to_end = shape(argument).map(|shape| {
+                  this.push_operation(argument.span(), Operation::Force);
+                  this.emit_assert_shape(shape);
+
+                  this.push_operation(argument.span(), Operation::JumpIfError);
+                  this.push_u16(u16::default())
+               });
+
                this.emit_force(argument);
                this.push_operation(argument.span(), Operation::Equal);
 
@@ -365,6 +525,10 @@ impl Emitter {
                this.emit_force(expression);
 
                this.point_here(over_body);
+
+               if let Some(to_end) = to_end {
+                  this.point_here(to_end);
+               }
             });
          });
    }
@@ -397,7 +561,7 @@ impl Emitter {
                   },
 
                   &lode::Segment::Interpolation(ref interpolation) => {
-                     this.emit_scope(segment.span(), |this| {
+                     this.emit_scope(segment.span(), Scope::of(*interpolation), |this| {
                         this.emit_force(*interpolation);
                      });
                   },
@@ -420,6 +584,8 @@ impl Emitter {
       let segments_are_trivial = segments.is_trivial();
       let needs_thunk = !is_bind || !segments_are_trivial;
 
+      let name = (!is_bind).then(|| scope::plain(segments)).flatten();
+
       self.emit_thunk(span).if_(needs_thunk).with(|this| {
          let segments = segments.into_iter().collect::<SmallVec<_, 4>>();
 
@@ -439,7 +605,7 @@ impl Emitter {
                },
 
                &lode::Segment::Interpolation(ref interpolation) => {
-                  this.emit_scope(segment.span(), |this| {
+                  this.emit_scope(segment.span(), Scope::of(*interpolation), |this| {
                      this.emit_force(*interpolation);
                   });
                },
@@ -452,13 +618,47 @@ impl Emitter {
          }
 
          if !is_bind {
-            this.push_operation(span, Operation::Resolve);
+            this.emit_resolve(span, name);
          }
       });
    }
 
+   /// Emits a resolve of the reference on the stack. If its name is known,
+   /// the scopes that are known not to bind it are skipped. The rest are
+   /// searched by name, see [`crate::Scopes::get_from`].
+   fn emit_resolve(&mut self, span: Span, name: Option<String>) {
+      let count = self.scopes.len();
+
+      let depth = name.filter(|_| self.resolve_statically).map_or(0, |name| {
+         match Scope::locate(&mut self.scopes, &LocalName::new(smallvec![name])) {
+            LocalPosition::Known { scopes, .. } | LocalPosition::Unknown { scopes, .. } => {
+               scopes.len() - 1
+            },
+
+            LocalPosition::Undefined => count,
+         }
+      });
+
+      match u16::try_from(depth) {
+         Ok(depth @ 1..) => {
+            self.push_operation(span, Operation::ResolveFrom);
+            self.push_u16(depth);
+         },
+
+         _ => self.push_operation(span, Operation::Resolve),
+      }
+   }
+
    fn emit_bind<'arena>(&mut self, bind: lode::Resolved<'arena, Spanned<&'arena lode::Bind>>) {
       self.emit_identifier_like(bind.segments(), bind.span(), true);
+
+      if let Some(documentation) = bind.documentation() {
+         self.emit_push(
+            documentation.span(),
+            value::SString::from(&***documentation),
+         );
+         self.push_operation(bind.span(), Operation::Document);
+      }
    }
 
    fn emit_identifier<'arena>(
@@ -488,7 +688,7 @@ impl Emitter {
                   },
 
                   &lode::Segment::Interpolation(ref interpolation) => {
-                     this.emit_scope(segment.span(), |this| {
+                     this.emit_scope(segment.span(), Scope::of(*interpolation), |this| {
                         this.emit_force(*interpolation);
                      });
                   },
@@ -502,7 +702,37 @@ impl Emitter {
          });
    }
 
+   /// Warns about the match arms after the arm of the if, if its pattern
+   /// matches every value.
+   fn warn_unreachable_arms<'arena>(
+      &mut self,
+      if_: lode::Resolved<'arena, Spanned<&'arena lode::If>>,
+   ) {
+      let Some(pattern) = match_pattern(if_).filter(|&pattern| matches_everything(pattern)) else {
+         return;
+      };
+
+      let mut alternative = if_.alternative();
+
+      while let Some(arm) = match_arm(alternative) {
+         self.reports.push(
+            Report::warn("unreachable match arm")
+               .primary(alternative.span(), "this arm is never reached")
+               .secondary(pattern.span(), "because this pattern matches every value"),
+         );
+
+         // The arms after the next catch-all are warned about by it.
+         if match_pattern(arm).is_some_and(matches_everything) {
+            break;
+         }
+
+         alternative = arm.alternative();
+      }
+   }
+
    fn emit_if<'arena>(&mut self, if_: lode::Resolved<'arena, Spanned<&'arena lode::If>>) {
+      self.warn_unreachable_arms(if_);
+
       let condition = if_.condition();
       let consequence = if_.consequence();
       let alternative = if_.alternative();
@@ -523,7 +753,7 @@ impl Emitter {
          };
 
          this.push_operation(if_.span(), Operation::Pop);
-         this.emit_scope(alternative.span(), |this| {
+         this.emit_scope(alternative.span(), Scope::of(alternative), |this| {
             this.emit_force(alternative);
          });
          let over_consequence = {
@@ -533,7 +763,7 @@ impl Emitter {
 
          this.point_here(to_consequence);
          this.push_operation(if_.span(), Operation::Pop);
-         this.emit_scope(consequence.span(), |this| {
+         this.emit_scope(consequence.span(), Scope::of(consequence), |this| {
             this.emit_force(consequence);
          });
 
@@ -641,3 +871,154 @@ impl Emitter {
       self.push_operation(expression.span(), Operation::Force);
    }
 }
+
+#[cfg(test)]
+mod tests {
+   use cab_syntax as syntax;
+   use rpds::ListSync as List;
+   use ust::{
+      Display as _,
+      report,
+      terminal,
+   };
+
+   use super::*;
+   use crate::builtin::evaluate_compiled;
+
+   /// Sources that must evaluate to the same value with references resolved
+   /// statically and dynamically.
+   const CORPUS: &[&str] = &[
+      "{ @a = 1; @b = { @c = a } }.b.c",
+      "{ @a = 1; @b = { @a = 2; @c = a } }.b.c",
+      "{ @a = { @x = 1 }; @b = a.x }.b",
+      "{ @x = 1; @y = { @x = 2 }.x }.y",
+      "{ @x = 1; @y = { @z = 2 }.x }.y",
+      "{ @toJSON = 1; @a = { @b = { @c = toJSON } } }.a.b.c",
+      "{ @a = { @b = { @c = toJSON [ 1 ] } } }.a.b.c",
+      "{ @a = { @b = undefined } }.a.b",
+      "(@x => { @y = x }.y) 3",
+      "(@x => @y => { @a = [ x, y ] }.a) 1 2",
+      "(@x => if x then { @a = toUpper \"a\" }.a else 2) true",
+      "mapAttrs (@name => @value => { @a = [ name, value ] }.a) { @a = 1; @b = 2 }",
+      "{ @a = 1; @b = match 2 { 1 => a, @a => { @c = a }.c } }.b",
+      "(@x => match x { 0 => toJSON, @n => [ n, x ] }) 3",
+   ];
+
+   fn display(value: &crate::Value) -> String {
+      let mut displayed = String::new();
+
+      {
+         let writer = &mut terminal::writer(terminal::StyleChoice::Never, &mut displayed);
+         value
+            .display_styled(writer)
+            .expect("displaying to a string must not fail");
+      }
+
+      displayed
+   }
+
+   fn compile(source: &str, compile_oracle: &CompileOracle) -> Compile {
+      let parse = syntax::ParseOracle::new().parse(syntax::tokenize(source));
+      assert!(parse.reports.is_empty(), "source must parse cleanly");
+
+      let lower = syntax::LowerOracle::new().lower(parse.expression.as_ref());
+      assert!(lower.reports.is_empty(), "source must lower cleanly");
+
+      compile_oracle
+         .compile(lower.expression())
+         .path(value::Path::rootless(List::new_sync()))
+   }
+
+   #[tokio::test]
+   async fn differential() {
+      for source in CORPUS {
+         for optimize in [false, true] {
+            assert_eq!(
+               display(&evaluate_compiled(source, &CompileOracle::new().dynamic(), optimize).await),
+               display(&evaluate_compiled(source, &CompileOracle::new(), optimize).await),
+               "statically resolved code must evaluate to the same value as dynamically resolved \
+                code: {source}",
+            );
+         }
+      }
+   }
+
+   #[tokio::test]
+   async fn matches() {
+      for (source, expected) in [
+         (r#"match 2 { 1 => "one", 2 => "two" }"#, r#""two""#),
+         (r#"match "b" { "a" => 1, @other => other }"#, r#""b""#),
+         ("match 'c' { 1 => 1, 'c' => 2 }", "2"),
+         ("(@x => match x { 0 => 0, @n => n }) 3", "3"),
+         ("{ @scrutinee = 5; @a = match 1 { 2 => 0, @n => scrutinee } }.a", "5"),
+         ("match [ 1 ] { 1 => 0, [] => 1, @list => list }", "1 : []"),
+         ("match [] { 1 => 0, [] => 1 }", "1"),
+         ("match { @a = 2 } { 1 => 0, [] => 1, @set => set.a }", "2"),
+      ] {
+         for optimize in [false, true] {
+            assert_eq!(
+               display(&evaluate_compiled(source, &CompileOracle::new(), optimize).await),
+               expected,
+               "{source}",
+            );
+         }
+      }
+
+      let displayed = display(
+         &evaluate_compiled("match 3 { 1 => 1, 2 => 2 }", &CompileOracle::new(), true).await,
+      );
+      assert!(
+         displayed.contains("no arm matched, tried the patterns `1`, `2`"),
+         "{displayed}",
+      );
+   }
+
+   #[test]
+   fn unreachable_arms() {
+      let compile = compile("match x { 1 => 1, @n => n, 2 => 2 }", &CompileOracle::new());
+
+      let severities = compile
+         .reports
+         .iter()
+         .map(|report| &report.severity)
+         .collect::<Vec<_>>();
+      assert!(
+         matches!(*severities, [&report::Severity::Warn]),
+         "{severities:?}",
+      );
+
+      for source in [
+         "match x { 1 => 1, @n => n }",
+         "match x { 1 => 1, 2 => 2 }",
+         "if x = 1 then 1 else 2",
+      ] {
+         assert!(
+            compile(source, &CompileOracle::new()).reports.is_empty(),
+            "{source}",
+         );
+      }
+   }
+
+   #[test]
+   fn resolve_from() {
+      let source = "{ @a = 1; @b = { @c = { @d = [ a, toJSON, c ] } } }";
+
+      let assembly = compile(source, &CompileOracle::new())
+         .code
+         .assembly()
+         .to_string();
+
+      // `a` skips the scopes of the values of `c` and `b`, `c` only skips the
+      // scope of its own value and `toJSON` skips every scope of the program.
+      assert!(assembly.contains("ResolveFrom 2"), "{assembly}");
+      assert!(assembly.contains("ResolveFrom 1"), "{assembly}");
+      assert!(assembly.contains("ResolveFrom 4"), "{assembly}");
+
+      let assembly = compile(source, &CompileOracle::new().dynamic())
+         .code
+         .assembly()
+         .to_string();
+
+      assert!(!assembly.contains("ResolveFrom"), "{assembly}");
+   }
+}
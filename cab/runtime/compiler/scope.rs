@@ -2,11 +2,15 @@
 
 use std::borrow::Cow;
 
+use cab_syntax::lode;
 use derive_more::{
    Deref,
    DerefMut,
 };
-use ranged::Span;
+use ranged::{
+   IntoSpan as _,
+   Span,
+};
 use smallvec::{
    SmallVec,
    smallvec,
@@ -172,6 +176,84 @@ impl Scope<'_> {
       }
    }
 
+   /// Returns a scope that can bind any name, for scopes whose bindings can't
+   /// be known at compile time.
+   pub fn wildcard() -> Self {
+      let mut this = Self::new();
+      this.push(Span::dummy(), LocalName::wildcard());
+      this
+   }
+
+   /// Returns the scope the expression is evaluated in, with every name the
+   /// expression can bind in it.
+   ///
+   /// Names bound in nested scopes are included as well. That is harmless, as
+   /// it only makes lookups stop at this scope instead of skipping it.
+   pub fn of(expression: lode::Resolved<'_, &lode::Expression>) -> Self {
+      let mut this = Self::new();
+      this.collect(expression);
+      this
+   }
+
+   #[stacksafe::stacksafe]
+   fn collect(&mut self, expression: lode::Resolved<'_, &lode::Expression>) {
+      let children = match expression.propagate() {
+         lode::ExpressionPropagated::Parenthesis(parenthesis) => vec![parenthesis.expression()],
+         lode::ExpressionPropagated::Attributes(attributes) => {
+            attributes.expression().into_iter().collect()
+         },
+
+         lode::ExpressionPropagated::Same(same) => vec![same.left(), same.right()],
+         lode::ExpressionPropagated::Sequence(sequence) => vec![sequence.left(), sequence.right()],
+         lode::ExpressionPropagated::Call(call) => vec![call.function(), call.argument()],
+         lode::ExpressionPropagated::Construct(construct) => {
+            vec![construct.head(), construct.tail()]
+         },
+         lode::ExpressionPropagated::Select(select) => vec![select.scope(), select.expression()],
+         lode::ExpressionPropagated::Equal(equal) => {
+            if binds_dynamically(equal.left(), is_literal(equal.right()))
+               || binds_dynamically(equal.right(), is_literal(equal.left()))
+            {
+               self.push(equal.span(), LocalName::wildcard());
+            }
+
+            vec![equal.left(), equal.right()]
+         },
+         lode::ExpressionPropagated::And(and) => vec![and.left(), and.right()],
+         lode::ExpressionPropagated::Or(or) => vec![or.left(), or.right()],
+         lode::ExpressionPropagated::All(all) => vec![all.left(), all.right()],
+         lode::ExpressionPropagated::Any(any) => vec![any.left(), any.right()],
+         lode::ExpressionPropagated::Lambda(lambda) => vec![lambda.argument(), lambda.expression()],
+
+         lode::ExpressionPropagated::Path(path) => interpolations(path.segments()),
+         lode::ExpressionPropagated::Bind(bind) => {
+            let name = plain(bind.segments()).map_or_else(LocalName::wildcard, |name| {
+               LocalName::new(smallvec![name])
+            });
+            self.push(bind.span(), name);
+
+            interpolations(bind.segments())
+         },
+         lode::ExpressionPropagated::Identifier(identifier) => {
+            interpolations(identifier.segments())
+         },
+         lode::ExpressionPropagated::SString(string) => interpolations(string.segments()),
+
+         lode::ExpressionPropagated::If(if_) => {
+            vec![if_.condition(), if_.consequence(), if_.alternative()]
+         },
+
+         lode::ExpressionPropagated::Nil(_)
+         | lode::ExpressionPropagated::Char(_)
+         | lode::ExpressionPropagated::Integer(_)
+         | lode::ExpressionPropagated::Float(_) => vec![],
+      };
+
+      for child in children {
+         self.collect(child);
+      }
+   }
+
    pub fn global() -> Self {
       let mut this = Self::new();
 
@@ -207,7 +289,7 @@ impl<'a> Scope<'a> {
    }
 
    // See comment below.
-   fn locate<'this>(
+   pub fn locate<'this>(
       scopes: &'this mut [Scope<'a>],
       name: &LocalName<'a>,
    ) -> LocalPosition<'this, 'a> {
@@ -258,3 +340,72 @@ impl<'a> Scope<'a> {
       })
    }
 }
+
+/// Returns the name the segments spell out, if they don't contain any
+/// interpolations.
+pub fn plain(segments: lode::Resolved<'_, &lode::Segments>) -> Option<String> {
+   if !segments.is_trivial() {
+      return None;
+   }
+
+   segments
+      .into_iter()
+      .find_map(|segment| {
+         match segment.value {
+            lode::Segment::Content(content) => Some(content.value.into_owned()),
+            lode::Segment::Interpolation(_) => None,
+         }
+      })
+}
+
+fn interpolations<'arena>(
+   segments: lode::Resolved<'arena, &'arena lode::Segments>,
+) -> Vec<lode::Resolved<'arena, &'arena lode::Expression>> {
+   segments
+      .into_iter()
+      .filter_map(|segment| {
+         match segment.value {
+            lode::Segment::Content(_) => None,
+            lode::Segment::Interpolation(expression) => Some(expression),
+         }
+      })
+      .collect()
+}
+
+/// Whether the expression is a literal that can't bind. Literals are compared
+/// against forced values, as they can only equal the values they are made of.
+pub fn is_literal(expression: lode::Resolved<'_, &lode::Expression>) -> bool {
+   matches!(
+      expression.propagate(),
+      lode::ExpressionPropagated::Nil(_)
+         | lode::ExpressionPropagated::SString(_)
+         | lode::ExpressionPropagated::Char(_)
+         | lode::ExpressionPropagated::Integer(_)
+         | lode::ExpressionPropagated::Float(_)
+   )
+}
+
+/// Whether the value of the expression may be a bind that isn't written in it
+/// literally. Equating such a value binds a name that can't be known at compile
+/// time.
+///
+/// Only forced values can be arbitrary binds, as unforced ones are thunks.
+fn binds_dynamically(expression: lode::Resolved<'_, &lode::Expression>, forced: bool) -> bool {
+   match expression.propagate() {
+      lode::ExpressionPropagated::Parenthesis(parenthesis) => {
+         binds_dynamically(parenthesis.expression(), forced)
+      },
+      lode::ExpressionPropagated::Sequence(sequence) => binds_dynamically(sequence.right(), true),
+
+      lode::ExpressionPropagated::Select(_)
+      | lode::ExpressionPropagated::Same(_)
+      | lode::ExpressionPropagated::All(_)
+      | lode::ExpressionPropagated::Any(_) => true,
+
+      lode::ExpressionPropagated::Call(_)
+      | lode::ExpressionPropagated::Identifier(_)
+      | lode::ExpressionPropagated::If(_) => forced,
+
+      _ => false,
+   }
+}
@@ -0,0 +1,107 @@
+//! Rendering of the documented members of attributes.
+
+use std::fmt::Write as _;
+
+use cab_syntax::escape_html;
+
+use crate::value;
+
+/// Returns the documented members of the attributes, sorted by name.
+fn documented(attributes: &value::Attributes) -> impl Iterator<Item = (&str, &str)> {
+   attributes.iter().filter_map(|(name, _)| {
+      let documentation = attributes.documentation(name)?;
+
+      Some((&***name, &***documentation))
+   })
+}
+
+/// Renders the documented members of the attributes to Markdown, with a
+/// section for each member.
+#[must_use]
+pub fn to_markdown(title: &str, attributes: &value::Attributes) -> String {
+   let mut markdown = format!("# {title}\n");
+
+   for (name, documentation) in documented(attributes) {
+      write!(markdown, "\n## `{name}`\n\n{documentation}\n")
+         .expect("writing to a string must not fail");
+   }
+
+   markdown
+}
+
+/// Renders the documented members of the attributes to a standalone HTML
+/// page, with a section for each member. Paragraphs of the documentation are
+/// separated by empty lines.
+#[must_use]
+pub fn to_html(title: &str, attributes: &value::Attributes) -> String {
+   let title = escape_html(title);
+
+   let mut html = format!(
+      "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+       </head>\n<body>\n<h1>{title}</h1>\n"
+   );
+
+   for (name, documentation) in documented(attributes) {
+      let name = escape_html(name);
+
+      write!(
+         html,
+         "<section id=\"{name}\">\n<h2><code>{name}</code></h2>\n"
+      )
+      .expect("writing to a string must not fail");
+
+      for paragraph in documentation
+         .split("\n\n")
+         .filter(|paragraph| !paragraph.trim().is_empty())
+      {
+         writeln!(
+            html,
+            "<p>{paragraph}</p>",
+            paragraph = escape_html(paragraph.trim())
+         )
+         .expect("writing to a string must not fail");
+      }
+
+      html.push_str("</section>\n");
+   }
+
+   html.push_str("</body>\n</html>\n");
+   html
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::{
+      Value,
+      builtin::evaluate_with,
+   };
+
+   async fn attributes(source: &str) -> value::Attributes {
+      let Value::Attributes(attributes) = evaluate_with(source, true).await else {
+         panic!("{source} must evaluate to attributes");
+      };
+
+      attributes
+   }
+
+   #[tokio::test]
+   async fn documentation() {
+      let attributes = attributes(
+         "{\n  #| Adds one.\n  #|\n  #| Like `+ 1`.\n  @increment = 1;\n\n  @hidden = 2;\n  #| A <b>.\n  \
+          @b = 3\n}",
+      )
+      .await;
+
+      assert_eq!(
+         to_markdown("math", &attributes),
+         "# math\n\n## `b`\n\nA <b>.\n\n## `increment`\n\nAdds one.\n\nLike `+ 1`.\n",
+      );
+
+      assert!(to_html("math", &attributes).contains(
+         "<section id=\"increment\">\n<h2><code>increment</code></h2>\n<p>Adds one.</p>\n<p>Like \
+          `+ 1`.</p>\n</section>\n"
+      ));
+      assert!(to_html("math", &attributes).contains("<p>A &lt;b&gt;.</p>"));
+   }
+}
@@ -2,6 +2,21 @@
 
 #![feature(gen_blocks)]
 
+mod assembly;
+pub use assembly::Assembly;
+
+mod builtin;
+pub use builtin::{
+   builtins,
+   to_json,
+};
+
+mod doc;
+pub use doc::{
+   to_html,
+   to_markdown,
+};
+
 mod code;
 pub use code::{
    ByteIndex,
@@ -10,7 +25,14 @@ pub use code::{
 };
 
 mod compiler;
-pub use compiler::CompileOracle;
+pub use compiler::{
+   Compile,
+   CompileOracle,
+};
+
+mod optimize;
+
+mod verify;
 
 mod scope;
 pub use scope::{
@@ -20,7 +42,18 @@ pub use scope::{
 };
 
 mod state;
-pub use state::State;
+pub use state::{
+   Budget,
+   Lock,
+   Reproducibility,
+   State,
+};
+
+mod profile;
+pub use profile::{
+   Profiler,
+   SpanUsage,
+};
 
 mod operation;
 pub use operation::{
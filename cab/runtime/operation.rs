@@ -25,14 +25,17 @@ pub enum Operation {
    Interpolate,
 
    Resolve,
+   ResolveFrom,
 
    AssertBoolean,
+   AssertShape,
 
    Construct,
 
    Call,
 
    Equal,
+   Document,
 
    All,
    Any,
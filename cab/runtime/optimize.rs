@@ -0,0 +1,574 @@
+use cab_util::suffix::Arc as _;
+use dup::Dupe as _;
+use ranged::Span;
+use rustc_hash::FxHashMap;
+
+use crate::{
+   Argument,
+   ByteIndex,
+   Code,
+   Operation,
+   Value,
+   value,
+};
+
+/// The passes that are run in order until none of them changes anything.
+const PASSES: &[fn(&mut Vec<Instruction>) -> bool] = &[
+   thread_jumps,
+   fold_constants,
+   remove_useless_jumps,
+   remove_push_pops,
+   remove_empty_scopes,
+   remove_unreachable,
+];
+
+enum Operand {
+   None,
+   Value(Value),
+   /// The index of the instruction to jump to. Can be the length of the
+   /// instructions, which is the end of the code.
+   Target(usize),
+   U64(u64),
+   U16(u16),
+}
+
+/// A decoded operation. Jumps refer to other instructions instead of bytes,
+/// so instructions can be removed without worrying about offsets.
+struct Instruction {
+   span:      Span,
+   operation: Operation,
+   operand:   Operand,
+}
+
+impl Instruction {
+   fn target(&self) -> Option<usize> {
+      if let Operand::Target(target) = self.operand {
+         Some(target)
+      } else {
+         None
+      }
+   }
+
+   /// Returns the value this instruction pushes if it is a push of a value
+   /// that is already in weak head normal form.
+   fn constant(&self) -> Option<&Value> {
+      match (self.operation, &self.operand) {
+         (Operation::Push, &Operand::Value(ref value))
+            if !matches!(
+               *value,
+               Value::Thunk(_) | Value::Thunkable(_) | Value::NeedsArgumentToThunk(_)
+            ) =>
+         {
+            Some(value)
+         },
+
+         _ => None,
+      }
+   }
+}
+
+impl Code {
+   /// Returns an optimized copy of the code. The codes of the thunks it
+   /// creates are optimized too.
+   ///
+   /// The optimized code evaluates to the same value as the original, and
+   /// every operation that is kept keeps its span.
+   #[must_use]
+   pub fn optimized(&self) -> Self {
+      let code = optimized(self);
+      code.verify_debug();
+      code
+   }
+}
+
+#[stacksafe::stacksafe]
+fn optimized(code: &Code) -> Code {
+   let mut instructions = decode(code);
+
+   while PASSES
+      .iter()
+      .fold(false, |changed, pass| pass(&mut instructions) | changed)
+   {}
+
+   encode(code.path().dupe(), instructions)
+}
+
+fn decode(code: &Code) -> Vec<Instruction> {
+   let mut instructions = Vec::new();
+   let mut positions = FxHashMap::<usize, usize>::default();
+
+   let mut index = ByteIndex::zero();
+   while let Some((operation, argument, next)) = code.read(index) {
+      positions.insert(*index, instructions.len());
+
+      let operand = match argument {
+         None => Operand::None,
+
+         Some(Argument::ValueIndex(value_index)) => {
+            Operand::Value(match code[value_index] {
+               Value::NeedsArgumentToThunk(ref code) => {
+                  Value::NeedsArgumentToThunk(optimized(code).arc())
+               },
+               Value::Thunkable(ref code) => Value::Thunkable(optimized(code).arc()),
+
+               ref value => value.dupe(),
+            })
+         },
+
+         Some(Argument::ByteIndex(target)) => Operand::Target(*target),
+
+         Some(Argument::U64(u64)) => Operand::U64(u64),
+
+         Some(Argument::U16(u16)) => Operand::U16(u16),
+      };
+
+      instructions.push(Instruction {
+         span: code.read_operation(index).0.span,
+         operation,
+         operand,
+      });
+
+      index = next;
+   }
+   positions.insert(*index, instructions.len());
+
+   for instruction in &mut instructions {
+      if let Operand::Target(ref mut target) = instruction.operand {
+         *target = *positions
+            .get(target)
+            .expect("jump target must be the start of an operation");
+      }
+   }
+
+   instructions
+}
+
+fn encode(path: value::Path, instructions: Vec<Instruction>) -> Code {
+   let mut code = Code::new(path);
+
+   let mut positions = Vec::with_capacity(instructions.len() + 1);
+   let mut jumps = Vec::new();
+
+   for instruction in instructions {
+      positions.push(code.push_operation(instruction.span, instruction.operation));
+
+      match instruction.operand {
+         Operand::None => {},
+
+         Operand::Value(value) => {
+            let index = code.value(value);
+            code.push_u64(*index as _);
+         },
+
+         Operand::Target(target) => {
+            jumps.push((code.push_u16(u16::default()), target));
+         },
+
+         Operand::U64(u64) => {
+            code.push_u64(u64);
+         },
+
+         Operand::U16(u16) => {
+            code.push_u16(u16);
+         },
+      }
+   }
+   positions.push(code.here());
+
+   for (index, target) in jumps {
+      code.point(index, positions[target]);
+   }
+
+   code
+}
+
+/// Returns whether each instruction is the target of a jump. Has an extra
+/// item for the end of the code.
+fn targets(instructions: &[Instruction]) -> Vec<bool> {
+   let mut targets = vec![false; instructions.len() + 1];
+
+   for target in instructions.iter().filter_map(Instruction::target) {
+      targets[target] = true;
+   }
+
+   targets
+}
+
+/// Removes the marked instructions. Jumps to removed instructions are pointed
+/// to the next instruction that is kept.
+fn remove(instructions: &mut Vec<Instruction>, removed: &[bool]) -> bool {
+   if !removed.contains(&true) {
+      return false;
+   }
+
+   let mut positions = Vec::with_capacity(instructions.len() + 1);
+   let mut kept = 0;
+   for &removed in removed {
+      positions.push(kept);
+
+      if !removed {
+         kept += 1;
+      }
+   }
+   positions.push(kept);
+
+   let mut removed = removed.iter();
+   instructions.retain_mut(|instruction| {
+      if let Operand::Target(ref mut target) = instruction.operand {
+         *target = positions[*target];
+      }
+
+      !removed
+         .next()
+         .expect("removed must have an item per instruction")
+   });
+
+   true
+}
+
+/// Points jumps that target unconditional jumps to their final targets.
+fn thread_jumps(instructions: &mut Vec<Instruction>) -> bool {
+   let mut changed = false;
+
+   for index in 0..instructions.len() {
+      let Some(target_original) = instructions[index].target() else {
+         continue;
+      };
+
+      let mut target = target_original;
+
+      // Bounded, so cycles of jumps don't loop forever.
+      for _ in 0..instructions.len() {
+         match instructions.get(target) {
+            Some(&Instruction {
+               operation: Operation::Jump,
+               operand: Operand::Target(next),
+               ..
+            }) if next != target => target = next,
+
+            _ => break,
+         }
+      }
+
+      if target != target_original {
+         instructions[index].operand = Operand::Target(target);
+         changed = true;
+      }
+   }
+
+   changed
+}
+
+/// Folds operations that act on a value pushed right before them.
+fn fold_constants(instructions: &mut Vec<Instruction>) -> bool {
+   enum Fold {
+      Remove,
+      Jump,
+   }
+
+   let targets = targets(instructions);
+   let mut removed = vec![false; instructions.len()];
+   let mut changed = false;
+
+   for index in 1..instructions.len() {
+      // Something else might jump here with another value.
+      if targets[index] {
+         continue;
+      }
+
+      let Some(constant) = instructions[index - 1].constant() else {
+         continue;
+      };
+
+      let fold = match (instructions[index].operation, constant) {
+         // Forcing a value that is already in weak head normal form does nothing.
+         (Operation::Force, _) => Fold::Remove,
+
+         (Operation::JumpIfError, value) if !matches!(*value, Value::Error(_)) => Fold::Remove,
+
+         (Operation::JumpIf, &Value::Boolean(false)) => Fold::Remove,
+         (Operation::JumpIf, &Value::Boolean(true)) => Fold::Jump,
+
+         _ => continue,
+      };
+
+      match fold {
+         Fold::Remove => removed[index] = true,
+         Fold::Jump => instructions[index].operation = Operation::Jump,
+      }
+
+      changed = true;
+   }
+
+   remove(instructions, &removed) | changed
+}
+
+/// Removes unconditional jumps to the next instruction.
+fn remove_useless_jumps(instructions: &mut Vec<Instruction>) -> bool {
+   let removed = instructions
+      .iter()
+      .enumerate()
+      .map(|(index, instruction)| {
+         instruction.operation == Operation::Jump && instruction.target() == Some(index + 1)
+      })
+      .collect::<Vec<_>>();
+
+   remove(instructions, &removed)
+}
+
+/// Removes pushes that are immediately popped.
+fn remove_push_pops(instructions: &mut Vec<Instruction>) -> bool {
+   let targets = targets(instructions);
+   let mut removed = vec![false; instructions.len()];
+
+   for index in 1..instructions.len() {
+      if instructions[index - 1].operation == Operation::Push
+         && instructions[index].operation == Operation::Pop
+         && !targets[index]
+      {
+         removed[index - 1] = true;
+         removed[index] = true;
+      }
+   }
+
+   remove(instructions, &removed)
+}
+
+/// Whether a reference in the code, or in the codes of the thunks it creates,
+/// skips the scope that is the given number of scopes out from where the code
+/// starts when resolving.
+#[stacksafe::stacksafe]
+fn skips_scope(code: &Code, depth: usize) -> bool {
+   let mut nested = 0_usize;
+
+   let mut index = ByteIndex::zero();
+   while let Some((operation, argument, next)) = code.read(index) {
+      match (operation, argument) {
+         (Operation::ScopeStart, _) => nested += 1,
+         (Operation::ScopeEnd, _) => nested = nested.saturating_sub(1),
+
+         (Operation::ResolveFrom, Some(Argument::U16(skip)))
+            if usize::from(skip) > nested + depth =>
+         {
+            return true;
+         },
+
+         (Operation::Push, Some(Argument::ValueIndex(value_index))) => {
+            if let Value::NeedsArgumentToThunk(ref code) | Value::Thunkable(ref code) =
+               code[value_index]
+               && skips_scope(code, nested + depth)
+            {
+               return true;
+            }
+         },
+
+         _ => {},
+      }
+
+      index = next;
+   }
+
+   false
+}
+
+/// Removes scopes that can never have anything bound in them.
+///
+/// A scope is kept if it contains anything that binds, reads the scope as
+/// attributes, captures it in a thunk or forces something, as forcing can
+/// propagate bindings into the scope. Scopes that are jumped into or out of
+/// are kept as well.
+///
+/// References that skip a known number of scopes when resolving keep the
+/// scopes they skip, as removing one would make them skip one too many.
+/// Thunks created in nested scopes don't keep the scope otherwise, as bindings
+/// only propagate into the innermost scope.
+fn remove_empty_scopes(instructions: &mut Vec<Instruction>) -> bool {
+   let mut removed = vec![false; instructions.len()];
+
+   for start in 0..instructions.len() {
+      if instructions[start].operation != Operation::ScopeStart {
+         continue;
+      }
+
+      let mut depth = 0_usize;
+      let mut end = None;
+
+      for (index, instruction) in instructions.iter().enumerate().skip(start + 1) {
+         match instruction.operation {
+            Operation::ScopeStart => depth += 1,
+
+            Operation::ScopeEnd if depth == 0 => {
+               end = Some(index);
+               break;
+            },
+            Operation::ScopeEnd => depth -= 1,
+
+            Operation::Equal
+            | Operation::Document
+            | Operation::ScopePush
+            | Operation::ScopeSwap
+            | Operation::Force
+               if depth == 0 =>
+            {
+               break;
+            },
+
+            Operation::Push if depth == 0 && instruction.constant().is_none() => break,
+
+            Operation::ResolveFrom
+               if let Operand::U16(skip) = instruction.operand
+                  && usize::from(skip) > depth =>
+            {
+               break;
+            },
+
+            Operation::Push => {
+               match instruction.operand {
+                  Operand::Value(
+                     Value::NeedsArgumentToThunk(ref code) | Value::Thunkable(ref code),
+                  ) if skips_scope(code, depth) => break,
+
+                  // The references of thunks that were already created can't
+                  // be seen.
+                  Operand::Value(Value::Thunk(_)) => break,
+
+                  _ => {},
+               }
+            },
+
+            _ => {},
+         }
+      }
+
+      let Some(end) = end else {
+         continue;
+      };
+
+      let inside = start + 1..=end;
+      let crosses = instructions.iter().enumerate().any(|(index, instruction)| {
+         instruction
+            .target()
+            .is_some_and(|target| inside.contains(&index) != inside.contains(&target))
+      });
+
+      if !crosses {
+         removed[start] = true;
+         removed[end] = true;
+      }
+   }
+
+   remove(instructions, &removed)
+}
+
+/// Removes instructions that can't be reached from the start of the code.
+fn remove_unreachable(instructions: &mut Vec<Instruction>) -> bool {
+   let mut reachable = vec![false; instructions.len() + 1];
+   let mut pending = vec![0];
+
+   while let Some(index) = pending.pop() {
+      if reachable[index] {
+         continue;
+      }
+      reachable[index] = true;
+
+      let Some(instruction) = instructions.get(index) else {
+         continue;
+      };
+
+      if let Some(target) = instruction.target() {
+         pending.push(target);
+      }
+
+      if instruction.operation != Operation::Jump {
+         pending.push(index + 1);
+      }
+   }
+
+   let removed = reachable[..instructions.len()]
+      .iter()
+      .map(|&reachable| !reachable)
+      .collect::<Vec<_>>();
+
+   remove(instructions, &removed)
+}
+
+#[cfg(test)]
+mod tests {
+   use ust::{
+      Display as _,
+      terminal,
+   };
+
+   use crate::{
+      CompileOracle,
+      builtin::{
+         compile,
+         evaluate_with,
+      },
+   };
+
+   /// Sources that must evaluate to the same value with and without
+   /// optimization.
+   const CORPUS: &[&str] = &[
+      "1",
+      "(((1)))",
+      "if true then 1 else 2",
+      "if false then 1 else (2)",
+      "if (true) then (if false then 1 else 2) else 3",
+      "if 1 then 1 else 2",
+      "true && (false || true)",
+      "false || (true && false)",
+      "[ 1, (2), if true then [ 3 ] else [] ]",
+      "{ @a = if b then 1 else 2; @b = false }",
+      "{ @a = 1; @b = { @c = a } }.b.c",
+      "(@x => if x then \"yes\" else \"no\") true",
+      "(@x => @y => [ y, x ]) 1 (2)",
+      "((@y => y)) 1",
+      "(@x => (([ x ]))) 1",
+      "undefined",
+      "if undefined then 1 else 2",
+      "toJSON { @a = [ 1, 2 ]; @b = \"x\" }",
+      "stringLength (if true then \"abc\" else 1)",
+      "mapAttrs (@name => @value => if value then name else 0) { @a = true; @b = false }",
+   ];
+
+   fn display(value: &crate::Value) -> String {
+      let mut displayed = String::new();
+
+      {
+         let writer = &mut terminal::writer(terminal::StyleChoice::Never, &mut displayed);
+         value
+            .display_styled(writer)
+            .expect("displaying to a string must not fail");
+      }
+
+      displayed
+   }
+
+   #[tokio::test]
+   async fn differential() {
+      for source in CORPUS {
+         assert_eq!(
+            display(&evaluate_with(source, false).await),
+            display(&evaluate_with(source, true).await),
+            "optimized code must evaluate to the same value as unoptimized code: {source}",
+         );
+      }
+   }
+
+   #[test]
+   fn removes_scopes_that_are_not_skipped() {
+      let scopes = |source| {
+         let (code, _) = compile(source, &CompileOracle::new(), true);
+
+         code.assembly().to_string().matches("ScopeStart").count()
+      };
+
+      // The lambda resolves its argument in its own scope, so the outer
+      // parenthesis is removed even though the lambda is created inside it.
+      assert_eq!(scopes("((@y => y))"), scopes("(@y => y)"));
+
+      // The reference skips both parentheses, so both are kept.
+      assert_eq!(scopes("@x => (([ x ]))"), scopes("@x => ([ x ])") + 1);
+   }
+}
@@ -0,0 +1,512 @@
+use std::{
+   fmt::{
+      self,
+      Write as _,
+   },
+   iter,
+   sync::{
+      Arc,
+      Mutex,
+   },
+   time::{
+      Duration,
+      Instant,
+   },
+};
+
+use dup::Dupe as _;
+use ranged::Span;
+use rustc_hash::FxHashMap;
+use ust::{
+   Display,
+   Write,
+   style::{
+      self,
+      StyledExt as _,
+   },
+   terminal,
+   with,
+   write,
+};
+
+use crate::{
+   Code,
+   value,
+};
+
+const EXPECT_LOCK: &str = "profiler lock must not be poisoned";
+
+/// The maximum amount of rows displayed by the [`Display`] implementation
+/// of [`Profiler`].
+const ROWS_MAX: usize = 32;
+
+const STYLE_HEADER: style::Style = style::Color::BrightBlack.fg().bold();
+const STYLE_TIME: style::Style = style::Color::Yellow.fg().bold();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Allocation {
+   Cons,
+   Attributes,
+}
+
+/// The resources accounted to a single source span of a single [`Code`].
+#[derive(Clone)]
+pub struct SpanUsage {
+   pub location: value::Location,
+
+   pub forces:     u64,
+   pub operations: u64,
+
+   pub allocations_cons:       u64,
+   pub allocations_attributes: u64,
+
+   /// The time spent executing operations of this span, excluding the time
+   /// spent forcing other thunks.
+   pub time: Duration,
+}
+
+impl SpanUsage {
+   fn new(location: value::Location) -> Self {
+      Self {
+         location,
+
+         forces:     0,
+         operations: 0,
+
+         allocations_cons:       0,
+         allocations_attributes: 0,
+
+         time: Duration::ZERO,
+      }
+   }
+}
+
+struct CodeUsage {
+   // Held so the address used as the key doesn't get reused for another code.
+   _code: Arc<Code>,
+   spans: FxHashMap<Span, SpanUsage>,
+}
+
+type Key = (usize, Span);
+
+struct Frame {
+   key:      Key,
+   started:  Instant,
+   children: Duration,
+}
+
+pub(crate) struct Tick {
+   key:      Key,
+   started:  Instant,
+   children: Duration,
+}
+
+#[derive(Default)]
+struct ProfilerInner {
+   codes:        Vec<CodeUsage>,
+   code_indices: FxHashMap<usize, usize>,
+
+   frames: Vec<Frame>,
+   folded: FxHashMap<Vec<Key>, Duration>,
+}
+
+impl ProfilerInner {
+   fn code_index(&mut self, code: &Arc<Code>) -> usize {
+      let address = Arc::as_ptr(code).addr();
+
+      if let Some(&index) = self.code_indices.get(&address) {
+         return index;
+      }
+
+      let index = self.codes.len();
+
+      self.codes.push(CodeUsage {
+         _code: code.dupe(),
+         spans: FxHashMap::default(),
+      });
+      self.code_indices.insert(address, index);
+
+      index
+   }
+
+   fn span(&mut self, code: &Arc<Code>, location: &value::Location) -> (Key, &mut SpanUsage) {
+      let code_index = self.code_index(code);
+
+      let profile = self.codes[code_index]
+         .spans
+         .entry(location.span)
+         .or_insert_with(|| SpanUsage::new(location.dupe()));
+
+      ((code_index, location.span), profile)
+   }
+
+   fn children(&self) -> Duration {
+      self
+         .frames
+         .last()
+         .map_or(Duration::ZERO, |frame| frame.children)
+   }
+
+   fn tock(&mut self, tick: Tick) {
+      let elapsed = tick.started.elapsed();
+      let children = self.children().saturating_sub(tick.children);
+
+      let (code_index, span) = tick.key;
+
+      self.codes[code_index]
+         .spans
+         .get_mut(&span)
+         .expect("ticked span must have a profile")
+         .time += elapsed.saturating_sub(children);
+   }
+
+   fn name(&self, (code_index, span): Key) -> String {
+      let location = &self.codes[code_index].spans[&span].location;
+
+      let mut name = String::new();
+
+      {
+         let writer = &mut terminal::writer(terminal::StyleChoice::Never, &mut name);
+         let _ = location.display_styled(writer);
+      }
+
+      // Semicolons separate frames in the folded format.
+      name.replace(';', ":")
+   }
+}
+
+/// An evaluation profiler. Counts forces, executed operations and
+/// allocations, and measures the time spent per source span of every
+/// [`Code`] that gets forced.
+///
+/// Native thunks are not accounted for.
+pub struct Profiler(Mutex<ProfilerInner>);
+
+impl Profiler {
+   #[must_use]
+   pub fn new() -> Self {
+      Self(Mutex::new(ProfilerInner::default()))
+   }
+
+   /// Returns the profiles of every span, hottest first.
+   #[must_use]
+   pub fn spans(&self) -> Vec<SpanUsage> {
+      let inner = self.0.lock().expect(EXPECT_LOCK);
+
+      let mut spans = inner
+         .codes
+         .iter()
+         .flat_map(|code| code.spans.values().cloned())
+         .collect::<Vec<_>>();
+
+      spans.sort_by_key(|span| span.time);
+      spans.reverse();
+      spans
+   }
+
+   /// Returns the collected stacks in the folded format, which is understood
+   /// by most flamegraph tools. The weight of each stack is in nanoseconds.
+   #[must_use]
+   pub fn folded(&self) -> String {
+      let inner = self.0.lock().expect(EXPECT_LOCK);
+
+      let mut names = FxHashMap::<Key, String>::default();
+
+      let mut lines = inner
+         .folded
+         .iter()
+         .map(|(stack, time)| {
+            let stack = stack
+               .iter()
+               .map(|&key| {
+                  names
+                     .entry(key)
+                     .or_insert_with(|| inner.name(key))
+                     .clone()
+               })
+               .collect::<Vec<_>>()
+               .join(";");
+
+            format!("{stack} {time}", time = time.as_nanos())
+         })
+         .collect::<Vec<_>>();
+
+      lines.sort_unstable();
+
+      let mut folded = String::new();
+      for line in lines {
+         folded.push_str(&line);
+         folded.push('\n');
+      }
+      folded
+   }
+
+   pub(crate) fn enter(&self, code: &Arc<Code>, location: &value::Location) {
+      let mut inner = self.0.lock().expect(EXPECT_LOCK);
+
+      let (key, profile) = inner.span(code, location);
+      profile.forces += 1;
+
+      inner.frames.push(Frame {
+         key,
+         started:  Instant::now(),
+         children: Duration::ZERO,
+      });
+   }
+
+   pub(crate) fn exit(&self, tick: &mut Option<Tick>) {
+      let mut inner = self.0.lock().expect(EXPECT_LOCK);
+
+      if let Some(tick) = tick.take() {
+         inner.tock(tick);
+      }
+
+      let frame = inner.frames.pop().expect("exit must be called after enter");
+      let elapsed = frame.started.elapsed();
+
+      if let Some(parent) = inner.frames.last_mut() {
+         parent.children += elapsed;
+      }
+
+      let stack = inner
+         .frames
+         .iter()
+         .map(|frame| frame.key)
+         .chain(iter::once(frame.key))
+         .collect::<Vec<_>>();
+
+      *inner.folded.entry(stack).or_default() += elapsed.saturating_sub(frame.children);
+   }
+
+   pub(crate) fn tick(&self, tick: &mut Option<Tick>, code: &Arc<Code>, location: &value::Location) {
+      let mut inner = self.0.lock().expect(EXPECT_LOCK);
+
+      if let Some(tick) = tick.take() {
+         inner.tock(tick);
+      }
+
+      let (key, profile) = inner.span(code, location);
+      profile.operations += 1;
+
+      let children = inner.children();
+
+      *tick = Some(Tick {
+         key,
+         started: Instant::now(),
+         children,
+      });
+   }
+
+   pub(crate) fn allocation(
+      &self,
+      code: &Arc<Code>,
+      location: &value::Location,
+      allocation: Allocation,
+   ) {
+      let mut inner = self.0.lock().expect(EXPECT_LOCK);
+
+      let (_, profile) = inner.span(code, location);
+
+      match allocation {
+         Allocation::Cons => profile.allocations_cons += 1,
+         Allocation::Attributes => profile.allocations_attributes += 1,
+      }
+   }
+}
+
+impl Display for Profiler {
+   fn display_styled(&self, writer: &mut dyn Write) -> fmt::Result {
+      let spans = self.spans();
+
+      let total = spans.iter().map(|span| span.time).sum::<Duration>();
+
+      with(writer, STYLE_HEADER, |writer| {
+         writeln!(
+            writer,
+            "{time:>12} {percent:>7} {forces:>8} {operations:>10} {cons:>8} {attributes:>10}  \
+             {location}",
+            time = "time",
+            percent = "%",
+            forces = "forces",
+            operations = "operations",
+            cons = "cons",
+            attributes = "attributes",
+            location = "location",
+         )
+      })?;
+
+      for span in spans.iter().take(ROWS_MAX) {
+         let time = format!("{time:?}", time = span.time);
+
+         let percent = if total.is_zero() {
+            0.0
+         } else {
+            span.time.as_secs_f64() / total.as_secs_f64() * 100.0
+         };
+
+         with(writer, STYLE_TIME, |writer| write!(writer, "{time:>12}"))?;
+
+         write!(
+            writer,
+            " {percent:>6.2}% {forces:>8} {operations:>10} {cons:>8} {attributes:>10}  ",
+            forces = span.forces,
+            operations = span.operations,
+            cons = span.allocations_cons,
+            attributes = span.allocations_attributes,
+         )?;
+
+         span.location.display_styled(writer)?;
+         writeln!(writer)?;
+      }
+
+      if spans.len() > ROWS_MAX {
+         with(writer, STYLE_HEADER, |writer| {
+            writeln!(
+               writer,
+               "{hidden} more spans not shown",
+               hidden = spans.len() - ROWS_MAX,
+            )
+         })?;
+      }
+
+      write(writer, &"total".bold())?;
+      writeln!(
+         writer,
+         ": {time:?} across {forces} forces and {operations} operations, allocated {cons} cons \
+          and {attributes} attributes",
+         time = total,
+         forces = spans.iter().map(|span| span.forces).sum::<u64>(),
+         operations = spans.iter().map(|span| span.operations).sum::<u64>(),
+         cons = spans.iter().map(|span| span.allocations_cons).sum::<u64>(),
+         attributes = spans
+            .iter()
+            .map(|span| span.allocations_attributes)
+            .sum::<u64>(),
+      )
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::{
+      CompileOracle,
+      State,
+      builtin::{
+         compile,
+         evaluate_code_in,
+      },
+   };
+
+   /// A lambda that calls another lambda every time it is called.
+   const SOURCE: &str =
+      "{ @id = @x => x; @check = @y => (id y) = 1; @result = [ check 1, check 2 ] }";
+
+   async fn profile(source: &str) -> Profiler {
+      let state = State {
+         profiler: Some(Profiler::new()),
+         ..State::new()
+      };
+
+      let (code, span) = compile(source, &CompileOracle::new(), false);
+      evaluate_code_in(code, span, &state).await;
+
+      state.profiler.expect("state must have a profiler")
+   }
+
+   /// Returns the spans that were forced and whose source starts with the
+   /// prefix.
+   fn forced<'a>(
+      spans: &'a [SpanUsage],
+      source: &'a str,
+      prefix: &'a str,
+   ) -> impl Iterator<Item = &'a SpanUsage> {
+      spans.iter().filter(move |span| {
+         span.forces > 0 && source[span.location.span.into_std()].starts_with(prefix)
+      })
+   }
+
+   /// Returns the name of the span in the folded format.
+   fn name(span: &SpanUsage) -> String {
+      let mut name = String::new();
+
+      {
+         let writer = &mut terminal::writer(terminal::StyleChoice::Never, &mut name);
+         span
+            .location
+            .display_styled(writer)
+            .expect("writing to a string must not fail");
+      }
+
+      name.replace(';', ":")
+   }
+
+   #[tokio::test]
+   async fn counts() {
+      let profiler = profile(SOURCE).await;
+      let spans = profiler.spans();
+
+      let forces = |prefix| {
+         forced(&spans, SOURCE, prefix)
+            .map(|span| span.forces)
+            .sum::<u64>()
+      };
+
+      assert_eq!(forces("@y =>"), 2);
+      assert_eq!(forces("@x =>"), 2);
+
+      assert_eq!(
+         spans.iter().map(|span| span.allocations_cons).sum::<u64>(),
+         2,
+      );
+      assert!(spans.iter().any(|span| span.operations > 0));
+   }
+
+   #[tokio::test]
+   async fn folded() {
+      let profiler = profile(SOURCE).await;
+      let spans = profiler.spans();
+
+      let name_of = |prefix| {
+         let mut names = forced(&spans, SOURCE, prefix).map(name).collect::<Vec<_>>();
+         names.sort_unstable();
+         names.dedup();
+
+         assert_eq!(names.len(), 1, "{prefix} must be a single span");
+         names.remove(0)
+      };
+
+      let check = name_of("@y =>");
+      let id = name_of("@x =>");
+
+      let folded = profiler.folded();
+      let stacks = folded
+         .lines()
+         .map(|line| {
+            let (stack, time) = line.rsplit_once(' ').expect("line must have a weight");
+            time.parse::<u128>().expect("weight must be nanoseconds");
+
+            stack.split(';').collect::<Vec<_>>()
+         })
+         .collect::<Vec<_>>();
+
+      // The body of id is only ever executed right inside the body of check.
+      let ids = stacks
+         .iter()
+         .filter(|stack| stack.last() == Some(&&*id))
+         .collect::<Vec<_>>();
+
+      assert!(!ids.is_empty(), "{folded}");
+      assert!(
+         ids.iter()
+            .all(|stack| stack.len() >= 2 && stack[stack.len() - 2] == check),
+         "{folded}",
+      );
+      assert!(
+         !stacks
+            .iter()
+            .any(|stack| stack.windows(2).any(|window| window == [&*id, &*check])),
+         "{folded}",
+      );
+   }
+}
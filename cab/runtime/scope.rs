@@ -1,7 +1,13 @@
-use std::sync::atomic;
+use std::sync::{
+   Arc,
+   Mutex,
+   atomic,
+};
 
+use cab_util::suffix::Arc as _;
 use dup::Dupe;
 use rpds::ListSync as List;
+use rustc_hash::FxHashSet;
 
 use crate::{
    Value,
@@ -9,6 +15,11 @@ use crate::{
 };
 
 const EXPECT_SCOPE: &str = "must have at least once scope";
+const EXPECT_LOCK: &str = "scope lock must not be poisoned";
+
+/// The thunks that captured a scope while it was open, or [`None`] once the
+/// scope is closed.
+type Captures = Arc<Mutex<Option<Vec<value::WeakThunk>>>>;
 
 #[derive(Clone, Copy, Dupe, PartialEq, Eq, Hash)]
 pub struct ScopeId(u64);
@@ -22,10 +33,37 @@ impl ScopeId {
    }
 }
 
+/// A set of bindings that identifiers resolve to.
+///
+/// Scopes are persistent. Inserting into a scope creates a new scope with the
+/// same identity, which is what thunks that capture the scope before the
+/// insertion keep seeing.
+///
+/// # Recursive Attributes
+///
+/// The bindings of an attribute body can refer to each other regardless of
+/// the order they are bound in, so `{ @a = b; @b = 1 }` evaluates to
+/// `{ @a = 1, @b = 1 }`.
+///
+/// While the body is evaluated, bindings get inserted into its scope one by
+/// one, so thunks created in the middle of the body only capture the bindings
+/// before them. Scopes are open from their start to their end, and record the
+/// thunks that capture them in the meantime. Once the body is done, the scope
+/// is sealed with the final attributes through [`Scopes::seal`], which gives
+/// every recorded thunk that wasn't forced yet the bindings it refers to.
+///
+/// Forcing a binding that comes later in the body before the body is done is
+/// an undefined value error, as the scope isn't sealed yet.
+///
+/// Thunks only get the bindings their code refers to, so the attributes of a
+/// body don't keep themselves alive unless their bindings refer to each other
+/// in a cycle. Thunks that refer to names only known at runtime get every
+/// binding.
 #[derive(Clone, Dupe)]
 pub struct Scope {
    id:         ScopeId,
    attributes: value::Attributes,
+   captures:   Option<Captures>,
 }
 
 impl From<&value::Attributes> for Scope {
@@ -33,6 +71,7 @@ impl From<&value::Attributes> for Scope {
       Self {
          id:         ScopeId::new(),
          attributes: attributes.dupe(),
+         captures:   None,
       }
    }
 }
@@ -40,10 +79,7 @@ impl From<&value::Attributes> for Scope {
 impl Scope {
    #[must_use]
    pub fn new() -> Self {
-      Self {
-         id:         ScopeId::new(),
-         attributes: value::attributes::new! {},
-      }
+      Self::from(&value::attributes::new! {})
    }
 
    #[must_use]
@@ -56,20 +92,94 @@ impl Scope {
       &self.attributes
    }
 
+   #[must_use]
+   pub fn get(&self, key: &value::SString) -> Option<&Value> {
+      self.attributes.get(key)
+   }
+
    #[must_use]
    pub fn insert(&self, key: value::SString, value: Value) -> Self {
+      self.with_attributes(self.attributes.insert(key, value))
+   }
+
+   #[must_use]
+   pub fn merge(&self, with: &value::Attributes) -> Self {
+      self.with_attributes(self.attributes.merge(with))
+   }
+
+   /// Returns the scope with its attributes replaced, keeping its identity.
+   #[must_use]
+   pub fn with_attributes(&self, attributes: value::Attributes) -> Self {
       Self {
-         id:         self.id,
-         attributes: self.attributes.insert(key, value),
+         id: self.id,
+         attributes,
+         captures: self.captures.dupe(),
       }
    }
 
+   /// Creates an empty scope that records the thunks that capture it until it
+   /// is closed. See the [`Scope`] documentation.
    #[must_use]
-   pub fn merge(&self, with: &value::Attributes) -> Self {
+   pub fn open() -> Self {
       Self {
-         id:         self.id,
-         attributes: self.attributes.merge(with),
+         captures: Some(Mutex::new(Some(Vec::new())).arc()),
+         ..Self::new()
+      }
+   }
+
+   /// Records the thunk if the scope is open.
+   fn capture(&self, thunk: &value::WeakThunk) {
+      let Some(ref captures) = self.captures else {
+         return;
+      };
+
+      let mut captures = captures.lock().expect(EXPECT_LOCK);
+      let Some(ref mut thunks) = *captures else {
+         return;
+      };
+
+      // Forget the thunks that were freed before growing, so scopes that are
+      // open for long don't grow without bound.
+      if thunks.len() == thunks.capacity() {
+         thunks.retain(value::WeakThunk::is_alive);
       }
+
+      thunks.push(thunk.dupe());
+   }
+
+   /// Closes the scope, returning the thunks that captured it while it was
+   /// open. Returns nothing if the scope was already closed.
+   pub fn close(&self) -> Vec<value::WeakThunk> {
+      self
+         .captures
+         .as_ref()
+         .and_then(|captures| captures.lock().expect(EXPECT_LOCK).take())
+         .unwrap_or_default()
+   }
+
+   /// Returns the copy of the scope with the bindings of the sealed attributes
+   /// it doesn't have yet. If the names the copy is looked up with are known,
+   /// only those are kept.
+   #[must_use]
+   fn sealed(
+      &self,
+      with: &value::Attributes,
+      names: Option<&FxHashSet<value::SString>>,
+   ) -> Self {
+      let Some(names) = names else {
+         return self.with_attributes(with.merge(&self.attributes));
+      };
+
+      let attributes = names
+         .iter()
+         .fold(value::attributes::new! {}, |attributes, name| {
+            match self.attributes.get(name).or_else(|| with.get(name)) {
+               Some(value) => attributes.insert(name.dupe(), value.dupe()),
+               None => attributes,
+            }
+         });
+
+      self.with_attributes(attributes)
    }
 }
 
@@ -93,7 +203,19 @@ impl Scopes {
 
    #[must_use]
    pub fn get(&self, key: &value::SString) -> Option<&Value> {
-      self.iter().find_map(|scope| scope.attributes.get(key))
+      self.get_from(0, key)
+   }
+
+   /// Looks the key up starting from the scope that is `depth` scopes away from
+   /// the tip. The compiler uses this to skip scopes it knows can't bind the
+   /// key.
+   ///
+   /// The remaining scopes are still searched by name. Patterns bind into
+   /// scopes at runtime and sealing rebuilds them by name, so bindings don't
+   /// have slots that are known at compile time.
+   #[must_use]
+   pub fn get_from(&self, depth: usize, key: &value::SString) -> Option<&Value> {
+      self.iter().skip(depth).find_map(|scope| scope.get(key))
    }
 
    #[must_use]
@@ -106,13 +228,45 @@ impl Scopes {
       self.0.drop_first().map(Self)
    }
 
+   /// Records the thunk in the open scopes, so it gets the bindings that come
+   /// after it once they are sealed.
+   pub fn capture(&self, thunk: &value::WeakThunk) {
+      for scope in self.iter() {
+         scope.capture(thunk);
+      }
+   }
+
+   /// Returns the scopes with the copy of the sealed scope replaced by its
+   /// sealed version, restricted to the names if they are known. See the
+   /// [`Scope`] documentation.
+   #[must_use]
+   pub fn seal(&self, scope: &Scope, names: Option<&FxHashSet<value::SString>>) -> Self {
+      let mut copies = Vec::new();
+      let mut rest = self.dupe();
+
+      while let Some(copy) = rest.tip() {
+         if copy.id == scope.id {
+            let sealed = copy.sealed(&scope.attributes, names);
+
+            return copies.into_iter().rev().fold(
+               rest.pop().expect(EXPECT_SCOPE).push(sealed),
+               |scopes, copy| scopes.push(copy),
+            );
+         }
+
+         copies.push(copy.dupe());
+         rest = rest.pop().expect(EXPECT_SCOPE);
+      }
+
+      self.dupe()
+   }
+
+   /// Returns the scopes with the attributes merged into the tip.
    #[must_use]
-   pub fn merge_tip_from(&self, with: &Self) -> Self {
-      self.pop().expect(EXPECT_SCOPE).push(
-         self
-            .tip()
-            .expect(EXPECT_SCOPE)
-            .merge(with.tip().expect(EXPECT_SCOPE).attributes()),
-      )
+   pub fn merge_tip(&self, with: &value::Attributes) -> Self {
+      self
+         .pop()
+         .expect(EXPECT_SCOPE)
+         .push(self.tip().expect(EXPECT_SCOPE).merge(with))
    }
 }
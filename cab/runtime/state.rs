@@ -1,8 +1,169 @@
+use std::{
+   collections::BTreeMap,
+   path::PathBuf,
+   sync::{
+      Arc,
+      Mutex,
+      atomic::{
+         self,
+         AtomicU64,
+      },
+   },
+};
+
 use cab_syntax::ParseOracle;
+use cyn::ResultExt as _;
+use dup::Dupe;
+
+use crate::{
+   CompileOracle,
+   Profiler,
+};
 
-use crate::CompileOracle;
+const EXPECT_LOCK: &str = "lock must not be poisoned";
 
 pub struct State {
    pub parse_oracle:   ParseOracle,
    pub compile_oracle: CompileOracle,
+
+   /// The profiler to account evaluation resources to, if any.
+   pub profiler: Option<Profiler>,
+
+   /// The limit on the operations to execute, if any.
+   pub budget: Option<Budget>,
+
+   /// The directory to cache the outputs of commands in, if any. Commands
+   /// can't be run without one.
+   pub cache: Option<PathBuf>,
+
+   /// How builtins that read the environment of the evaluation behave.
+   pub reproducibility: Reproducibility,
+}
+
+impl State {
+   /// Returns a pure state without a profiler, a budget or a cache. Set the
+   /// fields to configure it further.
+   #[must_use]
+   pub fn new() -> Self {
+      Self {
+         parse_oracle:   ParseOracle::new(),
+         compile_oracle: CompileOracle::new(),
+
+         profiler: None,
+         budget:   None,
+         cache:    None,
+
+         reproducibility: Reproducibility::pure(),
+      }
+   }
+}
+
+/// How builtins that read the environment of the evaluation, like `getEnv` and
+/// `currentTime`, behave.
+#[derive(Clone)]
+pub enum Reproducibility {
+   /// They return the values recorded in the lock, and fail for the ones that
+   /// aren't recorded. The evaluation only depends on its sources and the
+   /// lock.
+   Pure(Lock),
+
+   /// They read the environment, and record what they read in the lock.
+   Impure {
+      lock:      Lock,
+      /// The arguments `args` returns.
+      arguments: Vec<String>,
+   },
+}
+
+impl Reproducibility {
+   /// Returns the pure reproducibility with an empty lock, where every
+   /// builtin that reads the environment fails.
+   #[must_use]
+   pub fn pure() -> Self {
+      Self::Pure(Lock::new())
+   }
+
+   /// Returns the lock values are returned from or recorded to.
+   #[must_use]
+   pub fn lock(&self) -> &Lock {
+      match *self {
+         Self::Pure(ref lock) | Self::Impure { ref lock, .. } => lock,
+      }
+   }
+}
+
+/// The values builtins that read the environment returned, by the builtin and
+/// what it read, like `getEnv HOME`.
+///
+/// Locks are stored as JSON objects sorted by key, so changes to the inputs of
+/// an evaluation show up in diffs of the lock. Copies of a lock share the same
+/// values.
+#[derive(Default, Clone, Dupe)]
+pub struct Lock(Arc<Mutex<BTreeMap<String, serde_json::Value>>>);
+
+impl Lock {
+   #[must_use]
+   pub fn new() -> Self {
+      Self(Arc::new(Mutex::new(BTreeMap::new())))
+   }
+
+   pub fn from_json(json: &str) -> cyn::Result<Self> {
+      let values = serde_json::from_str(json).chain_err("invalid lock")?;
+
+      Ok(Self(Arc::new(Mutex::new(values))))
+   }
+
+   #[must_use]
+   pub fn to_json(&self) -> String {
+      let values = self.0.lock().expect(EXPECT_LOCK);
+
+      let mut json = serde_json::to_string_pretty(&*values).expect("lock values must serialize");
+      json.push('\n');
+      json
+   }
+
+   /// Returns the value recorded for the key, if any.
+   #[must_use]
+   pub fn get(&self, key: &str) -> Option<serde_json::Value> {
+      self.0.lock().expect(EXPECT_LOCK).get(key).cloned()
+   }
+
+   /// Records the value for the key, replacing any previous value.
+   pub fn record(&self, key: &str, value: serde_json::Value) {
+      self
+         .0
+         .lock()
+         .expect(EXPECT_LOCK)
+         .insert(key.to_owned(), value);
+   }
+}
+
+/// A limit on the number of operations an evaluation can execute. Once it is
+/// spent, every thunk that is being forced evaluates to an error instead of
+/// executing further.
+pub struct Budget(AtomicU64);
+
+impl Budget {
+   #[must_use]
+   pub fn new(operations: u64) -> Self {
+      Self(AtomicU64::new(operations))
+   }
+
+   /// Spends a single operation. Returns whether there was one left to spend.
+   pub fn spend(&self) -> bool {
+      self
+         .0
+         .fetch_update(
+            atomic::Ordering::Relaxed,
+            atomic::Ordering::Relaxed,
+            |left| left.checked_sub(1),
+         )
+         .is_ok()
+   }
+
+   /// Returns whether every operation was spent.
+   #[must_use]
+   pub fn is_spent(&self) -> bool {
+      self.0.load(atomic::Ordering::Relaxed) == 0
+   }
 }
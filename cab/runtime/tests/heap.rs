@@ -0,0 +1,87 @@
+//! The heap counts are global to the process, so the tests that compare them
+//! live in their own test binary, away from the tests that allocate values
+//! concurrently.
+
+use std::fmt::Write as _;
+
+use cab_runtime::{
+   self as runtime,
+   Value,
+   value,
+};
+use cab_syntax as syntax;
+use cab_util::suffix::Arc as _;
+use dup::Dupe as _;
+use ranged::Span;
+use rpds::ListSync as List;
+
+/// Returns a configuration of services that refer to the ones that are bound
+/// after them.
+fn config(len: usize) -> String {
+   let mut source = String::from("{ @first = service0;");
+
+   for index in 0..len {
+      write!(
+         source,
+         " @service{index} = {{ @port = {index}; @tags = [ \"a\", \"b\" ]; @next = {next} }};",
+         next = if index + 1 == len {
+            "null".to_owned()
+         } else {
+            format!("service{next}", next = index + 1)
+         },
+      )
+      .expect("writing to a string must not fail");
+   }
+
+   source.push_str(" }");
+   source
+}
+
+/// Evaluates the source, forcing it deeply if asked to, and returns what the
+/// result keeps alive.
+async fn evaluate(source: &str, deep: bool) -> value::Heap {
+   let state = runtime::State::new();
+
+   let parse = state.parse_oracle.parse(syntax::tokenize(source));
+   assert!(parse.reports.is_empty(), "source must parse cleanly");
+
+   let lower = syntax::LowerOracle::new().lower(parse.expression.as_ref());
+   assert!(lower.reports.is_empty(), "source must lower cleanly");
+
+   let path = value::Path::rootless(List::new_sync());
+   let code = state
+      .compile_oracle
+      .compile(lower.expression())
+      .path(path.dupe())
+      .code
+      .optimized();
+
+   let thunk = value::Thunk::forceable(code.arc())
+      .scopes(runtime::Scopes::new().push(runtime::Scope::from(&runtime::builtins())))
+      .location(value::Location::new(path, Span::at(0_u32, source.len())));
+
+   let value = if deep {
+      Value::from(thunk).forced_deep(&state).await
+   } else {
+      Value::from(thunk).forced(&state).await
+   };
+
+   assert!(
+      matches!(value, Value::Attributes(_)),
+      "configuration must evaluate to attributes",
+   );
+
+   value.heap()
+}
+
+#[tokio::test]
+async fn evaluations_free_everything() {
+   let baseline = value::Heap::live();
+
+   for deep in [false, true] {
+      let heap = evaluate(&config(1000), deep).await;
+      assert!(heap.thunks + heap.attributes >= 1000, "{heap:?}");
+
+      assert_eq!(value::Heap::live(), baseline, "deep: {deep}");
+   }
+}
@@ -1,5 +1,10 @@
 #![allow(dead_code)]
 
+use std::{
+   sync::Arc,
+   vec,
+};
+
 use dup::Dupe;
 use rpds::HashTrieMapSync as HashTrieMap;
 use rustc_hash::FxBuildHasher;
@@ -12,13 +17,30 @@ use ust::{
 use super::Value;
 use crate::value;
 
+/// A set of named values, along with the documentation of some of them.
+///
+/// Documentation is metadata. It is kept when entries are inserted or merged,
+/// but is not a part of the value otherwise.
 #[derive(Clone, Dupe)]
-pub struct Attributes(#[doc(hidden)] pub HashTrieMap<value::SString, Value, FxBuildHasher>);
+pub struct Attributes(
+   #[doc(hidden)] pub HashTrieMap<value::SString, Value, FxBuildHasher>,
+   #[doc(hidden)] pub HashTrieMap<value::SString, value::SString, FxBuildHasher>,
+   #[doc(hidden)] pub Arc<Live>,
+);
+
+type Live = value::heap::Live<{ value::heap::ATTRIBUTES }>;
 
 #[doc(hidden)]
 pub mod private {
+   use std::sync::Arc;
+
    pub use rpds::HashTrieMapSync as HashTrieMap;
    pub use rustc_hash::FxBuildHasher;
+
+   #[must_use]
+   pub fn live() -> Arc<super::Live> {
+      Arc::default()
+   }
 }
 
 #[macro_export]
@@ -28,7 +50,11 @@ macro_rules! __attributes_new {
       $crate::value::Attributes(
          $crate::value::attributes::private::HashTrieMap::new_with_hasher_and_ptr_kind(
             $crate::value::attributes::private::FxBuildHasher
-         )
+         ),
+         $crate::value::attributes::private::HashTrieMap::new_with_hasher_and_ptr_kind(
+            $crate::value::attributes::private::FxBuildHasher
+         ),
+         $crate::value::attributes::private::live(),
       )
          $(.insert($crate::value::string::new!($key), $value))*
    }
@@ -61,14 +87,11 @@ impl tag::DisplayTags for Attributes {
          tags.write_if(Newline(1), Broken);
 
          tags.write_if_with(Indent(INDENT_WIDTH), Broken, |tags| {
-            let mut entries = self.0.iter().collect::<Vec<_>>();
-            entries.sort_by_key(|&(name, _)| name);
-
-            if !entries.is_empty() {
+            if !self.0.is_empty() {
                tags.write_if(Space, Flat);
             }
 
-            let mut entries = entries.into_iter().peekable();
+            let mut entries = self.iter().peekable();
             while let Some((name, value)) = entries.next() {
                tags.write("@".style(value::STYLE_BIND_AT));
                tags.write((***name).style(value::STYLE_BIND));
@@ -99,15 +122,42 @@ impl tag::DisplayTags for Attributes {
    }
 }
 
+impl<'a> IntoIterator for &'a Attributes {
+   type Item = (&'a value::SString, &'a Value);
+   type IntoIter = vec::IntoIter<Self::Item>;
+
+   fn into_iter(self) -> Self::IntoIter {
+      let mut entries = self.0.iter().collect::<Vec<_>>();
+      entries.sort_by_key(|&(name, _)| name);
+      entries.into_iter()
+   }
+}
+
 impl Attributes {
    #[must_use]
    pub fn insert(&self, key: value::SString, value: Value) -> Self {
-      Self(self.0.insert(key, value))
+      Self(self.0.insert(key, value), self.1.dupe(), private::live())
    }
 
    #[must_use]
    pub fn remove(&self, key: &value::SString) -> Self {
-      Self(self.0.remove(key))
+      Self(self.0.remove(key), self.1.remove(key), private::live())
+   }
+
+   /// Returns the attributes with the documentation of the key set. The key
+   /// doesn't need to have a value yet.
+   #[must_use]
+   pub fn document(&self, key: value::SString, documentation: value::SString) -> Self {
+      Self(
+         self.0.dupe(),
+         self.1.insert(key, documentation),
+         private::live(),
+      )
+   }
+
+   #[must_use]
+   pub fn documentation(&self, key: &value::SString) -> Option<&value::SString> {
+      self.1.get(key)
    }
 
    #[must_use]
@@ -120,6 +170,13 @@ impl Attributes {
             .fold(self.0.dupe(), |attributes, (key, value)| {
                attributes.insert(key.dupe(), value.dupe())
             }),
+         with
+            .1
+            .into_iter()
+            .fold(self.1.dupe(), |documentations, (key, documentation)| {
+               documentations.insert(key.dupe(), documentation.dupe())
+            }),
+         private::live(),
       )
    }
 
@@ -128,8 +185,27 @@ impl Attributes {
       self.0.get(key)
    }
 
+   /// Returns the address of this version of the attributes, which identifies
+   /// it while it is alive.
    #[must_use]
-   pub fn equals(_left: &Self, _right: &Self) -> (bool, Attributes) {
-      todo!()
+   pub(super) fn address(&self) -> usize {
+      Arc::as_ptr(&self.2).addr()
+   }
+
+   #[must_use]
+   pub fn len(&self) -> usize {
+      self.0.size()
+   }
+
+   #[must_use]
+   pub fn is_empty(&self) -> bool {
+      self.0.is_empty()
+   }
+
+   /// Iterates over the entries, sorted by name. This is the order attributes
+   /// are displayed and converted in, which doesn't depend on the hashes of
+   /// the names.
+   pub fn iter(&self) -> vec::IntoIter<(&value::SString, &Value)> {
+      self.into_iter()
    }
 }
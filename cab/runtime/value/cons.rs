@@ -1,3 +1,8 @@
+use std::{
+   mem,
+   sync::Arc,
+};
+
 use dup::Dupe;
 use ust::{
    style::StyledExt as _,
@@ -10,7 +15,30 @@ use crate::{
 };
 
 #[derive(Clone, Dupe)]
-pub struct Cons(pub Value, pub Value);
+pub struct Cons(pub Value, pub Value, value::heap::Live<{ value::heap::CONS }>);
+
+impl Cons {
+   #[must_use]
+   pub fn new(head: Value, tail: Value) -> Self {
+      Self(head, tail, value::heap::Live::default())
+   }
+}
+
+impl Drop for Cons {
+   fn drop(&mut self) {
+      // The drop glue would recurse once per item, which overflows the stack
+      // for long lists. So take the tails we own out one by one instead.
+      let mut tail = mem::replace(&mut self.1, Value::from(Nil));
+
+      while let Value::Cons(cons) = tail {
+         let Some(mut cons) = Arc::into_inner(cons) else {
+            break;
+         };
+
+         tail = mem::replace(&mut cons.1, Value::from(Nil));
+      }
+   }
+}
 
 impl tag::DisplayTags for Cons {
    fn display_tags<'a>(&'a self, tags: &mut tag::Tags<'a>) {
@@ -26,7 +54,7 @@ impl tag::DisplayTags for Cons {
          },
       };
 
-      let &Cons(ref head, ref tail) = self;
+      let &Cons(ref head, ref tail, ..) = self;
 
       tags.write_with(Group(40), |tags| {
          head.display_tags(tags);
@@ -45,8 +73,8 @@ impl tag::DisplayTags for Cons {
 impl From<Cons> for value::Attributes {
    fn from(cons: Cons) -> Self {
       value::attributes::new! {
-         "fst": cons.0,
-         "snd": cons.1,
+         "fst": cons.0.dupe(),
+         "snd": cons.1.dupe(),
       }
    }
 }
@@ -57,7 +85,7 @@ impl TryFrom<value::Attributes> for Cons {
    fn try_from(attrs: value::Attributes) -> Result<Self, Self::Error> {
       let fst = attrs.get(&value::string::new!("fst")).ok_or(())?;
       let snd = attrs.get(&value::string::new!("snd")).ok_or(())?;
-      Ok(Cons(fst.dupe(), snd.dupe()))
+      Ok(Cons::new(fst.dupe(), snd.dupe()))
    }
 }
 
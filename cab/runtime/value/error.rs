@@ -32,7 +32,7 @@ impl tag::DisplayTags for Error {
          let Ok(cons) = TryInto::<Arc<value::Cons>>::try_into(head.dupe()) else {
             break head;
          };
-         let &value::Cons(ref item, ref tail) = &*cons;
+         let &value::Cons(ref item, ref tail, ..) = &*cons;
          head = tail.dupe();
 
          let Ok(location) = TryInto::<value::Location>::try_into(item.dupe()) else {
@@ -75,10 +75,20 @@ impl Error {
       }
    }
 
+   /// Creates an error for a value that isn't of the expected kind, naming both
+   /// kinds.
+   #[must_use]
+   pub fn expected(kind: &str, actual: &Value) -> Self {
+      Self::new(value::SString::from(&*format!(
+         "expected {kind}, got {actual}",
+         actual = actual.kind(),
+      )))
+   }
+
    #[must_use]
    pub fn append_trace(&self, location: value::Location) -> Self {
       Self {
-         trace: Value::from(value::Cons(Value::from(location), self.trace.dupe()).arc()),
+         trace: Value::from(value::Cons::new(Value::from(location), self.trace.dupe()).arc()),
          value: self.value.dupe(),
       }
    }
@@ -0,0 +1,197 @@
+//! Statistics of the heap objects values keep alive.
+
+use std::{
+   collections::HashSet,
+   sync::{
+      Arc,
+      atomic,
+   },
+};
+
+use dup::Dupe;
+
+use crate::{
+   Scopes,
+   Value,
+   value,
+};
+
+pub const THUNK: usize = 0;
+pub const ATTRIBUTES: usize = 1;
+pub const CONS: usize = 2;
+
+static LIVE: [atomic::AtomicUsize; 3] = [const { atomic::AtomicUsize::new(0) }; 3];
+
+/// Counts an object of the kind as alive until it is dropped. Clones are
+/// counted as new objects.
+#[doc(hidden)]
+pub struct Live<const KIND: usize>;
+
+impl<const KIND: usize> Default for Live<KIND> {
+   fn default() -> Self {
+      LIVE[KIND].fetch_add(1, atomic::Ordering::Relaxed);
+      Self
+   }
+}
+
+impl<const KIND: usize> Clone for Live<KIND> {
+   fn clone(&self) -> Self {
+      Self::default()
+   }
+}
+
+impl<const KIND: usize> Dupe for Live<KIND> {}
+
+impl<const KIND: usize> Drop for Live<KIND> {
+   fn drop(&mut self) {
+      LIVE[KIND].fetch_sub(1, atomic::Ordering::Relaxed);
+   }
+}
+
+/// An amount of heap objects.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Heap {
+   /// The thunks, including the ones that are evaluated.
+   pub thunks:     usize,
+   /// The attribute maps, including the scopes thunks capture. Inserting into
+   /// attributes creates a new version of them that shares most of the old
+   /// one, and every version is counted.
+   pub attributes: usize,
+   /// The cons cells.
+   pub cons:       usize,
+}
+
+impl Heap {
+   /// Returns the heap objects that are alive in the whole process, no matter
+   /// what keeps them alive. Objects that are leaked through reference cycles
+   /// are counted too.
+   #[must_use]
+   pub fn live() -> Self {
+      Self {
+         thunks:     LIVE[THUNK].load(atomic::Ordering::Relaxed),
+         attributes: LIVE[ATTRIBUTES].load(atomic::Ordering::Relaxed),
+         cons:       LIVE[CONS].load(atomic::Ordering::Relaxed),
+      }
+   }
+}
+
+/// A walk over everything a value keeps alive.
+pub(super) struct Walk {
+   heap:   Heap,
+   /// The addresses of the thunks, attribute versions and cons cells that
+   /// were reached.
+   seen:   HashSet<usize>,
+   values: Vec<Value>,
+}
+
+impl Walk {
+   /// Queues the value to be walked.
+   pub(super) fn value(&mut self, value: &Value) {
+      self.values.push(value.dupe());
+   }
+
+   /// Counts the attributes and queues their values to be walked, unless the
+   /// same version of them was reached before.
+   pub(super) fn attributes(&mut self, attributes: &value::Attributes) {
+      if !self.seen.insert(attributes.address()) {
+         return;
+      }
+
+      self.heap.attributes += 1;
+
+      for (_, value) in attributes {
+         self.value(value);
+      }
+   }
+
+   /// Walks the attributes of the scopes.
+   pub(super) fn scopes(&mut self, scopes: &Scopes) {
+      for scope in scopes.iter() {
+         self.attributes(scope.attributes());
+      }
+   }
+}
+
+impl Value {
+   /// Returns the heap objects this value keeps alive, each counted once no
+   /// matter how many times it is reachable. Thunks that are being forced are
+   /// counted, but not walked into, as their state is held by the evaluation
+   /// forcing them.
+   #[must_use]
+   pub fn heap(&self) -> Heap {
+      let mut walk = Walk {
+         heap:   Heap::default(),
+         seen:   HashSet::new(),
+         values: vec![self.dupe()],
+      };
+
+      while let Some(value) = walk.values.pop() {
+         match value {
+            Value::Thunk(ref thunk) => {
+               if walk.seen.insert(thunk.address()) {
+                  walk.heap.thunks += 1;
+                  thunk.walk(&mut walk);
+               }
+            },
+
+            Value::Cons(ref cons) => {
+               if walk.seen.insert(Arc::as_ptr(cons).addr()) {
+                  walk.heap.cons += 1;
+                  walk.value(&cons.0);
+                  walk.value(&cons.1);
+               }
+            },
+
+            Value::Attributes(ref attributes) => walk.attributes(attributes),
+
+            _ => {},
+         }
+      }
+
+      walk.heap
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use cab_util::suffix::Arc as _;
+
+   use super::*;
+   use crate::builtin::evaluate_with;
+
+   #[tokio::test]
+   async fn counts() {
+      assert_eq!(
+         evaluate_with("[ 1, 2, 3 ]", true).await.heap(),
+         Heap {
+            thunks:     0,
+            attributes: 0,
+            cons:       3,
+         },
+      );
+
+      // Shared cells are counted once.
+      let list = evaluate_with("[ 1, 2 ]", true).await;
+      let Value::Cons(ref cons) = list else {
+         panic!("list must be a cons");
+      };
+
+      assert_eq!(
+         Value::from(value::Cons::new(list.dupe(), cons.1.dupe()).arc()).heap(),
+         Heap {
+            thunks:     0,
+            attributes: 0,
+            cons:       3,
+         },
+      );
+
+      assert_eq!(
+         evaluate_with("{ @a = { @b = [ 1 ] } }", true).await.heap(),
+         Heap {
+            thunks:     0,
+            attributes: 2,
+            cons:       1,
+         },
+      );
+   }
+}
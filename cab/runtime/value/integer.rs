@@ -1,6 +1,7 @@
 use std::sync::Arc;
 
 use cab_util::suffix::Arc as _;
+use derive_more::Deref;
 use dup::Dupe;
 use ust::{
    style::StyledExt as _,
@@ -12,7 +13,7 @@ use crate::{
    value,
 };
 
-#[derive(Clone, Dupe)]
+#[derive(Deref, Clone, Dupe)]
 pub struct Integer(Arc<num::BigInt>);
 
 impl tag::DisplayTags for Integer {
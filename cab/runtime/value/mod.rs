@@ -3,7 +3,10 @@ use std::{
    sync::Arc,
 };
 
-use cab_syntax::token;
+use cab_syntax::{
+   lode,
+   token,
+};
 use cab_util::suffix::Arc as _;
 use derive_more::{
    From,
@@ -32,6 +35,9 @@ pub use cons::{
 pub mod error;
 pub use error::Error;
 
+mod heap;
+pub use heap::Heap;
+
 pub mod integer;
 pub use integer::Integer;
 
@@ -41,11 +47,24 @@ pub use path::Path;
 pub mod location;
 pub use location::Location;
 
+mod null;
+pub use null::Null;
+
+pub mod serial;
+pub use serial::{
+   from_value,
+   to_value,
+};
+
 pub mod string;
 pub use string::SString;
 
 mod thunk;
-pub use thunk::Thunk;
+pub use thunk::{
+   NativeFuture,
+   Thunk,
+   WeakThunk,
+};
 
 #[warn(variant_size_differences)]
 #[derive(Clone, Dupe, From, TryInto)]
@@ -55,6 +74,8 @@ pub enum Value {
 
    Boolean(bool),
 
+   Null(Null),
+
    Cons(Arc<Cons>),
    Nil(Nil),
 
@@ -117,6 +138,8 @@ impl tag::DisplayTags for Value {
          Value::Boolean(true) => tags.write("true".magenta().bold()),
          Value::Boolean(false) => tags.write("false".magenta().bold()),
 
+         Value::Null(Null) => tags.write("null".magenta().bold()),
+
          Value::Nil(Nil) => tags.write("[]".style(STYLE_PUNCTUATION)),
          Value::Cons(ref cons) => cons.display_tags(tags),
 
@@ -188,6 +211,77 @@ impl From<&Value> for Attributes {
 }
 
 impl Value {
+   /// Returns the name of the kind of this value, to be used in diagnostics.
+   #[must_use]
+   pub fn kind(&self) -> &'static str {
+      match *self {
+         Value::Error(_) => <Arc<Error>>::KIND,
+         Value::Location(_) => Location::KIND,
+         Value::Boolean(_) => bool::KIND,
+         Value::Null(_) => Null::KIND,
+         Value::Cons(_) => <Arc<Cons>>::KIND,
+         Value::Nil(_) => Nil::KIND,
+         Value::Attributes(_) => Attributes::KIND,
+         Value::Path(_) => Path::KIND,
+         Value::Bind(_) => "bind",
+         Value::Reference(_) => "reference",
+         Value::String(_) => SString::KIND,
+         Value::Char(_) => char::KIND,
+         Value::Integer(_) => Integer::KIND,
+         Value::Float(_) => f64::KIND,
+         Value::Thunk(_) => Thunk::KIND,
+         Value::NeedsArgumentToThunk(_) => "lambda code",
+         Value::Thunkable(_) => "thunk code",
+      }
+   }
+
+   /// Whether this value is of the shape. The value must already be forced.
+   #[must_use]
+   pub fn is(&self, shape: lode::Shape) -> bool {
+      matches!(
+         (shape, self),
+         (lode::Shape::Boolean, &Value::Boolean(_))
+            | (lode::Shape::Char, &Value::Char(_))
+            | (lode::Shape::Integer, &Value::Integer(_))
+            | (lode::Shape::Float, &Value::Float(_))
+            | (lode::Shape::String, &Value::String(_))
+            | (lode::Shape::Path, &Value::Path(_))
+            | (lode::Shape::Attributes, &Value::Attributes(_))
+            | (lode::Shape::List, &(Value::Cons(_) | Value::Nil(_)))
+      )
+   }
+
+   /// Returns the name of the kind of the values of the shape, to be used in
+   /// diagnostics.
+   #[must_use]
+   pub fn shape_kind(shape: lode::Shape) -> &'static str {
+      match shape {
+         lode::Shape::Boolean => bool::KIND,
+         lode::Shape::Char => char::KIND,
+         lode::Shape::Integer => Integer::KIND,
+         lode::Shape::Float => f64::KIND,
+         lode::Shape::String => SString::KIND,
+         lode::Shape::Path => Path::KIND,
+         lode::Shape::Attributes => Attributes::KIND,
+         // Lists are either cons or nil, which have kinds of their own.
+         lode::Shape::List => "list",
+      }
+   }
+
+   /// Whether this value is compared by itself rather than by its attributes.
+   fn is_scalar(&self) -> bool {
+      matches!(
+         *self,
+         Self::Boolean(_)
+            | Self::Char(_)
+            | Self::Integer(_)
+            | Self::Float(_)
+            | Self::String(_)
+            | Self::Null(_)
+            | Self::Nil(_)
+      )
+   }
+
    #[must_use]
    pub fn typed<T: Dupe>(self) -> Typed<T>
    where
@@ -199,9 +293,13 @@ impl Value {
       }
    }
 
+   /// Compares the values, returning whether they are equal and what the
+   /// binds in them bind to. Returns [`None`] if the values can't be compared
+   /// yet, like lists with lists and attributes with attributes, as that needs
+   /// their items to be forced.
    #[must_use]
-   pub fn equals(left: &Value, right: &Value) -> (bool, Attributes) {
-      match (left, right) {
+   pub fn equals(left: &Value, right: &Value) -> Option<(bool, Attributes)> {
+      Some(match (left, right) {
          (left @ &Self::Bind(ref left_identifier), right @ &Self::Bind(ref right_identifier)) => {
             (
                true,
@@ -216,13 +314,33 @@ impl Value {
                attributes::new! {}.insert(identifier.dupe(), value.dupe()),
             )
          },
-         (left, right) => {
-            Attributes::equals(
-               &Into::<Attributes>::into(left),
-               &Into::<Attributes>::into(right),
-            )
+         (&Self::Boolean(left), &Self::Boolean(right)) => (left == right, attributes::new! {}),
+         (&Self::Char(left), &Self::Char(right)) => (left == right, attributes::new! {}),
+         (&Self::Integer(ref left), &Self::Integer(ref right)) => {
+            (**left == **right, attributes::new! {})
          },
-      }
+         #[expect(clippy::float_cmp)]
+         (&Self::Float(left), &Self::Float(right)) => (left == right, attributes::new! {}),
+         (&Self::String(ref left), &Self::String(ref right)) => {
+            (left == right, attributes::new! {})
+         },
+         (&Self::Null(_), &Self::Null(_)) | (&Self::Nil(_), &Self::Nil(_)) => {
+            (true, attributes::new! {})
+         },
+
+         // Scalars of different kinds are never equal.
+         (left, right) if left.is_scalar() && right.is_scalar() => (false, attributes::new! {}),
+
+         // Neither are scalars and lists or attributes.
+         (scalar, &(Self::Cons(_) | Self::Attributes(_)))
+         | (&(Self::Cons(_) | Self::Attributes(_)), scalar)
+            if scalar.is_scalar() =>
+         {
+            (false, attributes::new! {})
+         },
+
+         _ => return None,
+      })
    }
 }
 
@@ -248,13 +366,54 @@ where
    }
 }
 
-impl<T: Dupe> Typed<T>
+impl<T: Dupe + Kind> Typed<T>
 where
    Value: TryInto<T>,
 {
    pub fn must(self) -> Result<T, Value> {
+      let kind = self.value.kind();
+
       self.value.try_into().map_err(|_| {
-         Value::from(Error::new(string::new!("TODO better expected type error")).arc())
+         Value::from(
+            Error::new(SString::from(&*format!(
+               "expected {expected}, got {kind}",
+               expected = T::KIND,
+            )))
+            .arc(),
+         )
       })
    }
 }
+
+/// A type a [`Value`] can be turned into, along with the name of the kind of
+/// the values it is turned from.
+pub trait Kind {
+   /// The name of the kind, as returned by [`Value::kind`].
+   const KIND: &'static str;
+}
+
+macro_rules! kind {
+   ($($type:ty => $kind:literal),* $(,)?) => {
+      $(
+         impl Kind for $type {
+            const KIND: &'static str = $kind;
+         }
+      )*
+   };
+}
+
+kind! {
+   Arc<Error> => "error",
+   Location => "location",
+   bool => "boolean",
+   Null => "null",
+   Arc<Cons> => "cons",
+   Nil => "nil",
+   Attributes => "attributes",
+   Path => "path",
+   SString => "string",
+   char => "char",
+   Integer => "integer",
+   f64 => "float",
+   Thunk => "thunk",
+}
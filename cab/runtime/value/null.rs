@@ -0,0 +1,5 @@
+use dup::Dupe;
+
+/// The absence of a value, which `null` in JSON and YAML is converted into.
+#[derive(Clone, Dupe, Copy)]
+pub struct Null;
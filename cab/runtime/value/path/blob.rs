@@ -32,6 +32,10 @@ impl Root for Blob {
       Some(&self.config)
    }
 
+   async fn exists(self: Arc<Self>, subpath: &Subpath) -> Result<bool> {
+      Ok(subpath.is_empty())
+   }
+
    async fn read(self: Arc<Self>, subpath: &Subpath) -> Result<Bytes> {
       if !subpath.is_empty() {
          bail!("blob only contains a single leaf");
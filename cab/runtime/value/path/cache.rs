@@ -0,0 +1,147 @@
+use std::{
+   path::{
+      Component,
+      Path,
+      PathBuf,
+   },
+   sync::Arc,
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use cyn::{
+   Result,
+   bail,
+};
+use rpds::ListSync as List;
+
+use super::{
+   Root,
+   Subpath,
+   fs,
+};
+use crate::{
+   Value,
+   value,
+};
+
+/// Returns a root of the outputs cached in the directory. The outputs are
+/// never modified, so the root isn't writeable.
+#[must_use]
+pub fn cache(directory: PathBuf) -> impl Root {
+   Cache {
+      config: Value::from(value::SString::from(&*directory.display().to_string())),
+      directory,
+   }
+}
+
+struct Cache {
+   config:    Value,
+   directory: PathBuf,
+}
+
+impl Cache {
+   /// Returns the path of the subpath in the directory. Parts that would
+   /// leave the directory, like `..` or ones with separators, are rejected.
+   fn to_pathbuf(&self, subpath: &Subpath) -> Result<PathBuf> {
+      let mut path = self.directory.clone();
+
+      for part in subpath {
+         let mut components = Path::new(&***part).components();
+
+         let (Some(Component::Normal(_)), None) = (components.next(), components.next()) else {
+            bail!("cache path part '{part}' must be a name", part = &***part);
+         };
+
+         path.push(&***part);
+      }
+
+      Ok(path)
+   }
+}
+
+#[async_trait]
+impl Root for Cache {
+   fn type_(&self) -> &'static str {
+      "cache"
+   }
+
+   fn config(&self) -> Option<&Value> {
+      Some(&self.config)
+   }
+
+   async fn list(self: Arc<Self>, subpath: &Subpath) -> Result<List<Subpath>> {
+      fs::list(&self.to_pathbuf(subpath)?, subpath).await
+   }
+
+   async fn exists(self: Arc<Self>, subpath: &Subpath) -> Result<bool> {
+      fs::exists(&self.to_pathbuf(subpath)?).await
+   }
+
+   async fn read(self: Arc<Self>, subpath: &Subpath) -> Result<Bytes> {
+      fs::read(&self.to_pathbuf(subpath)?).await
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use std::fs as std_fs;
+
+   use cab_util::suffix::Arc as _;
+
+   use super::*;
+   use crate::builtin::TemporaryDirectory;
+
+   #[test]
+   fn stays_in_directory() {
+      let cache = Cache {
+         config:    Value::from(value::Null),
+         directory: PathBuf::from("/cache"),
+      };
+
+      let subpath = |parts: &[&str]| {
+         parts
+            .iter()
+            .map(|&part| value::SString::from(part))
+            .collect::<Subpath>()
+      };
+
+      assert_eq!(
+         cache.to_pathbuf(&subpath(&["a", "b"])).ok(),
+         Some(PathBuf::from("/cache/a/b")),
+      );
+
+      for parts in [&["a", ".."][..], &[".."], &["."], &[""], &["a/b"], &["/etc"]] {
+         assert!(cache.to_pathbuf(&subpath(parts)).is_err(), "{parts:?}");
+      }
+   }
+
+   #[tokio::test]
+   async fn lists_below_subpath() {
+      let directory = TemporaryDirectory::new();
+      std_fs::create_dir_all(directory.0.join("a/b")).expect("directories must be creatable");
+      std_fs::write(directory.0.join("a/b/d"), "").expect("file must be writeable");
+      std_fs::write(directory.0.join("a/b/c"), "").expect("file must be writeable");
+
+      let subpath = ["a", "b"]
+         .into_iter()
+         .map(value::SString::from)
+         .collect::<Subpath>();
+
+      let listed = Cache {
+         config:    Value::from(value::Null),
+         directory: directory.0.clone(),
+      }
+      .arc()
+      .list(&subpath)
+      .await
+      .expect("directory must be listable");
+
+      let listed = listed
+         .iter()
+         .map(|subpath| subpath.iter().map(|part| &***part).collect::<Vec<_>>())
+         .collect::<Vec<_>>();
+
+      assert_eq!(listed, [["a", "b", "c"], ["a", "b", "d"]]);
+   }
+}
@@ -1,6 +1,9 @@
 use std::{
    iter,
-   path::PathBuf,
+   path::{
+      Path as StdPath,
+      PathBuf,
+   },
    sync::Arc,
 };
 
@@ -12,6 +15,7 @@ use cyn::{
    ResultExt as _,
    bail_tags,
 };
+use dup::IteratorDupedExt as _;
 use rpds::ListSync as List;
 use tokio::fs;
 use ust::{
@@ -69,6 +73,61 @@ fn to_pathbuf(subpath: &Subpath) -> Result<PathBuf> {
    })
 }
 
+/// Lists the directory at the path, which is at the subpath of its root. The
+/// entries are sorted by name.
+pub(super) async fn list(path: &StdPath, subpath: &Subpath) -> Result<List<Subpath>> {
+   let mut contents = Vec::new();
+
+   let mut read = fs::read_dir(path)
+      .await
+      .chain_err_with(|| format!("failed to read dir '{path}'", path = path.display()))?;
+
+   while let Some(entry) = read
+      .next_entry()
+      .await
+      .chain_err_with(|| format!("failed to read entry of '{path}'", path = path.display()))?
+   {
+      let name = entry.file_name();
+      let name = name.to_str().ok_or_chain_with(|| {
+         format!(
+            "entry with name similar to '{name}' has a name that is not valid UTF-8",
+            name = name.display()
+         )
+      })?;
+
+      contents.push(
+         subpath
+            .iter()
+            .duped()
+            .chain(iter::once(value::SString::from(name)))
+            .collect(),
+      );
+   }
+
+   contents.sort_unstable();
+
+   Ok(rpds::List::from_iter(contents))
+}
+
+/// Reads the file at the path.
+pub(super) async fn read(path: &StdPath) -> Result<Bytes> {
+   let content = fs::read(path)
+      .await
+      .chain_err_with(|| format!("failed to read '{path}'", path = path.display()))?;
+
+   Ok(Bytes::from(content))
+}
+
+/// Whether anything exists at the path.
+pub(super) async fn exists(path: &StdPath) -> Result<bool> {
+   fs::try_exists(path).await.chain_err_with(|| {
+      format!(
+         "failed to check whether '{path}' exists",
+         path = path.display(),
+      )
+   })
+}
+
 #[must_use]
 pub fn fs() -> impl Root {
    Fs
@@ -83,43 +142,15 @@ impl Root for Fs {
    }
 
    async fn list(self: Arc<Self>, subpath: &Subpath) -> Result<List<Subpath>> {
-      let mut contents = Vec::new();
-
-      let path = to_pathbuf(subpath)?;
-
-      let mut read = fs::read_dir(&path)
-         .await
-         .chain_err_with(|| format!("failed to read dir '{path}'", path = path.display()))?;
-
-      while let Some(entry) = read
-         .next_entry()
-         .await
-         .chain_err_with(|| format!("failed to read entry of '{path}'", path = path.display()))?
-      {
-         let name = entry.file_name();
-         let name = name.to_str().ok_or_chain_with(|| {
-            format!(
-               "entry with name similar to '{name}' has a name that is not valid UTF-8",
-               name = name.display()
-            )
-         })?;
-
-         contents.push(subpath.push_front(value::SString::from(name)));
-      }
-
-      contents.sort_unstable();
-
-      Ok(rpds::List::from_iter(contents))
+      list(&to_pathbuf(subpath)?, subpath).await
    }
 
    async fn read(self: Arc<Self>, subpath: &Subpath) -> Result<Bytes> {
-      let path = to_pathbuf(subpath)?;
-
-      let content = fs::read(&path)
-         .await
-         .chain_err_with(|| format!("failed to read '{path}'", path = path.display()))?;
+      read(&to_pathbuf(subpath)?).await
+   }
 
-      Ok(Bytes::from(content))
+   async fn exists(self: Arc<Self>, subpath: &Subpath) -> Result<bool> {
+      exists(&to_pathbuf(subpath)?).await
    }
 
    async fn is_writeable(&self) -> bool {
@@ -33,6 +33,9 @@ use crate::value;
 mod blob;
 pub use blob::blob;
 
+mod cache;
+pub use cache::cache;
+
 mod fs;
 pub use fs::fs;
 
@@ -61,6 +64,10 @@ pub trait Root: Send + Sync + 'static {
 
    async fn read(self: Arc<Self>, subpath: &Subpath) -> Result<Bytes>;
 
+   async fn exists(self: Arc<Self>, subpath: &Subpath) -> Result<bool> {
+      Ok(self.dupe().list(subpath).await.is_ok() || self.read(subpath).await.is_ok())
+   }
+
    async fn is_writeable(&self) -> bool {
       false
    }
@@ -139,6 +146,16 @@ impl Path {
 }
 
 impl Path {
+   #[must_use]
+   pub fn root(&self) -> Option<&Arc<dyn Root>> {
+      self.root.as_ref()
+   }
+
+   #[must_use]
+   pub fn subpath(&self) -> &Subpath {
+      &self.subpath
+   }
+
    #[must_use]
    pub fn get(&self, part: Part) -> Self {
       Self {
@@ -205,6 +222,23 @@ impl Path {
          .dupe()
    }
 
+   /// Whether the path exists. Rootless paths don't contain anything, so they
+   /// never exist.
+   pub async fn exists(&self) -> Result<bool> {
+      let Some(root) = self.root.dupe() else {
+         return Ok(false);
+      };
+
+      root
+         .exists(&self.subpath)
+         .await
+         .tag_err(&|tags: &mut tag::Tags| {
+            tags.write("failed to check whether ");
+            self.display_tags_owned(tags);
+            tags.write(" exists");
+         })
+   }
+
    pub async fn write(&self, content: Bytes) -> Result<()> {
       let root = self.root.dupe().ok_or_tag(&|tags: &mut tag::Tags| {
          tags.write("tried to write to rootless path ");
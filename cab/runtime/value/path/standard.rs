@@ -36,6 +36,10 @@ impl Root for Standard {
       None
    }
 
+   async fn exists(self: Arc<Self>, subpath: &Subpath) -> Result<bool> {
+      Ok(subpath.is_empty())
+   }
+
    async fn read(self: Arc<Self>, subpath: &Subpath) -> Result<Bytes> {
       if !subpath.is_empty() {
          bail!("standard only contains a single leaf");
@@ -0,0 +1,848 @@
+//! Conversions between values and Rust types through [`serde`].
+//!
+//! Values are converted as they are, so they have to be forced deeply before
+//! being deserialized. Unit and [`None`] are converted into null, the same way
+//! JSON `null` is. Nil is deserialized into [`None`] too, as it used to be what
+//! they were converted into.
+
+use std::{
+   error,
+   fmt,
+   vec,
+};
+
+use derive_more::Display;
+use dup::Dupe as _;
+use num::ToPrimitive as _;
+use serde::{
+   de,
+   ser,
+};
+
+use crate::{
+   Value,
+   value,
+};
+
+/// An error that happened while converting between a value and a Rust type.
+#[derive(Debug, Display)]
+#[display("{_0}")]
+pub struct Error(String);
+
+impl error::Error for Error {}
+
+impl ser::Error for Error {
+   fn custom<T: fmt::Display>(message: T) -> Self {
+      Self(message.to_string())
+   }
+}
+
+impl de::Error for Error {
+   fn custom<T: fmt::Display>(message: T) -> Self {
+      Self(message.to_string())
+   }
+}
+
+/// Converts the Rust value into a value.
+pub fn to_value<T: ser::Serialize + ?Sized>(value: &T) -> Result<Value, Error> {
+   value.serialize(Serializer)
+}
+
+/// Converts the deeply forced value into a Rust value.
+pub fn from_value<T: de::DeserializeOwned>(value: Value) -> Result<T, Error> {
+   T::deserialize(value)
+}
+
+fn list(items: Vec<Value>) -> Value {
+   items
+      .into_iter()
+      .rev()
+      .fold(Value::from(value::Nil), |tail, head| {
+         Value::from(value::Cons::new(head, tail).arc())
+      })
+}
+
+fn integer(integer: impl Into<num::BigInt>) -> Value {
+   Value::from(value::Integer::from(integer.into()))
+}
+
+fn string(string: &str) -> Value {
+   Value::from(value::SString::from(string))
+}
+
+// SERIALIZER
+
+/// A serializer that turns Rust values into values.
+pub struct Serializer;
+
+impl ser::Serializer for Serializer {
+   type Ok = Value;
+   type Error = Error;
+
+   type SerializeSeq = SerializeList;
+   type SerializeTuple = SerializeList;
+   type SerializeTupleStruct = SerializeList;
+   type SerializeTupleVariant = SerializeList;
+   type SerializeMap = SerializeAttributes;
+   type SerializeStruct = SerializeAttributes;
+   type SerializeStructVariant = SerializeAttributes;
+
+   fn serialize_bool(self, boolean: bool) -> Result<Value, Error> {
+      Ok(Value::Boolean(boolean))
+   }
+
+   fn serialize_i8(self, integer: i8) -> Result<Value, Error> {
+      Ok(self::integer(integer))
+   }
+
+   fn serialize_i16(self, integer: i16) -> Result<Value, Error> {
+      Ok(self::integer(integer))
+   }
+
+   fn serialize_i32(self, integer: i32) -> Result<Value, Error> {
+      Ok(self::integer(integer))
+   }
+
+   fn serialize_i64(self, integer: i64) -> Result<Value, Error> {
+      Ok(self::integer(integer))
+   }
+
+   fn serialize_i128(self, integer: i128) -> Result<Value, Error> {
+      Ok(self::integer(integer))
+   }
+
+   fn serialize_u8(self, integer: u8) -> Result<Value, Error> {
+      Ok(self::integer(integer))
+   }
+
+   fn serialize_u16(self, integer: u16) -> Result<Value, Error> {
+      Ok(self::integer(integer))
+   }
+
+   fn serialize_u32(self, integer: u32) -> Result<Value, Error> {
+      Ok(self::integer(integer))
+   }
+
+   fn serialize_u64(self, integer: u64) -> Result<Value, Error> {
+      Ok(self::integer(integer))
+   }
+
+   fn serialize_u128(self, integer: u128) -> Result<Value, Error> {
+      Ok(self::integer(integer))
+   }
+
+   fn serialize_f32(self, float: f32) -> Result<Value, Error> {
+      Ok(Value::Float(f64::from(float)))
+   }
+
+   fn serialize_f64(self, float: f64) -> Result<Value, Error> {
+      Ok(Value::Float(float))
+   }
+
+   fn serialize_char(self, char: char) -> Result<Value, Error> {
+      Ok(Value::Char(char))
+   }
+
+   fn serialize_str(self, string: &str) -> Result<Value, Error> {
+      Ok(self::string(string))
+   }
+
+   fn serialize_bytes(self, bytes: &[u8]) -> Result<Value, Error> {
+      Ok(list(bytes.iter().map(|&byte| integer(byte)).collect()))
+   }
+
+   fn serialize_none(self) -> Result<Value, Error> {
+      Ok(Value::from(value::Null))
+   }
+
+   fn serialize_some<T: ser::Serialize + ?Sized>(self, value: &T) -> Result<Value, Error> {
+      value.serialize(self)
+   }
+
+   fn serialize_unit(self) -> Result<Value, Error> {
+      Ok(Value::from(value::Null))
+   }
+
+   fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, Error> {
+      Ok(Value::from(value::Null))
+   }
+
+   fn serialize_unit_variant(
+      self,
+      _name: &'static str,
+      _index: u32,
+      variant: &'static str,
+   ) -> Result<Value, Error> {
+      Ok(string(variant))
+   }
+
+   fn serialize_newtype_struct<T: ser::Serialize + ?Sized>(
+      self,
+      _name: &'static str,
+      value: &T,
+   ) -> Result<Value, Error> {
+      value.serialize(self)
+   }
+
+   fn serialize_newtype_variant<T: ser::Serialize + ?Sized>(
+      self,
+      _name: &'static str,
+      _index: u32,
+      variant: &'static str,
+      value: &T,
+   ) -> Result<Value, Error> {
+      Ok(Value::from(value::attributes::new! {}.insert(
+         value::SString::from(variant),
+         value.serialize(self)?,
+      )))
+   }
+
+   fn serialize_seq(self, length: Option<usize>) -> Result<SerializeList, Error> {
+      Ok(SerializeList {
+         variant: None,
+         items:   Vec::with_capacity(length.unwrap_or_default()),
+      })
+   }
+
+   fn serialize_tuple(self, length: usize) -> Result<SerializeList, Error> {
+      self.serialize_seq(Some(length))
+   }
+
+   fn serialize_tuple_struct(
+      self,
+      _name: &'static str,
+      length: usize,
+   ) -> Result<SerializeList, Error> {
+      self.serialize_seq(Some(length))
+   }
+
+   fn serialize_tuple_variant(
+      self,
+      _name: &'static str,
+      _index: u32,
+      variant: &'static str,
+      length: usize,
+   ) -> Result<SerializeList, Error> {
+      Ok(SerializeList {
+         variant: Some(variant),
+         items:   Vec::with_capacity(length),
+      })
+   }
+
+   fn serialize_map(self, _length: Option<usize>) -> Result<SerializeAttributes, Error> {
+      Ok(SerializeAttributes {
+         variant:    None,
+         attributes: value::attributes::new! {},
+         name:       None,
+      })
+   }
+
+   fn serialize_struct(
+      self,
+      _name: &'static str,
+      length: usize,
+   ) -> Result<SerializeAttributes, Error> {
+      self.serialize_map(Some(length))
+   }
+
+   fn serialize_struct_variant(
+      self,
+      _name: &'static str,
+      _index: u32,
+      variant: &'static str,
+      _length: usize,
+   ) -> Result<SerializeAttributes, Error> {
+      Ok(SerializeAttributes {
+         variant:    Some(variant),
+         attributes: value::attributes::new! {},
+         name:       None,
+      })
+   }
+}
+
+/// Wraps the value in attributes with the variant as the only name, if there
+/// is a variant.
+fn variant(variant: Option<&'static str>, value: Value) -> Value {
+   match variant {
+      Some(variant) => {
+         Value::from(value::attributes::new! {}.insert(value::SString::from(variant), value))
+      },
+
+      None => value,
+   }
+}
+
+#[doc(hidden)]
+pub struct SerializeList {
+   variant: Option<&'static str>,
+   items:   Vec<Value>,
+}
+
+impl SerializeList {
+   fn push<T: ser::Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+      self.items.push(value.serialize(Serializer)?);
+      Ok(())
+   }
+
+   fn finish(self) -> Value {
+      variant(self.variant, list(self.items))
+   }
+}
+
+impl ser::SerializeSeq for SerializeList {
+   type Ok = Value;
+   type Error = Error;
+
+   fn serialize_element<T: ser::Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+      self.push(value)
+   }
+
+   fn end(self) -> Result<Value, Error> {
+      Ok(self.finish())
+   }
+}
+
+impl ser::SerializeTuple for SerializeList {
+   type Ok = Value;
+   type Error = Error;
+
+   fn serialize_element<T: ser::Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+      self.push(value)
+   }
+
+   fn end(self) -> Result<Value, Error> {
+      Ok(self.finish())
+   }
+}
+
+impl ser::SerializeTupleStruct for SerializeList {
+   type Ok = Value;
+   type Error = Error;
+
+   fn serialize_field<T: ser::Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+      self.push(value)
+   }
+
+   fn end(self) -> Result<Value, Error> {
+      Ok(self.finish())
+   }
+}
+
+impl ser::SerializeTupleVariant for SerializeList {
+   type Ok = Value;
+   type Error = Error;
+
+   fn serialize_field<T: ser::Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+      self.push(value)
+   }
+
+   fn end(self) -> Result<Value, Error> {
+      Ok(self.finish())
+   }
+}
+
+#[doc(hidden)]
+pub struct SerializeAttributes {
+   variant:    Option<&'static str>,
+   attributes: value::Attributes,
+
+   /// The name of the entry whose value is being serialized.
+   name: Option<value::SString>,
+}
+
+impl SerializeAttributes {
+   fn insert<T: ser::Serialize + ?Sized>(&mut self, name: &str, value: &T) -> Result<(), Error> {
+      self.attributes = self
+         .attributes
+         .insert(value::SString::from(name), value.serialize(Serializer)?);
+      Ok(())
+   }
+
+   fn finish(self) -> Value {
+      variant(self.variant, Value::from(self.attributes))
+   }
+}
+
+impl ser::SerializeMap for SerializeAttributes {
+   type Ok = Value;
+   type Error = Error;
+
+   fn serialize_key<T: ser::Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+      self.name = Some(match key.serialize(Serializer)? {
+         Value::String(name) => name,
+         Value::Char(char) => value::SString::from(&*char.to_string()),
+
+         other => {
+            return Err(Error(format!(
+               "attribute names must be strings, got {kind}",
+               kind = other.kind(),
+            )));
+         },
+      });
+
+      Ok(())
+   }
+
+   fn serialize_value<T: ser::Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+      let name = self
+         .name
+         .take()
+         .expect("serialize-value must be called after serialize-key");
+
+      self.attributes = self.attributes.insert(name, value.serialize(Serializer)?);
+      Ok(())
+   }
+
+   fn end(self) -> Result<Value, Error> {
+      Ok(self.finish())
+   }
+}
+
+impl ser::SerializeStruct for SerializeAttributes {
+   type Ok = Value;
+   type Error = Error;
+
+   fn serialize_field<T: ser::Serialize + ?Sized>(
+      &mut self,
+      name: &'static str,
+      value: &T,
+   ) -> Result<(), Error> {
+      self.insert(name, value)
+   }
+
+   fn end(self) -> Result<Value, Error> {
+      Ok(self.finish())
+   }
+}
+
+impl ser::SerializeStructVariant for SerializeAttributes {
+   type Ok = Value;
+   type Error = Error;
+
+   fn serialize_field<T: ser::Serialize + ?Sized>(
+      &mut self,
+      name: &'static str,
+      value: &T,
+   ) -> Result<(), Error> {
+      self.insert(name, value)
+   }
+
+   fn end(self) -> Result<Value, Error> {
+      Ok(self.finish())
+   }
+}
+
+// DESERIALIZER
+
+impl Value {
+   fn unexpected(&self) -> de::Unexpected<'_> {
+      match *self {
+         Value::Boolean(boolean) => de::Unexpected::Bool(boolean),
+         Value::Char(char) => de::Unexpected::Char(char),
+         Value::String(ref string) => de::Unexpected::Str(&***string),
+         Value::Float(float) => de::Unexpected::Float(float),
+         Value::Null(_) | Value::Nil(_) => de::Unexpected::Unit,
+         Value::Cons(_) => de::Unexpected::Seq,
+         Value::Attributes(_) => de::Unexpected::Map,
+
+         ref other => de::Unexpected::Other(other.kind()),
+      }
+   }
+
+   /// Collects the items of the list, failing on improper lists.
+   fn into_items(self) -> Result<Vec<Value>, Error> {
+      let mut items = Vec::new();
+
+      let mut value = self;
+      loop {
+         match value {
+            Value::Cons(cons) => {
+               items.push(cons.0.dupe());
+               value = cons.1.dupe();
+            },
+
+            Value::Nil(_) => return Ok(items),
+
+            other => {
+               return Err(Error(format!(
+                  "expected list to end with nil, got {kind}",
+                  kind = other.kind(),
+               )));
+            },
+         }
+      }
+   }
+}
+
+impl<'de> de::Deserializer<'de> for Value {
+   type Error = Error;
+
+   fn deserialize_any<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+      match self {
+         Value::Boolean(boolean) => visitor.visit_bool(boolean),
+
+         Value::Integer(ref integer) => {
+            if let Some(integer) = integer.to_i64() {
+               visitor.visit_i64(integer)
+            } else if let Some(integer) = integer.to_u64() {
+               visitor.visit_u64(integer)
+            } else if let Some(integer) = integer.to_i128() {
+               visitor.visit_i128(integer)
+            } else if let Some(integer) = integer.to_u128() {
+               visitor.visit_u128(integer)
+            } else {
+               Err(Error(format!(
+                  "integer {integer} is too large to convert",
+                  integer = **integer,
+               )))
+            }
+         },
+         Value::Float(float) => visitor.visit_f64(float),
+
+         Value::Char(char) => visitor.visit_char(char),
+         Value::String(ref string) => visitor.visit_str(&***string),
+
+         Value::Null(_) => visitor.visit_unit(),
+
+         Value::Nil(_) | Value::Cons(_) => {
+            let items = self.into_items()?;
+            let length = items.len();
+
+            let mut access = Seq(items.into_iter());
+            let value = visitor.visit_seq(&mut access)?;
+
+            if access.0.len() != 0 {
+               return Err(de::Error::invalid_length(length, &"fewer items"));
+            }
+
+            Ok(value)
+         },
+
+         Value::Attributes(attributes) => {
+            let mut access = Map {
+               entries: attributes
+                  .iter()
+                  .map(|(name, value)| (name.dupe(), value.dupe()))
+                  .collect::<Vec<_>>()
+                  .into_iter(),
+               value:   None,
+            };
+
+            visitor.visit_map(&mut access)
+         },
+
+         Value::Error(ref error) => {
+            Err(Error(format!(
+               "cannot convert error: {value}",
+               value = match error.value {
+                  Value::String(ref string) => &***string,
+                  ref other => other.kind(),
+               },
+            )))
+         },
+
+         Value::Thunk(_) => {
+            Err(Error(
+               "cannot convert thunk, the value must be forced deeply".to_owned(),
+            ))
+         },
+
+         ref other => {
+            Err(Error(format!(
+               "cannot convert {kind}",
+               kind = other.kind(),
+            )))
+         },
+      }
+   }
+
+   fn deserialize_option<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+      match self {
+         Value::Null(_) | Value::Nil(_) => visitor.visit_none(),
+         other => visitor.visit_some(other),
+      }
+   }
+
+   fn deserialize_unit<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+      match self {
+         Value::Null(_) | Value::Nil(_) => visitor.visit_unit(),
+         other => Err(de::Error::invalid_type(other.unexpected(), &visitor)),
+      }
+   }
+
+   fn deserialize_unit_struct<V: de::Visitor<'de>>(
+      self,
+      _name: &'static str,
+      visitor: V,
+   ) -> Result<V::Value, Error> {
+      self.deserialize_unit(visitor)
+   }
+
+   fn deserialize_newtype_struct<V: de::Visitor<'de>>(
+      self,
+      _name: &'static str,
+      visitor: V,
+   ) -> Result<V::Value, Error> {
+      visitor.visit_newtype_struct(self)
+   }
+
+   fn deserialize_enum<V: de::Visitor<'de>>(
+      self,
+      _name: &'static str,
+      _variants: &'static [&'static str],
+      visitor: V,
+   ) -> Result<V::Value, Error> {
+      match self {
+         Value::String(variant) => {
+            visitor.visit_enum(Enum {
+               variant,
+               value: None,
+            })
+         },
+
+         Value::Attributes(ref attributes) if attributes.len() == 1 => {
+            let (variant, value) = attributes
+               .iter()
+               .next()
+               .expect("attributes must have an entry");
+
+            visitor.visit_enum(Enum {
+               variant: variant.dupe(),
+               value:   Some(value.dupe()),
+            })
+         },
+
+         other => Err(de::Error::invalid_type(other.unexpected(), &visitor)),
+      }
+   }
+
+   serde::forward_to_deserialize_any! {
+      bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf seq
+      tuple tuple_struct map struct identifier ignored_any
+   }
+}
+
+impl de::IntoDeserializer<'_, Error> for Value {
+   type Deserializer = Self;
+
+   fn into_deserializer(self) -> Self {
+      self
+   }
+}
+
+struct Seq(vec::IntoIter<Value>);
+
+impl<'de> de::SeqAccess<'de> for Seq {
+   type Error = Error;
+
+   fn next_element_seed<T: de::DeserializeSeed<'de>>(
+      &mut self,
+      seed: T,
+   ) -> Result<Option<T::Value>, Error> {
+      self.0.next().map(|item| seed.deserialize(item)).transpose()
+   }
+
+   fn size_hint(&self) -> Option<usize> {
+      Some(self.0.len())
+   }
+}
+
+struct Map {
+   entries: vec::IntoIter<(value::SString, Value)>,
+   value:   Option<Value>,
+}
+
+impl<'de> de::MapAccess<'de> for Map {
+   type Error = Error;
+
+   fn next_key_seed<K: de::DeserializeSeed<'de>>(
+      &mut self,
+      seed: K,
+   ) -> Result<Option<K::Value>, Error> {
+      let Some((name, value)) = self.entries.next() else {
+         return Ok(None);
+      };
+
+      self.value = Some(value);
+      seed.deserialize(Value::from(name)).map(Some)
+   }
+
+   fn next_value_seed<V: de::DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+      seed.deserialize(
+         self
+            .value
+            .take()
+            .expect("next-value must be called after next-key"),
+      )
+   }
+
+   fn size_hint(&self) -> Option<usize> {
+      Some(self.entries.len())
+   }
+}
+
+struct Enum {
+   variant: value::SString,
+   value:   Option<Value>,
+}
+
+impl<'de> de::EnumAccess<'de> for Enum {
+   type Error = Error;
+   type Variant = Variant;
+
+   fn variant_seed<V: de::DeserializeSeed<'de>>(
+      self,
+      seed: V,
+   ) -> Result<(V::Value, Variant), Error> {
+      let variant = seed.deserialize(Value::from(self.variant))?;
+
+      Ok((variant, Variant(self.value)))
+   }
+}
+
+struct Variant(Option<Value>);
+
+impl Variant {
+   fn value(self, expected: &str) -> Result<Value, Error> {
+      self
+         .0
+         .ok_or_else(|| de::Error::invalid_type(de::Unexpected::UnitVariant, &expected))
+   }
+}
+
+impl<'de> de::VariantAccess<'de> for Variant {
+   type Error = Error;
+
+   fn unit_variant(self) -> Result<(), Error> {
+      match self.0 {
+         None | Some(Value::Null(_) | Value::Nil(_)) => Ok(()),
+         Some(ref other) => Err(de::Error::invalid_type(other.unexpected(), &"unit variant")),
+      }
+   }
+
+   fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+      seed.deserialize(self.value("newtype variant")?)
+   }
+
+   fn tuple_variant<V: de::Visitor<'de>>(
+      self,
+      _length: usize,
+      visitor: V,
+   ) -> Result<V::Value, Error> {
+      de::Deserializer::deserialize_seq(self.value("tuple variant")?, visitor)
+   }
+
+   fn struct_variant<V: de::Visitor<'de>>(
+      self,
+      _fields: &'static [&'static str],
+      visitor: V,
+   ) -> Result<V::Value, Error> {
+      de::Deserializer::deserialize_map(self.value("struct variant")?, visitor)
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use std::collections::BTreeMap;
+
+   use serde::{
+      Deserialize,
+      Serialize,
+   };
+
+   use super::*;
+   use crate::builtin::evaluate_with;
+
+   async fn evaluate(source: &str) -> Value {
+      evaluate_with(source, true).await
+   }
+
+   #[derive(Debug, PartialEq, Serialize, Deserialize)]
+   enum Shape {
+      Point,
+      Circle(f64),
+      Rectangle { width: u32, height: u32 },
+   }
+
+   #[derive(Debug, PartialEq, Serialize, Deserialize)]
+   struct Scene {
+      name:    String,
+      visible: bool,
+      tags:    Vec<char>,
+      offset:  (i64, i64),
+      parent:  Option<String>,
+      shapes:  Vec<Shape>,
+      extra:   BTreeMap<String, u128>,
+   }
+
+   fn scene() -> Scene {
+      Scene {
+         name:    "scene".to_owned(),
+         visible: true,
+         tags:    vec!['a', 'b'],
+         offset:  (-1, 2),
+         parent:  None,
+         shapes:  vec![
+            Shape::Point,
+            Shape::Circle(1.5),
+            Shape::Rectangle {
+               width:  3,
+               height: 4,
+            },
+         ],
+         extra:   BTreeMap::from([("big".to_owned(), u128::MAX)]),
+      }
+   }
+
+   #[test]
+   fn round_trip() {
+      let value = to_value(&scene()).expect("scene must convert into a value");
+
+      assert_eq!(
+         from_value::<Scene>(value).expect("value must convert into a scene"),
+         scene(),
+      );
+   }
+
+   #[tokio::test]
+   async fn from_evaluated() {
+      let value = evaluate(
+         r#"{
+            @name = "scene";
+            @visible = true;
+            @tags = [ 'a', 'b' ];
+            @offset = [ parseInteger "-1", 2 ];
+            @parent = null;
+            @shapes = [ "Point", { @Circle = 1.5 }, { @Rectangle = { @width = 3; @height = 4 } } ];
+            @extra = { @big = parseInteger "340282366920938463463374607431768211455" };
+         }"#,
+      )
+      .await;
+
+      assert_eq!(
+         from_value::<Scene>(value).expect("value must convert into a scene"),
+         scene(),
+      );
+   }
+
+   #[tokio::test]
+   async fn errors() {
+      for (source, message) in [
+         (
+            "{ @name = 1 }",
+            "invalid type: integer `1`, expected a string",
+         ),
+         (
+            "undefined",
+            "cannot convert error: undefined value: 'undefined'",
+         ),
+      ] {
+         let error = from_value::<Scene>(evaluate(source).await).expect_err(source);
+
+         assert!(error.to_string().contains(message), "{source}: {error}");
+      }
+
+      let error = from_value::<(i64,)>(evaluate("[ 1, 2 ]").await).expect_err("list is too long");
+      assert_eq!(error.to_string(), "invalid length 2, expected fewer items");
+   }
+}
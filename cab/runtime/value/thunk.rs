@@ -2,13 +2,19 @@
 
 use std::{
    mem,
-   sync::Arc,
+   pin::Pin,
+   sync::{
+      Arc,
+      Weak,
+   },
 };
 
+use cab_syntax::lode;
 use cab_util::{
    collect_vec,
    suffix::Arc as _,
 };
+use derive_more::Deref;
 use dup::{
    Dupe,
    OptionDupedExt as _,
@@ -16,6 +22,8 @@ use dup::{
 use tokio::sync::RwLock;
 
 use crate::{
+   Argument,
+   ByteIndex,
    Code,
    Operation,
    Scope,
@@ -23,16 +31,31 @@ use crate::{
    Scopes,
    State,
    Value,
-   value,
+   profile,
+   value::{
+      self,
+      heap,
+   },
 };
 
 const EXPECT_SCOPE: &str = "must have at least once scope";
 
+/// The future returned by native code.
+pub type NativeFuture<'a> = Pin<Box<dyn Future<Output = Value> + 'a>>;
+
+type NativeCode = Arc<dyn for<'a> Fn(&'a State, Option<Value>) -> NativeFuture<'a> + Send + Sync>;
+
+fn native(
+   code: impl for<'a> Fn(&'a State, Option<Value>) -> NativeFuture<'a> + Send + Sync + 'static,
+) -> NativeCode {
+   code.arc()
+}
+
 #[derive(Clone, Dupe)]
 enum ThunkInner {
    NeedsArgumentNative {
       location: value::Location,
-      code:     Arc<dyn Fn(Option<Value>) -> Value + Send + Sync>,
+      code:     NativeCode,
    },
 
    NeedsArgument {
@@ -44,10 +67,17 @@ enum ThunkInner {
 
    ForceableNative {
       location: value::Location,
-      code:     Arc<dyn Fn(Option<Value>) -> Value + Send + Sync>,
+      code:     NativeCode,
       stack:    Option<Value>,
    },
 
+   /// A native thunk that is never evaluated itself. Every force gets a new
+   /// [`ThunkInner::ForceableNative`] that runs the code again instead.
+   Volatile {
+      location: value::Location,
+      code:     NativeCode,
+   },
+
    Forceable {
       location:    value::Location,
       code:        Arc<Code>,
@@ -56,57 +86,116 @@ enum ThunkInner {
       attached_id: ScopeId,
    },
 
+   /// A thunk in weak head normal form. The scopagate is the attributes of
+   /// the scope the thunk was attached to, if the thunk bound anything in it.
+   /// Those bindings are propagated to the frame that forces the thunk, if
+   /// that frame is in the same scope. Thunks that didn't bind anything
+   /// don't keep the scopes they captured alive.
    Evaluated {
-      scopagate: Option<(ScopeId, Scopes)>,
+      scopagate: Option<(ScopeId, value::Attributes)>,
       value:     Value,
    },
 }
 
 impl ThunkInner {
    thread_local! {
-      static NOT_BOOLEAN: Arc<value::Error> = value::Error::new(value::string::new!("expected boolean, got something else")).arc();
-
-      static NOT_LAMBDA: Arc<value::Error> = value::Error::new(value::string::new!("expected lambda, got something else")).arc();
-
-      static NOT_ATTRIBUTES: Arc<value::Error> = value::Error::new(value::string::new!("expected attributes, got something else")).arc();
-
       static INFINITE_RECURSION: Arc<value::Error> = value::Error::new(value::string::new!("infinite recursion encountered")).arc();
+
+      static BUDGET_SPENT: Arc<value::Error> = value::Error::new(value::string::new!("operation budget spent")).arc();
    }
 
    fn black_hole(location: value::Location) -> Self {
       ThunkInner::ForceableNative {
          location,
-         code: (|_| {
-            Value::from(
-               value::Error::new(value::string::new!("infinite recursion encountered")).arc(),
-            )
-         })
-         .arc(),
+         code: native(|_, _| {
+            Box::pin(async {
+               Value::from(
+                  value::Error::new(value::string::new!("infinite recursion encountered")).arc(),
+               )
+            })
+         }),
          stack: None,
       }
    }
 }
 
+/// The state of a thunk, which is counted as alive while the thunk is.
+#[derive(Deref)]
+struct ThunkCell {
+   #[deref]
+   inner: RwLock<ThunkInner>,
+   live:  heap::Live<{ heap::THUNK }>,
+}
+
+impl ThunkCell {
+   fn new(inner: ThunkInner) -> Self {
+      Self {
+         inner: RwLock::new(inner),
+         live:  heap::Live::default(),
+      }
+   }
+}
+
 #[derive(Clone, Dupe)]
-pub struct Thunk(Arc<RwLock<ThunkInner>>);
+pub struct Thunk(Arc<ThunkCell>);
+
+/// A reference to a thunk that doesn't keep it alive, which open scopes
+/// record the thunks that capture them with.
+#[derive(Clone, Dupe)]
+pub struct WeakThunk(Weak<ThunkCell>);
+
+impl WeakThunk {
+   #[must_use]
+   pub fn is_alive(&self) -> bool {
+      self.0.strong_count() > 0
+   }
+
+   /// Gives the thunk the bindings of the sealed scope, if it is alive and
+   /// wasn't forced yet. See the [`Scope`] documentation.
+   pub async fn seal(&self, scope: &Scope) {
+      let Some(inner) = self.0.upgrade() else {
+         return;
+      };
+
+      if let ThunkInner::NeedsArgument {
+         ref code,
+         ref mut scopes,
+         ..
+      }
+      | ThunkInner::Forceable {
+         ref code,
+         ref mut scopes,
+         ..
+      } = *inner.write().await
+      {
+         *scopes = scopes.seal(scope, code.references());
+      }
+   }
+}
 
 #[bon::bon]
 impl Thunk {
    #[must_use]
    #[builder(finish_fn(name = "location"))]
    pub fn needs_argument_native(
-      #[builder(start_fn)] code: impl Fn(Value) -> Value + Send + Sync + 'static,
+      #[builder(start_fn)] code: impl for<'a> Fn(&'a State, Value) -> NativeFuture<'a>
+      + Send
+      + Sync
+      + 'static,
       #[builder(finish_fn)] location: value::Location,
    ) -> Self {
       Self(
-         RwLock::new(ThunkInner::NeedsArgumentNative {
+         ThunkCell::new(ThunkInner::NeedsArgumentNative {
             location,
-            code: (move |argument: Option<Value>| {
-               code(argument.expect(
-                  "NeedsArgumentNative must be passed in argument when turned into ForceableNative",
-               ))
-            })
-            .arc(),
+            code: native(move |state, argument| {
+               code(
+                  state,
+                  argument.expect(
+                     "NeedsArgumentNative must be passed in argument when turned into \
+                      ForceableNative",
+                  ),
+               )
+            }),
          })
          .arc(),
       )
@@ -121,28 +210,48 @@ impl Thunk {
    ) -> Self {
       let attached_id = scopes.tip().expect(EXPECT_SCOPE).id();
 
+      Self::capturing(&scopes.dupe(), ThunkInner::NeedsArgument {
+         location,
+         code,
+         scopes,
+         attached_id,
+      })
+   }
+
+   #[must_use]
+   #[builder(finish_fn(name = "location"))]
+   pub fn forceable_native(
+      #[builder(start_fn)] code: impl for<'a> Fn(&'a State) -> NativeFuture<'a>
+      + Send
+      + Sync
+      + 'static,
+      #[builder(finish_fn)] location: value::Location,
+   ) -> Self {
       Self(
-         RwLock::new(ThunkInner::NeedsArgument {
+         ThunkCell::new(ThunkInner::ForceableNative {
             location,
-            code,
-            scopes,
-            attached_id,
+            code: native(move |state, _| code(state)),
+            stack: None,
          })
          .arc(),
       )
    }
 
+   /// Creates a native thunk whose code runs again every time it is forced,
+   /// for values that aren't the same across evaluations.
    #[must_use]
    #[builder(finish_fn(name = "location"))]
-   pub fn forceable_native(
-      #[builder(start_fn)] code: impl Fn() -> Value + Send + Sync + 'static,
+   pub fn volatile_native(
+      #[builder(start_fn)] code: impl for<'a> Fn(&'a State) -> NativeFuture<'a>
+      + Send
+      + Sync
+      + 'static,
       #[builder(finish_fn)] location: value::Location,
    ) -> Self {
       Self(
-         RwLock::new(ThunkInner::ForceableNative {
+         ThunkCell::new(ThunkInner::Volatile {
             location,
-            code: (move |_| code()).arc(),
-            stack: None,
+            code: native(move |state, _| code(state)),
          })
          .arc(),
       )
@@ -157,52 +266,128 @@ impl Thunk {
    ) -> Self {
       let attached_id = scopes.tip().expect(EXPECT_SCOPE).id();
 
-      Self(
-         RwLock::new(ThunkInner::Forceable {
-            location,
-            code,
-            stack: None,
-            scopes,
-            attached_id,
-         })
-         .arc(),
-      )
+      Self::capturing(&scopes.dupe(), ThunkInner::Forceable {
+         location,
+         code,
+         stack: None,
+         scopes,
+         attached_id,
+      })
+   }
+
+   /// Creates a thunk that captures the scopes, recording it in the open ones.
+   fn capturing(scopes: &Scopes, inner: ThunkInner) -> Self {
+      let thunk = Self(ThunkCell::new(inner).arc());
+
+      scopes.capture(&WeakThunk(Arc::downgrade(&thunk.0)));
+      thunk
    }
 
    pub async fn argument(&self, argument: Value) -> Option<Self> {
       let inner = self.0.read().await.dupe();
 
-      let ThunkInner::NeedsArgument {
-         location,
-         code,
-         scopes,
-         attached_id,
-      } = inner
-      else {
-         return None;
-      };
+      let new = match inner {
+         ThunkInner::NeedsArgumentNative { location, code } => {
+            ThunkInner::ForceableNative {
+               location,
+               code,
+               stack: Some(argument),
+            }
+         },
 
-      Some(Thunk(
-         RwLock::new(ThunkInner::Forceable {
+         ThunkInner::NeedsArgument {
             location,
             code,
-            stack: Some(argument),
             scopes,
             attached_id,
-         })
-         .arc(),
-      ))
+         } => {
+            return Some(Self::capturing(&scopes.dupe(), ThunkInner::Forceable {
+               location,
+               code,
+               stack: Some(argument),
+               scopes,
+               attached_id,
+            }));
+         },
+
+         _ => return None,
+      };
+
+      Some(Thunk(ThunkCell::new(new).arc()))
    }
 
-   pub async fn get(&self) -> (Option<(ScopeId, Scopes)>, Value) {
-      if let ThunkInner::Evaluated {
-         ref scopagate,
-         ref value,
-      } = *self.0.read().await
-      {
-         (scopagate.dupe(), value.dupe())
-      } else {
-         (None, Value::from(self.dupe()))
+   pub async fn get(&self) -> (Option<(ScopeId, value::Attributes)>, Value) {
+      match *self.0.read().await {
+         ThunkInner::Evaluated {
+            ref scopagate,
+            ref value,
+         } => (scopagate.dupe(), value.dupe()),
+
+         ThunkInner::Volatile {
+            ref location,
+            ref code,
+         } => {
+            let thunk = Self(
+               ThunkCell::new(ThunkInner::ForceableNative {
+                  location: location.dupe(),
+                  code:     code.dupe(),
+                  stack:    None,
+               })
+               .arc(),
+            );
+
+            (None, Value::from(thunk))
+         },
+
+         _ => (None, Value::from(self.dupe())),
+      }
+   }
+
+   /// Returns the address of the thunk, which identifies it while it is alive.
+   pub(super) fn address(&self) -> usize {
+      Arc::as_ptr(&self.0).addr()
+   }
+
+   /// Queues what the thunk keeps alive to be walked. Thunks that are being
+   /// forced are locked, so they are skipped.
+   pub(super) fn walk(&self, walk: &mut heap::Walk) {
+      let Ok(inner) = self.0.try_read() else {
+         return;
+      };
+
+      match *inner {
+         ThunkInner::NeedsArgumentNative { .. } | ThunkInner::Volatile { .. } => {},
+
+         ThunkInner::ForceableNative { ref stack, .. } => {
+            if let Some(ref value) = *stack {
+               walk.value(value);
+            }
+         },
+
+         ThunkInner::NeedsArgument { ref scopes, .. } => walk.scopes(scopes),
+
+         ThunkInner::Forceable {
+            ref stack,
+            ref scopes,
+            ..
+         } => {
+            if let Some(ref value) = *stack {
+               walk.value(value);
+            }
+
+            walk.scopes(scopes);
+         },
+
+         ThunkInner::Evaluated {
+            ref scopagate,
+            ref value,
+         } => {
+            if let Some((_, ref attributes)) = *scopagate {
+               walk.attributes(attributes);
+            }
+
+            walk.value(value);
+         },
       }
    }
 
@@ -212,10 +397,39 @@ impl Thunk {
          ThunkInner::Evaluated { .. }
             | ThunkInner::NeedsArgumentNative { .. }
             | ThunkInner::NeedsArgument { .. }
+            | ThunkInner::Volatile { .. }
       )
    }
 
+   /// Forces the thunk to weak head normal form.
+   ///
+   /// Thunks forced while evaluating this one are not forced recursively, but
+   /// through an explicit stack of frames, so arbitrarily deep chains of thunks
+   /// don't overflow the native stack.
    pub async fn force(&self, state: &State) {
+      let Some(frame) = self.enter(state).await else {
+         return;
+      };
+
+      let mut frames = vec![frame];
+
+      while let Some(frame) = frames.last_mut() {
+         if let Some(thunk) = frame.run(state).await {
+            frames.extend(thunk.enter(state).await);
+            continue;
+         }
+
+         frames
+            .pop()
+            .expect("frame was just run")
+            .exit(state)
+            .await;
+      }
+   }
+
+   /// Starts forcing the thunk. Returns the frame to run if the thunk needs to
+   /// execute code, otherwise the thunk is in weak head normal form after this.
+   async fn enter(&self, state: &State) -> Option<Frame> {
       let this = mem::replace(&mut *self.0.write().await, ThunkInner::Evaluated {
          scopagate: None,
          value:     Value::from(ThunkInner::INFINITE_RECURSION.with(Dupe::dupe)),
@@ -225,7 +439,8 @@ impl Thunk {
          // WHNF? Only real typemasterbaiters will get this.
          whnf @ (ThunkInner::Evaluated { .. }
          | ThunkInner::NeedsArgumentNative { .. }
-         | ThunkInner::NeedsArgument { .. }) => whnf.dupe(),
+         | ThunkInner::NeedsArgument { .. }
+         | ThunkInner::Volatile { .. }) => whnf.dupe(),
 
          ThunkInner::ForceableNative {
             location,
@@ -236,7 +451,7 @@ impl Thunk {
 
             ThunkInner::Evaluated {
                scopagate: None,
-               value:     code(argument),
+               value:     code(state, argument).await,
             }
          },
 
@@ -244,278 +459,891 @@ impl Thunk {
             location,
             code,
             stack,
-            mut scopes,
+            scopes,
             attached_id,
          } => {
             *self.0.write().await = ThunkInner::black_hole(location.dupe());
 
-            collect_vec!(mut stack);
+            if let Some(profiler) = state.profiler.as_ref() {
+               profiler.enter(&code, &location);
+            }
 
-            let items = &mut code.iter().peekable();
-            while let Some((index, item)) = items.next() {
-               let operation = *item.as_operation().expect("next item must be an operation");
+            collect_vec!(stack);
 
-               match operation {
-                  Operation::Push => {
-                     let value_index = items
-                        .next()
-                        .expect("push must not be the last item")
-                        .1
-                        .as_argument()
-                        .expect("push must have an argument")
-                        .as_value_index()
-                        .expect("push argument must be a value index");
-
-                     stack.push(match &code[value_index] {
-                        &Value::NeedsArgumentToThunk(ref thunk_code) => {
-                           Value::from(
-                              Thunk::needs_argument(thunk_code.dupe())
-                                 .scopes(scopes.dupe())
-                                 .location(code.read_operation(index).0),
-                           )
-                        },
-                        &Value::Thunkable(ref thunk_code) => {
-                           Value::from(
-                              Thunk::forceable(thunk_code.dupe())
-                                 .scopes(scopes.dupe())
-                                 .location(code.read_operation(index).0),
-                           )
-                        },
-
-                        other => other.dupe(),
-                     });
-                  },
-                  Operation::Pop => {
-                     stack
-                        .pop()
-                        .expect("pop operation must not be called on empty stack");
-                  },
-                  Operation::Swap => {
-                     let &mut [.., ref mut x, ref mut y] = &mut *stack else {
-                        unreachable!("swap must be called on stack of length 2 or higher");
-                     };
+            return Some(Frame {
+               thunk: self.dupe(),
+               code,
+               index: ByteIndex::zero(),
+               tick: None,
 
-                     mem::swap(x, y);
-                  },
-                  operation @ (Operation::Jump | Operation::JumpIf | Operation::JumpIfError) => {
-                     let target_index = items
-                        .next()
-                        .expect("jump must not be the last item")
-                        .1
-                        .as_argument()
-                        .expect("jump must have an argument")
-                        .as_byte_index()
-                        .expect("jump argument must be a byte index");
-
-                     match operation {
-                        Operation::Jump => {},
-                        Operation::JumpIf => {
-                           let value = stack.last_mut().expect(
-                              "jump-if and jump-if-error must be called on stack with at least \
-                               one item",
-                           );
-
-                           let &mut Value::Boolean(value) = value else {
-                              *value = Value::from(
-                                 ThunkInner::NOT_BOOLEAN
-                                    .with(Dupe::dupe)
-                                    .append_trace(code.read_operation(index).0)
-                                    .arc(),
-                              );
-                              continue;
-                           };
-
-                           if !value {
-                              continue;
-                           }
-                        },
-                        Operation::JumpIfError => {
-                           let value = stack.last_mut().expect(
-                              "jump-if and jump-if-error must be called on stack with at least \
-                               one item",
-                           );
-
-                           let &mut Value::Error(ref error) = value else {
-                              continue;
-                           };
-
-                           *value =
-                              Value::from(error.append_trace(code.read_operation(index).0).arc());
-                        },
-                        _ => unreachable!(),
-                     }
+               stack,
+               forcing: None,
 
-                     while items
-                        .next_if(|&(next_index, _)| next_index != target_index)
-                        .is_some()
-                     {}
-                  },
-                  Operation::Force => {
-                     let mut value = stack
-                        .pop()
-                        .expect("force must not be called on an empty stack");
-
-                     while let Value::Thunk(ref thunk) = value {
-                        if !thunk.is_whnf().await {
-                           Box::pin(thunk.force(state)).await;
-                           continue;
-                        }
-
-                        let (scopagate, value_new) = thunk.get().await;
-
-                        if let Some((scope_id, scopes_new)) = scopagate
-                           && scope_id == scopes.tip().expect(EXPECT_SCOPE).id()
-                        {
-                           scopes = scopes.merge_tip_from(&scopes_new);
-                        }
-
-                        let should_break = matches!(value_new, Value::Thunk(ref thunk_new) if Arc::ptr_eq(&thunk.0, &thunk_new.0));
-                        value = value_new;
-
-                        if should_break {
-                           break;
-                        }
-                     }
+               scopes,
+               attached_id,
+               bound: false,
+            });
+         },
+      };
 
-                     stack.push(value);
-                  },
-                  Operation::ScopeStart => {
-                     scopes = scopes.push(Scope::from(&value::attributes::new! {}));
-                  },
-                  Operation::ScopeEnd => {
-                     scopes = scopes
-                        .pop()
-                        .expect("scope-end must not be called with no scopes");
-                  },
-                  Operation::ScopePush => {
-                     stack.push(Value::from(
-                        scopes.tip().expect(EXPECT_SCOPE).attributes().dupe(),
-                     ));
-                  },
-                  Operation::ScopeSwap => {
-                     let value = stack
-                        .last_mut()
-                        .expect("scope-swap must not be called on a empty stack");
+      *self.0.write().await = new;
 
-                     let &mut Value::Attributes(ref mut value) = value else {
-                        *value = Value::from(
-                           ThunkInner::NOT_ATTRIBUTES
-                              .with(Dupe::dupe)
-                              .append_trace(code.read_operation(index).0)
-                              .arc(),
-                        );
-                        continue;
-                     };
+      None
+   }
+}
 
-                     let mut scope = scopes.tip().expect(EXPECT_SCOPE).attributes().dupe();
-                     mem::swap(&mut scope, value);
-                     let used_attributes = scope;
+impl Value {
+   /// Forces the value to weak head normal form, unwrapping evaluated thunks.
+   /// Thunks that need an argument are returned as-is.
+   pub async fn forced(self, state: &State) -> Value {
+      let mut value = self;
 
-                     scopes = scopes
-                        .pop()
-                        .expect(EXPECT_SCOPE)
-                        .push(Scope::from(&used_attributes));
-                  },
-                  Operation::Interpolate => todo!(),
-                  Operation::Resolve => {
-                     let reference = stack
-                        .last_mut()
-                        .expect("resolve must not be called on an empty stack");
-
-                     let &mut Value::Reference(ref identifier) = reference else {
-                        unreachable!("resolve must be called on an identifier");
-                     };
+      while let Value::Thunk(ref thunk) = value {
+         thunk.force(state).await;
 
-                     let value = scopes.get(identifier).duped().unwrap_or_else(|| {
-                        Value::from(
-                           value::Error::new(value::SString::from(&*format!(
-                              "undefined value: '{identifier}'",
-                              identifier = &**identifier,
-                           )))
-                           .append_trace(code.read_operation(index).0)
-                           .arc(),
-                        )
-                     });
+         let (_, value_new) = thunk.get().await;
+
+         let should_break = matches!(value_new, Value::Thunk(ref thunk_new) if Arc::ptr_eq(&thunk.0, &thunk_new.0));
+         value = value_new;
 
-                     *reference = value;
+         if should_break {
+            break;
+         }
+      }
+
+      value
+   }
+
+   /// Forces the value and everything it contains, returning a value that has
+   /// no thunks in it apart from the ones that need an argument.
+   pub async fn forced_deep(self, state: &State) -> Value {
+      match self.forced(state).await {
+         Value::Attributes(attributes) => {
+            // Start from the attributes to keep their documentation.
+            let mut forced = attributes.dupe();
+
+            for (name, value) in &attributes.0 {
+               let value = Box::pin(value.dupe().forced_deep(state)).await;
+               forced = forced.insert(name.dupe(), value);
+            }
+
+            Value::from(forced)
+         },
+
+         Value::Cons(cons) => {
+            let mut items = Vec::new();
+
+            // Iterate the tail so long lists don't recurse.
+            let mut tail = Value::Cons(cons);
+            while let Value::Cons(cons) = tail {
+               items.push(Box::pin(cons.0.dupe().forced_deep(state)).await);
+               tail = cons.1.dupe().forced(state).await;
+            }
+
+            items.into_iter().rev().fold(tail, |tail, head| {
+               Value::from(value::Cons::new(head, tail).arc())
+            })
+         },
+
+         value => value,
+      }
+   }
+}
+
+/// The execution state of a [`ThunkInner::Forceable`] that is being forced.
+struct Frame {
+   thunk: Thunk,
+   code:  Arc<Code>,
+   index: ByteIndex,
+   tick:  Option<profile::Tick>,
+
+   stack: Vec<Value>,
+   /// The value a force operation is in the middle of forcing.
+   forcing: Option<Value>,
+
+   scopes:      Scopes,
+   attached_id: ScopeId,
+   /// Whether anything was bound in the scope the thunk was attached to.
+   bound:       bool,
+}
+
+impl Frame {
+   /// Executes operations until the end of the code is reached, in which case
+   /// [`None`] is returned, or until a thunk needs to be forced, in which case
+   /// it is returned and the frame must be run again after forcing it.
+   async fn run(&mut self, state: &State) -> Option<Thunk> {
+      let profiler = state.profiler.as_ref();
+
+      loop {
+         if let Some(value) = self.forcing.take()
+            && let Some(thunk) = self.force(value).await
+         {
+            return Some(thunk);
+         }
+
+         let (operation, argument, next) = self.code.read(self.index)?;
+         let index = mem::replace(&mut self.index, next);
+
+         if let Some(budget) = state.budget.as_ref()
+            && !budget.spend()
+         {
+            self.stack.clear();
+            self.stack.push(Value::from(
+               ThunkInner::BUDGET_SPENT
+                  .with(Dupe::dupe)
+                  .append_trace(self.code.read_operation(index).0)
+                  .arc(),
+            ));
+
+            return None;
+         }
+
+         if let Some(profiler) = profiler {
+            profiler.tick(&mut self.tick, &self.code, &self.code.read_operation(index).0);
+         }
+
+         match operation {
+            Operation::Push => {
+               let value_index = argument
+                  .expect("push must have an argument")
+                  .as_value_index()
+                  .expect("push argument must be a value index");
+
+               let value = match &self.code[value_index] {
+                  &Value::NeedsArgumentToThunk(ref thunk_code) => {
+                     Value::from(
+                        Thunk::needs_argument(thunk_code.dupe())
+                           .scopes(self.scopes.dupe())
+                           .location(self.code.read_operation(index).0),
+                     )
                   },
-                  Operation::AssertBoolean => {
-                     let value = stack
-                        .last_mut()
-                        .expect("assert-boolean must not be called on an empty stack");
+                  &Value::Thunkable(ref thunk_code) => {
+                     Value::from(
+                        Thunk::forceable(thunk_code.dupe())
+                           .scopes(self.scopes.dupe())
+                           .location(self.code.read_operation(index).0),
+                     )
+                  },
+
+                  other => other.dupe(),
+               };
+
+               self.stack.push(value);
+            },
+            Operation::Pop => {
+               self
+                  .stack
+                  .pop()
+                  .expect("pop operation must not be called on empty stack");
+            },
+            Operation::Swap => {
+               let &mut [.., ref mut x, ref mut y] = &mut *self.stack else {
+                  unreachable!("swap must be called on stack of length 2 or higher");
+               };
+
+               mem::swap(x, y);
+            },
+            operation @ (Operation::Jump | Operation::JumpIf | Operation::JumpIfError) => {
+               let target_index = argument
+                  .expect("jump must have an argument")
+                  .as_byte_index()
+                  .expect("jump argument must be a byte index");
 
-                     let &mut Value::Boolean(_) = value else {
+               match operation {
+                  Operation::Jump => {},
+                  Operation::JumpIf => {
+                     let value = self.stack.last_mut().expect(
+                        "jump-if and jump-if-error must be called on stack with at least one \
+                         item",
+                     );
+
+                     let &mut Value::Boolean(value) = value else {
                         *value = Value::from(
-                           ThunkInner::NOT_BOOLEAN
-                              .with(Dupe::dupe)
-                              .append_trace(code.read_operation(index).0)
+                           value::Error::expected("boolean", value)
+                              .append_trace(self.code.read_operation(index).0)
                               .arc(),
                         );
                         continue;
                      };
+
+                     if !value {
+                        continue;
+                     }
                   },
-                  Operation::Construct => {
-                     let tail = stack
-                        .pop()
-                        .expect("construct must be called on a stack with 2 items or more");
-                     let head = stack
-                        .pop()
-                        .expect("construct must be called on a stack with 2 items or more");
-
-                     stack.push(Value::from(value::Cons(head, tail).arc()));
+                  Operation::JumpIfError => {
+                     let value = self.stack.last_mut().expect(
+                        "jump-if and jump-if-error must be called on stack with at least one \
+                         item",
+                     );
+
+                     let &mut Value::Error(ref error) = value else {
+                        continue;
+                     };
+
+                     *value =
+                        Value::from(error.append_trace(self.code.read_operation(index).0).arc());
                   },
-                  Operation::Call => {
-                     let argument = stack.pop().expect("call must not be called on empty stack");
-
-                     match stack.pop().expect("call must not be called on empty stack") {
-                        Value::Thunk(thunk) if let Some(thunk) = thunk.argument(argument).await => {
-                           stack.push(Value::from(thunk));
-                        },
-
-                        _ => {
-                           stack.push(Value::from(
-                              ThunkInner::NOT_LAMBDA
-                                 .with(Dupe::dupe)
-                                 .append_trace(code.read_operation(index).0)
-                                 .arc(),
-                           ));
-                        },
-                     }
+                  _ => unreachable!(),
+               }
+
+               self.index = target_index;
+            },
+            Operation::Force => {
+               let value = self
+                  .stack
+                  .pop()
+                  .expect("force must not be called on an empty stack");
+
+               self.forcing = Some(value);
+            },
+            Operation::ScopeStart => {
+               if let Some(profiler) = profiler {
+                  profiler.allocation(
+                     &self.code,
+                     &self.code.read_operation(index).0,
+                     profile::Allocation::Attributes,
+                  );
+               }
+
+               self.scopes = self.scopes.push(Scope::open());
+            },
+            Operation::ScopeEnd => {
+               self.scopes.tip().expect(EXPECT_SCOPE).close();
+
+               self.scopes = self
+                  .scopes
+                  .pop()
+                  .expect("scope-end must not be called with no scopes");
+            },
+            Operation::ScopePush => {
+               // Closing the scope returns nothing if it was already sealed,
+               // so sealing twice doesn't do anything.
+               let scope = self.scopes.tip().expect(EXPECT_SCOPE);
+               for thunk in scope.close() {
+                  thunk.seal(scope).await;
+               }
+
+               self.stack.push(Value::from(scope.attributes().dupe()));
+            },
+            Operation::ScopeSwap => {
+               let value = self
+                  .stack
+                  .last_mut()
+                  .expect("scope-swap must not be called on a empty stack");
+
+               let &mut Value::Attributes(ref mut value) = value else {
+                  *value = Value::from(
+                     value::Error::expected("attributes", value)
+                        .append_trace(self.code.read_operation(index).0)
+                        .arc(),
+                  );
+                  continue;
+               };
+
+               let tip = self.scopes.tip().expect(EXPECT_SCOPE);
+
+               let mut scope = tip.attributes().dupe();
+               mem::swap(&mut scope, value);
+
+               // Keep the identity of the scope, so it is still the same scope
+               // once it is swapped back in, and gets sealed.
+               let tip = tip.with_attributes(scope);
+
+               self.scopes = self.scopes.pop().expect(EXPECT_SCOPE).push(tip);
+               self.bound |= self.is_attached();
+            },
+            Operation::Interpolate => todo!(),
+            Operation::Resolve | Operation::ResolveFrom => {
+               let depth = argument.as_ref().and_then(Argument::as_u16).unwrap_or(0);
+
+               let reference = self
+                  .stack
+                  .last_mut()
+                  .expect("resolve must not be called on an empty stack");
+
+               let &mut Value::Reference(ref identifier) = reference else {
+                  unreachable!("resolve must be called on an identifier");
+               };
+
+               let value = self
+                  .scopes
+                  .get_from(usize::from(depth), identifier)
+                  .duped()
+                  .unwrap_or_else(|| {
+                     Value::from(
+                        value::Error::new(value::SString::from(&*format!(
+                           "undefined value: '{identifier}'",
+                           identifier = &**identifier,
+                        )))
+                        .append_trace(self.code.read_operation(index).0)
+                        .arc(),
+                     )
+                  });
+
+               *reference = value;
+            },
+            Operation::AssertBoolean => {
+               let value = self
+                  .stack
+                  .last_mut()
+                  .expect("assert-boolean must not be called on an empty stack");
+
+               let &mut Value::Boolean(_) = value else {
+                  *value = Value::from(
+                     value::Error::expected("boolean", value)
+                        .append_trace(self.code.read_operation(index).0)
+                        .arc(),
+                  );
+                  continue;
+               };
+            },
+            Operation::AssertShape => {
+               let Value::String(shape) = self
+                  .stack
+                  .pop()
+                  .expect("assert-shape must be called on a stack with 2 items or more")
+               else {
+                  unreachable!("assert-shape must be called with a shape name");
+               };
+
+               let shape = lode::Shape::from_name(&shape)
+                  .expect("assert-shape must be called with a valid shape name");
+
+               let value = self
+                  .stack
+                  .last_mut()
+                  .expect("assert-shape must be called on a stack with 2 items or more");
+
+               if let &mut Value::Error(_) = value {
+                  continue;
+               }
+
+               if !value.is(shape) {
+                  *value = Value::from(
+                     value::Error::expected(Value::shape_kind(shape), value)
+                        .append_trace(self.code.read_operation(index).0)
+                        .arc(),
+                  );
+               }
+            },
+            Operation::Construct => {
+               let tail = self
+                  .stack
+                  .pop()
+                  .expect("construct must be called on a stack with 2 items or more");
+               let head = self
+                  .stack
+                  .pop()
+                  .expect("construct must be called on a stack with 2 items or more");
+
+               if let Some(profiler) = profiler {
+                  profiler.allocation(
+                     &self.code,
+                     &self.code.read_operation(index).0,
+                     profile::Allocation::Cons,
+                  );
+               }
+
+               self
+                  .stack
+                  .push(Value::from(value::Cons::new(head, tail).arc()));
+            },
+            Operation::Call => {
+               let argument = self
+                  .stack
+                  .pop()
+                  .expect("call must not be called on empty stack");
+
+               match self
+                  .stack
+                  .pop()
+                  .expect("call must not be called on empty stack")
+               {
+                  Value::Thunk(thunk) if let Some(thunk) = thunk.argument(argument).await => {
+                     self.stack.push(Value::from(thunk));
                   },
-                  Operation::Equal => {
-                     let right = stack
-                        .pop()
-                        .expect("equal must be called on a stack with 2 items or more");
-                     let left = stack
-                        .pop()
-                        .expect("equal must be called on a stack with 2 items or more");
-
-                     let (equal, scope_new) = Value::equals(&left, &right);
-
-                     stack.push(Value::from(equal));
-
-                     scopes = scopes
-                        .pop()
-                        .expect(EXPECT_SCOPE)
-                        .push(scopes.tip().expect(EXPECT_SCOPE).merge(&scope_new));
+
+                  other => {
+                     self.stack.push(Value::from(
+                        value::Error::expected("lambda", &other)
+                           .append_trace(self.code.read_operation(index).0)
+                           .arc(),
+                     ));
                   },
-                  Operation::All => todo!(),
-                  Operation::Any => todo!(),
                }
-            }
+            },
+            Operation::Equal => {
+               let right = self
+                  .stack
+                  .pop()
+                  .expect("equal must be called on a stack with 2 items or more");
+               let left = self
+                  .stack
+                  .pop()
+                  .expect("equal must be called on a stack with 2 items or more");
+
+               // Errors propagate through equality rather than being compared.
+               if let Value::Error(_) = left {
+                  self.stack.push(left);
+                  continue;
+               }
+               if let Value::Error(_) = right {
+                  self.stack.push(right);
+                  continue;
+               }
 
-            let len = stack.len();
-            let Ok([value]) = <[_; 1]>::try_from(stack) else {
-               unreachable!("stack must have exactly one item left, has {len}");
-            };
+               let Some((equal, scope_new)) = Value::equals(&left, &right) else {
+                  self.stack.push(Value::from(
+                     value::Error::new(value::SString::from(&*format!(
+                        "can't compare {left} with {right} yet",
+                        left = left.kind(),
+                        right = right.kind(),
+                     )))
+                     .append_trace(self.code.read_operation(index).0)
+                     .arc(),
+                  ));
+
+                  continue;
+               };
+
+               self.stack.push(Value::from(equal));
+
+               // Comparisons that don't bind anything leave the scope as is.
+               if scope_new.is_empty() {
+                  continue;
+               }
 
-            ThunkInner::Evaluated {
-               scopagate: Some((attached_id, scopes)),
-               value,
-            }
+               if let Some(profiler) = profiler {
+                  profiler.allocation(
+                     &self.code,
+                     &self.code.read_operation(index).0,
+                     profile::Allocation::Attributes,
+                  );
+               }
+
+               self.scopes = self.scopes.merge_tip(&scope_new);
+               self.bound |= self.is_attached();
+            },
+            Operation::Document => {
+               let documentation = self
+                  .stack
+                  .pop()
+                  .expect("document must be called on a stack with 2 items or more");
+
+               // Only binds that are known before they are forced can be
+               // documented.
+               let (&Value::Bind(ref name), Value::String(documentation)) = (
+                  self
+                     .stack
+                     .last()
+                     .expect("document must be called on a stack with 2 items or more"),
+                  documentation,
+               ) else {
+                  continue;
+               };
+
+               let tip = self.scopes.tip().expect(EXPECT_SCOPE);
+               let tip = tip.with_attributes(tip.attributes().document(name.dupe(), documentation));
+
+               self.scopes = self.scopes.pop().expect(EXPECT_SCOPE).push(tip);
+               self.bound |= self.is_attached();
+            },
+            Operation::All => todo!(),
+            Operation::Any => todo!(),
+         }
+      }
+   }
+
+   /// Continues forcing the value of a force operation. Returns the thunk that
+   /// needs to be forced before it can continue, if any.
+   async fn force(&mut self, mut value: Value) -> Option<Thunk> {
+      while let Value::Thunk(ref thunk) = value {
+         if !thunk.is_whnf().await {
+            let thunk = thunk.dupe();
+            self.forcing = Some(value);
+            return Some(thunk);
+         }
+
+         let (scopagate, value_new) = thunk.get().await;
+
+         if let Some((scope_id, attributes)) = scopagate
+            && scope_id == self.scopes.tip().expect(EXPECT_SCOPE).id()
+         {
+            self.scopes = self.scopes.merge_tip(&attributes);
+            self.bound |= self.is_attached();
+         }
+
+         let should_break = matches!(value_new, Value::Thunk(ref thunk_new) if Arc::ptr_eq(&thunk.0, &thunk_new.0));
+         value = value_new;
+
+         if should_break {
+            break;
+         }
+      }
+
+      self.stack.push(value);
+      None
+   }
+
+   /// Finishes forcing the thunk of the frame, storing the value that is left
+   /// on the stack.
+   async fn exit(mut self, state: &State) {
+      if let Some(profiler) = state.profiler.as_ref() {
+         profiler.exit(&mut self.tick);
+      }
+
+      let len = self.stack.len();
+      let Ok([value]) = <[_; 1]>::try_from(self.stack) else {
+         unreachable!("stack must have exactly one item left, has {len}");
+      };
+
+      let scopagate = self.bound.then(|| {
+         (
+            self.attached_id,
+            self.scopes.tip().expect(EXPECT_SCOPE).attributes().dupe(),
+         )
+      });
+
+      *self.thunk.0.write().await = ThunkInner::Evaluated { scopagate, value };
+   }
+
+   /// Whether the tip of the scopes is the scope the thunk was attached to.
+   fn is_attached(&self) -> bool {
+      self.scopes.tip().expect(EXPECT_SCOPE).id() == self.attached_id
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use ranged::Span;
+   use rpds::ListSync as List;
+
+   use super::*;
+   use crate::{
+      Budget,
+      builtin::evaluate_with,
+   };
+
+   #[tokio::test]
+   async fn force_deep_cons_chain() {
+      const LEN: usize = 1_000_000;
+
+      let state = State::new();
+
+      let path = value::Path::rootless(List::new_sync());
+      let span = Span::at(0_u32, 0_u32);
+      let location = value::Location::new(path.dupe(), span);
+
+      // Forces the argument, which is the tail, and constructs an item in front of it.
+      let code = {
+         let mut code = Code::new(path);
+
+         code.push_operation(span, Operation::Force);
+
+         let head = code.value(Value::Boolean(true));
+         code.push_operation(span, Operation::Push);
+         code.push_u64(*head as _);
+
+         code.push_operation(span, Operation::Swap);
+         code.push_operation(span, Operation::Construct);
+
+         code.arc()
+      };
+
+      let scopes = Scopes::new().push(Scope::new());
+
+      let mut list = Value::from(value::Nil);
+      for _ in 0..LEN {
+         let thunk = Thunk::needs_argument(code.dupe())
+            .scopes(scopes.dupe())
+            .location(location.dupe())
+            .argument(list)
+            .await
+            .expect("thunk must need an argument");
+
+         list = Value::from(thunk);
+      }
+
+      let Value::Thunk(thunk) = list else {
+         unreachable!("list must be a thunk");
+      };
+
+      thunk.force(&state).await;
+
+      let (_, mut value) = thunk.get().await;
+
+      let mut len = 0;
+      while let Value::Cons(cons) = value {
+         assert!(matches!(cons.0, Value::Boolean(true)));
+
+         len += 1;
+         value = cons.1.dupe();
+      }
+
+      assert!(matches!(value, Value::Nil(_)));
+      assert_eq!(len, LEN);
+   }
+
+   #[tokio::test]
+   async fn budget() {
+      let state = State {
+         budget: Some(Budget::new(1000)),
+         ..State::new()
+      };
+
+      let path = value::Path::rootless(List::new_sync());
+      let span = Span::at(0_u32, 0_u32);
+      let location = value::Location::new(path.dupe(), span);
+
+      // Pushes and pops forever.
+      let code = {
+         let mut code = Code::new(path);
+
+         let value = code.value(Value::Boolean(true));
+         code.push_operation(span, Operation::Push);
+         code.push_u64(*value as _);
+
+         code.push_operation(span, Operation::Pop);
+
+         code.push_operation(span, Operation::Jump);
+         code.push_u16(0);
+
+         code.arc()
+      };
+
+      let thunk = Thunk::forceable(code)
+         .scopes(Scopes::new().push(Scope::new()))
+         .location(location);
+
+      thunk.force(&state).await;
+
+      let (_, value) = thunk.get().await;
+
+      assert!(matches!(value, Value::Error(_)));
+      assert!(state.budget.as_ref().is_some_and(Budget::is_spent));
+   }
+
+   #[tokio::test]
+   async fn scopagate() {
+      let state = State::new();
+
+      let path = value::Path::rootless(List::new_sync());
+      let span = Span::at(0_u32, 0_u32);
+      let location = value::Location::new(path.dupe(), span);
+
+      let big = (0..1000).fold(Value::from(value::Nil), |tail, _| {
+         Value::from(value::Cons::new(Value::Boolean(true), tail).arc())
+      });
+
+      let scopes = Scopes::new().push(Scope::from(&value::attributes::new! { "big": big }));
+
+      // Pushes true, binding it to `x` first if asked to.
+      let code = |bind: bool| {
+         let mut code = Code::new(path.dupe());
+
+         if bind {
+            let name = code.value(Value::Bind(value::string::new!("x")));
+            code.push_operation(span, Operation::Push);
+            code.push_u64(*name as _);
+         }
+
+         let value = code.value(Value::Boolean(true));
+         code.push_operation(span, Operation::Push);
+         code.push_u64(*value as _);
+
+         if bind {
+            code.push_operation(span, Operation::Equal);
+         }
+
+         code.arc()
+      };
+
+      let pure = Thunk::forceable(code(false))
+         .scopes(scopes.dupe())
+         .location(location.dupe());
+      let binding = Thunk::forceable(code(true))
+         .scopes(scopes)
+         .location(location);
+
+      assert_eq!(Value::from(pure.dupe()).heap().cons, 1000);
+
+      pure.force(&state).await;
+      binding.force(&state).await;
+
+      // Thunks that didn't bind anything drop the scopes they captured.
+      assert_eq!(
+         Value::from(pure.dupe()).heap(),
+         value::Heap {
+            thunks:     1,
+            attributes: 0,
+            cons:       0,
          },
+      );
+      assert!(pure.get().await.0.is_none());
+
+      // Thunks that bound something keep the attributes of their scope only.
+      let (scopagate, _) = binding.get().await;
+      let (_, attributes) = scopagate.expect("binding thunk must propagate its scope");
+      assert!(attributes.get(&value::string::new!("x")).is_some());
+      assert_eq!(Value::from(binding).heap().attributes, 1);
+   }
+
+   #[tokio::test]
+   async fn sealed_scopes_are_freed() {
+      use cab_syntax as syntax;
+
+      let state = State::new();
+
+      let source = "{ @a = b; @b = [ 1 ] }";
+      let parse = state.parse_oracle.parse(syntax::tokenize(source));
+      let lower = syntax::LowerOracle::new().lower(parse.expression.as_ref());
+
+      let path = value::Path::rootless(List::new_sync());
+      let code = state
+         .compile_oracle
+         .compile(lower.expression())
+         .path(path.dupe())
+         .code;
+
+      let thunk = Thunk::forceable(code.arc())
+         .scopes(Scopes::new().push(Scope::new()))
+         .location(value::Location::new(path, Span::at(0_u32, source.len())));
+
+      let Value::Attributes(attributes) = Value::from(thunk).forced(&state).await else {
+         panic!("body must evaluate to attributes");
       };
 
-      *self.0.write().await = new;
+      let Some(&Value::Thunk(ref a)) = attributes.get(&value::string::new!("a")) else {
+         panic!("a must not be forced yet");
+      };
+
+      // The unforced binding refers to a binding that comes after it, but
+      // doesn't keep the body alive.
+      let weak = Arc::downgrade(&a.0);
+
+      drop(attributes);
+
+      assert_eq!(weak.strong_count(), 0);
+   }
+
+   #[tokio::test]
+   async fn seal_twice() {
+      let state = State::new();
+
+      let path = value::Path::rootless(List::new_sync());
+      let span = Span::at(0_u32, 0_u32);
+
+      let code = {
+         let mut code = Code::new(path.dupe());
+
+         code.push_operation(span, Operation::ScopeStart);
+         code.push_operation(span, Operation::ScopePush);
+         code.push_operation(span, Operation::Pop);
+         code.push_operation(span, Operation::ScopePush);
+         code.push_operation(span, Operation::ScopeEnd);
+
+         code.arc()
+      };
+
+      let thunk = Thunk::forceable(code)
+         .scopes(Scopes::new().push(Scope::new()))
+         .location(value::Location::new(path, span));
+
+      thunk.force(&state).await;
+
+      assert!(matches!(thunk.get().await.1, Value::Attributes(_)));
+   }
+
+   /// Returns the message of the error and the spans it was traced through.
+   #[track_caller]
+   fn error(value: &Value) -> (String, Vec<Span>) {
+      let &Value::Error(ref error) = value else {
+         panic!("expected error, got {kind}", kind = value.kind());
+      };
+
+      let &Value::String(ref message) = &error.value else {
+         panic!("error must be a string");
+      };
+
+      let mut spans = Vec::new();
+      let mut trace = error.trace.dupe();
+      while let Value::Cons(cons) = trace {
+         if let &Value::Location(ref location) = &cons.0 {
+            spans.push(location.span);
+         }
+
+         trace = cons.1.dupe();
+      }
+
+      ((**message).to_owned(), spans)
+   }
+
+   #[tokio::test]
+   async fn shapes() {
+      assert!(matches!(
+         evaluate_with("{ @port :: Integer = 8080 }.port", true).await,
+         Value::Integer(_),
+      ));
+      assert!(matches!(
+         evaluate_with(r#"(@name :: String => name) "cab""#, true).await,
+         Value::String(_),
+      ));
+
+      let (message, spans) =
+         error(&evaluate_with(r#"{ @port :: Integer = "80" }.port"#, true).await);
+      assert_eq!(message, "expected integer, got string");
+      assert!(spans.contains(&Span::new(11_u32, 18_u32)));
+
+      let (message, spans) = error(&evaluate_with("(@name :: String => name) 1", true).await);
+      assert_eq!(message, "expected string, got integer");
+      assert!(spans.contains(&Span::new(10_u32, 16_u32)));
+
+      let (message, _) = error(&evaluate_with("{ @a = 1 }.a.b", true).await);
+      assert_eq!(message, "expected attributes, got integer");
+   }
+
+   #[tokio::test]
+   async fn equality() {
+      for (source, expected) in [
+         ("1 = 1", true),
+         ("1 = 2", false),
+         ("1.5 = 1.5", true),
+         ("'a' = 'b'", false),
+         (r#""a" = "a""#, true),
+         ("true = true", true),
+         ("[] = []", true),
+         ("null = null", true),
+         // Scalars of different kinds are never equal.
+         (r#"1 = "1""#, false),
+         ("1 = 1.0", false),
+         ("'a' = []", false),
+      ] {
+         for optimize in [false, true] {
+            assert!(
+               matches!(
+                  evaluate_with(source, optimize).await,
+                  Value::Boolean(equal) if equal == expected,
+               ),
+               "{source}",
+            );
+         }
+      }
+
+      // Errors propagate through equality rather than being compared.
+      for source in ["{ @a = 1 }.b = 1", "1 = { @a = 1 }.b", "{}.a = {}.a"] {
+         assert!(
+            matches!(evaluate_with(source, true).await, Value::Error(_)),
+            "{source}",
+         );
+      }
+
+      // Lists and attributes can't be compared with each other yet.
+      for (source, expected) in [
+         ("[ 1 ] = [ 1 ]", "can't compare cons with cons yet"),
+         ("{} = {}", "can't compare attributes with attributes yet"),
+      ] {
+         let (message, _) = error(&evaluate_with(source, true).await);
+         assert_eq!(message, expected, "{source}");
+      }
    }
 }
@@ -0,0 +1,415 @@
+use cyn::{
+   ResultExt as _,
+   bail,
+};
+use rustc_hash::FxHashMap;
+use ust::{
+   Display as _,
+   terminal,
+};
+
+use crate::{
+   Argument,
+   ByteIndex,
+   Code,
+   Operation,
+   Value,
+};
+
+const ENCODED_U16_OPERATION_LEN: usize = 1 + 0_u16.to_le_bytes().len();
+
+/// The state of the machine before an operation is executed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Depths {
+   stack:  usize,
+   scopes: usize,
+}
+
+impl Code {
+   /// Checks that the code can be executed without violating the assumptions
+   /// of the virtual machine:
+   ///
+   /// - Every operation and argument is well formed and jumps land on the
+   ///   start of an operation or the end of the code.
+   /// - Every pushed value exists.
+   /// - No operation pops more items than the stack has, and the stack has
+   ///   exactly one item left at the end, regardless of the path taken.
+   /// - Scope starts and ends are balanced on every path.
+   ///
+   /// The codes of the thunks the code creates are verified too. The code
+   /// itself is verified as the code of a thunk that doesn't need an argument.
+   ///
+   /// The compiler only emits valid code, which is verified in debug builds.
+   /// Code that comes from elsewhere, such as deserialized code, must be
+   /// verified before it is forced.
+   pub fn verify(&self) -> cyn::Result<()> {
+      verify(self, 0)
+   }
+
+   /// Panics if the code is not valid. Does nothing in release builds.
+   #[track_caller]
+   pub(crate) fn verify_debug(&self) {
+      if !cfg!(debug_assertions) {
+         return;
+      }
+
+      if let Err(chain) = self.verify() {
+         let mut message = String::new();
+
+         {
+            let writer = &mut terminal::writer(terminal::StyleChoice::Never, &mut message);
+            let _ = chain.display_styled(writer);
+         }
+
+         panic!("code must be valid: {message}");
+      }
+   }
+}
+
+#[stacksafe::stacksafe]
+fn verify(code: &Code, stack_initial: usize) -> cyn::Result<()> {
+   let len = code.bytes().len();
+
+   // Decode every operation first, so jump targets can be checked.
+   let mut operations = FxHashMap::<usize, (Operation, Option<Argument>, ByteIndex)>::default();
+
+   let mut index = ByteIndex::zero();
+   while *index < len {
+      let byte = code.bytes()[*index];
+
+      let Ok(operation) = Operation::try_from(byte) else {
+         bail!("invalid operation {byte:#X} at {index:#X}", index = *index);
+      };
+
+      if matches!(
+         operation,
+         Operation::Jump | Operation::JumpIf | Operation::JumpIfError | Operation::ResolveFrom
+      ) && *index + ENCODED_U16_OPERATION_LEN > len
+      {
+         bail!("truncated argument of {operation:?} at {index:#X}", index = *index);
+      }
+
+      let (operation, argument, next) = code
+         .read(index)
+         .expect("index was checked to be in bounds");
+
+      if *next > len {
+         bail!("truncated argument of {operation:?} at {index:#X}", index = *index);
+      }
+
+      if let Some(Argument::ValueIndex(value_index)) = argument {
+         let Some(value) = code.values().get(*value_index) else {
+            bail!(
+               "{operation:?} at {index:#X} pushes value {value_index:#X}, but there are only \
+                {count} values",
+               index = *index,
+               value_index = *value_index,
+               count = code.values().len(),
+            );
+         };
+
+         let nested = match *value {
+            Value::Thunkable(ref code) => Some((code, 0)),
+            Value::NeedsArgumentToThunk(ref code) => Some((code, 1)),
+            _ => None,
+         };
+
+         if let Some((nested, stack_initial)) = nested {
+            verify(nested, stack_initial).chain_err_with(|| {
+               format!("invalid thunk code pushed at {index:#X}", index = *index)
+            })?;
+         }
+      }
+
+      operations.insert(*index, (operation, argument, next));
+      index = next;
+   }
+
+   // Then simulate the stack and scope depths across every path.
+   let mut depths = FxHashMap::<usize, Depths>::default();
+   let mut pending = vec![(ByteIndex::zero(), Depths {
+      stack:  stack_initial,
+      scopes: 0,
+   })];
+
+   while let Some((index, state)) = pending.pop() {
+      match depths.get(&*index) {
+         Some(&existing) if existing == state => continue,
+
+         Some(&existing) => {
+            bail!(
+               "inconsistent depths at {index:#X}: stack of {stack} and {scopes} scopes on one \
+                path, stack of {stack_other} and {scopes_other} scopes on another",
+               index = *index,
+               stack = existing.stack,
+               scopes = existing.scopes,
+               stack_other = state.stack,
+               scopes_other = state.scopes,
+            );
+         },
+
+         None => {
+            depths.insert(*index, state);
+         },
+      }
+
+      if *index == len {
+         if state.stack != 1 {
+            bail!(
+               "stack must have exactly one item at the end, has {stack}",
+               stack = state.stack,
+            );
+         }
+
+         if state.scopes != 0 {
+            bail!(
+               "every scope must be ended at the end, {scopes} are not",
+               scopes = state.scopes,
+            );
+         }
+
+         continue;
+      }
+
+      let Some(&(operation, argument, next)) = operations.get(&*index) else {
+         bail!(
+            "jump target {index:#X} is not the start of an operation",
+            index = *index,
+         );
+      };
+
+      let (pops, pushes) = match operation {
+         Operation::Push | Operation::ScopePush => (0, 1),
+         Operation::Pop => (1, 0),
+
+         Operation::Jump | Operation::ScopeStart | Operation::ScopeEnd => (0, 0),
+
+         Operation::Swap => (2, 2),
+
+         Operation::JumpIf
+         | Operation::JumpIfError
+         | Operation::Force
+         | Operation::ScopeSwap
+         | Operation::Resolve
+         | Operation::ResolveFrom
+         | Operation::AssertBoolean => (1, 1),
+
+         Operation::Interpolate => {
+            let count = argument
+               .as_ref()
+               .and_then(Argument::as_u64)
+               .expect("interpolate must have an u64 argument");
+
+            let Ok(count) = usize::try_from(count) else {
+               bail!(
+                  "{operation:?} at {index:#X} pops too many items",
+                  index = *index,
+               );
+            };
+
+            (count, 1)
+         },
+
+         Operation::AssertShape
+         | Operation::Construct
+         | Operation::Call
+         | Operation::Equal
+         | Operation::Document
+         | Operation::All
+         | Operation::Any => (2, 1),
+      };
+
+      if state.stack < pops {
+         bail!(
+            "{operation:?} at {index:#X} needs {pops} items on the stack, has {stack}",
+            index = *index,
+            stack = state.stack,
+         );
+      }
+
+      let scopes = match operation {
+         Operation::ScopeStart => state.scopes + 1,
+
+         Operation::ScopeEnd => {
+            let Some(scopes) = state.scopes.checked_sub(1) else {
+               bail!("{operation:?} at {index:#X} has no scope to end", index = *index);
+            };
+
+            scopes
+         },
+
+         _ => state.scopes,
+      };
+
+      let state = Depths {
+         stack: state.stack - pops + pushes,
+         scopes,
+      };
+
+      if let Some(target) = argument.as_ref().and_then(Argument::as_byte_index) {
+         if *target > len {
+            bail!(
+               "jump target {target:#X} of {operation:?} at {index:#X} is out of bounds",
+               target = *target,
+               index = *index,
+            );
+         }
+
+         pending.push((target, state));
+      }
+
+      if operation != Operation::Jump {
+         pending.push((next, state));
+      }
+   }
+
+   Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+   use cab_syntax as syntax;
+   use ranged::Span;
+   use rpds::ListSync as List;
+
+   use super::*;
+   use crate::{
+      CompileOracle,
+      value,
+   };
+
+   fn code(build: impl FnOnce(&mut Code)) -> Code {
+      let mut code = Code::new(value::Path::rootless(List::new_sync()));
+      build(&mut code);
+      code
+   }
+
+   fn push(code: &mut Code, value: Value) {
+      let index = code.value(value);
+      code.push_operation(Span::empty(0_u32), Operation::Push);
+      code.push_u64(*index as _);
+   }
+
+   #[test]
+   fn compiled() {
+      for source in [
+         "1",
+         "[ 1, (2) ]",
+         "if true then { @a = 1; @b = a } else @x => x",
+         "(@x => @y => x) 1 2",
+         "{ @a = 1 }.a",
+         "true && false || true",
+      ] {
+         let parse = syntax::ParseOracle::new().parse(syntax::tokenize(source));
+         let lower = syntax::LowerOracle::new().lower(parse.expression.as_ref());
+
+         let code = CompileOracle::new()
+            .compile(lower.expression())
+            .path(value::Path::rootless(List::new_sync()))
+            .code;
+
+         assert!(
+            code.verify().is_ok(),
+            "compiled code must be valid: {source}",
+         );
+         assert!(
+            code.optimized().verify().is_ok(),
+            "optimized code must be valid: {source}",
+         );
+      }
+   }
+
+   #[test]
+   fn stack() {
+      assert!(code(|code| push(code, Value::Boolean(true))).verify().is_ok());
+
+      // Empty.
+      assert!(code(|_| {}).verify().is_err());
+
+      // Two items left.
+      assert!(
+         code(|code| {
+            push(code, Value::Boolean(true));
+            push(code, Value::Boolean(true));
+         })
+         .verify()
+         .is_err()
+      );
+
+      // Underflow.
+      assert!(
+         code(|code| {
+            push(code, Value::Boolean(true));
+            code.push_operation(Span::empty(0_u32), Operation::Swap);
+         })
+         .verify()
+         .is_err()
+      );
+   }
+
+   #[test]
+   fn jumps() {
+      // Jumps into the argument of the push.
+      assert!(
+         code(|code| {
+            push(code, Value::Boolean(true));
+            code.push_operation(Span::empty(0_u32), Operation::Jump);
+            code.push_u16(1);
+         })
+         .verify()
+         .is_err()
+      );
+
+      // Paths with different stack depths.
+      assert!(
+         code(|code| {
+            push(code, Value::Boolean(true));
+            code.push_operation(Span::empty(0_u32), Operation::JumpIf);
+            let to_end = code.push_u16(u16::default());
+            push(code, Value::Boolean(true));
+            code.point_here(to_end);
+         })
+         .verify()
+         .is_err()
+      );
+   }
+
+   #[test]
+   fn scopes() {
+      assert!(
+         code(|code| {
+            code.push_operation(Span::empty(0_u32), Operation::ScopeStart);
+            push(code, Value::Boolean(true));
+         })
+         .verify()
+         .is_err()
+      );
+
+      assert!(
+         code(|code| {
+            push(code, Value::Boolean(true));
+            code.push_operation(Span::empty(0_u32), Operation::ScopeEnd);
+         })
+         .verify()
+         .is_err()
+      );
+   }
+
+   #[test]
+   fn values() {
+      assert!(
+         code(|code| {
+            code.push_operation(Span::empty(0_u32), Operation::Push);
+            code.push_u64(1);
+         })
+         .verify()
+         .is_err()
+      );
+
+      assert!(
+         code(|code| code.push_operation(Span::empty(0_u32), Operation::Force))
+            .verify()
+            .is_err()
+      );
+   }
+}
@@ -0,0 +1,408 @@
+//! Highlighting of source code, with context from the syntax tree.
+
+use std::fmt::Write as _;
+
+use derive_more::Display;
+use ranged::{
+   IntoSpan as _,
+   Span,
+};
+use ust::{
+   COLORS,
+   Write as _,
+   style::{
+      self,
+      StyledExt as _,
+   },
+   terminal,
+   write,
+};
+
+use crate::{
+   Kind::{
+      self,
+      *,
+   },
+   ParseOracle,
+   red,
+   token,
+   tokenize,
+};
+
+/// The role of a token, derived from the nodes around it.
+#[derive(Display, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Context {
+   /// An identifier or `@` that binds a name, like `@foo`.
+   #[display("bind")]
+   Bind,
+   /// An identifier that references a name in scope.
+   #[display("reference")]
+   Reference,
+   /// An identifier that is selected from attributes, like `bar` in `foo.bar`.
+   #[display("attribute")]
+   Attribute,
+   /// A documentation comment, which starts with `#|`.
+   #[display("documentation")]
+   Documentation,
+}
+
+/// A highlighted token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Highlight {
+   /// The kind of the token.
+   pub kind:    Kind,
+   /// The kind of the node the token is in.
+   pub node:    Kind,
+   /// The role of the token, if it has one.
+   pub context: Option<Context>,
+   /// The span of the token in the source.
+   pub span:    Span,
+}
+
+/// Returns the name of the kind without its prefix, in kebab case.
+fn class(kind: Kind) -> String {
+   let name = format!("{kind:?}");
+
+   name
+      .strip_prefix("TOKEN_")
+      .or_else(|| name.strip_prefix("NODE_"))
+      .unwrap_or(&name)
+      .to_ascii_lowercase()
+      .replace('_', "-")
+}
+
+impl Highlight {
+   /// Returns the classes of this token, separated by spaces. The first class
+   /// is the kind, contents also have the node they are in to tell strings
+   /// from paths, and the context comes last.
+   #[must_use]
+   pub fn classes(&self) -> String {
+      let mut classes = class(self.kind);
+
+      if self.kind == TOKEN_CONTENT {
+         classes.push(' ');
+         classes.push_str(&class(self.node));
+      }
+
+      if let Some(context) = self.context {
+         let _ = write!(classes, " {context}");
+      }
+
+      classes
+   }
+
+   /// Returns the terminal style of this token. The color is the one of its
+   /// kind in [`COLORS`], like `--dump-token color` uses, and the context is
+   /// told apart by the weight and decoration.
+   #[must_use]
+   pub fn style(&self) -> style::Style {
+      let style = COLORS[self.kind as usize];
+
+      match self.context {
+         Some(Context::Bind) => style.bold(),
+         Some(Context::Attribute) => style.underline(),
+         Some(Context::Documentation) => style.italic(),
+         Some(Context::Reference) | None => style,
+      }
+   }
+}
+
+/// Whether the node is right after a token of the given kind, ignoring
+/// trivia.
+fn is_after(node: &red::Node, kind: Kind) -> bool {
+   let mut previous = node.prev_sibling_or_token();
+
+   while let Some(red::ElementRef::Token(token)) = previous {
+      if !token.kind().is_trivia() {
+         return token.kind() == kind;
+      }
+
+      previous = token.prev_sibling_or_token();
+   }
+
+   false
+}
+
+/// Returns the context of the token, derived from the nodes around it.
+fn context(token: &red::Token) -> Option<Context> {
+   if token.kind() == TOKEN_COMMENT {
+      return <&token::Comment>::try_from(token)
+         .ok()
+         .and_then(token::Comment::documentation)
+         .map(|_| Context::Documentation);
+   }
+
+   let parent = token.parent();
+
+   match (token.kind(), parent.kind()) {
+      (TOKEN_AT, NODE_BIND) => Some(Context::Bind),
+
+      (kind, NODE_IDENTIFIER) if !kind.is_trivia() => {
+         Some(
+            if is_after(parent, TOKEN_COLON_COLON) {
+               // The name of the shape of a bind.
+               Context::Reference
+            } else if parent.parent().is_some_and(|node| node.kind() == NODE_BIND) {
+               Context::Bind
+            } else if is_after(parent, TOKEN_PERIOD) {
+               Context::Attribute
+            } else {
+               Context::Reference
+            },
+         )
+      },
+
+      _ => None,
+   }
+}
+
+/// Parses the source and returns its tokens in order, with the context of
+/// the nodes they are in.
+#[must_use]
+pub fn highlight(source: &str) -> Vec<Highlight> {
+   let parse = ParseOracle::new().parse(tokenize(source));
+
+   parse
+      .node
+      .descendants_with_tokens()
+      .filter_map(red::ElementRef::into_token)
+      .map(|token| {
+         Highlight {
+            kind:    token.kind(),
+            node:    token.parent().kind(),
+            context: context(token),
+            span:    token.span(),
+         }
+      })
+      .collect()
+}
+
+/// Calls the closure with the text of every token and its highlight. Spaces
+/// and text that isn't a part of any token are passed without a highlight.
+fn render(source: &str, mut emit: impl FnMut(Option<&Highlight>, &str)) {
+   let mut position = 0;
+
+   for highlight in highlight(source) {
+      let range = highlight.span.into_std();
+
+      if position < range.start {
+         emit(None, &source[position..range.start]);
+      }
+
+      emit(
+         (highlight.kind != TOKEN_SPACE).then_some(&highlight),
+         &source[range.start..range.end],
+      );
+
+      position = range.end;
+   }
+
+   if position < source.len() {
+      emit(None, &source[position..]);
+   }
+}
+
+/// Escapes the characters of the text that are special in HTML, so that it
+/// can be put in elements and attribute values.
+#[must_use]
+pub fn escape_html(text: &str) -> String {
+   let mut escaped = String::with_capacity(text.len());
+
+   for c in text.chars() {
+      match c {
+         '&' => escaped.push_str("&amp;"),
+         '<' => escaped.push_str("&lt;"),
+         '>' => escaped.push_str("&gt;"),
+         '"' => escaped.push_str("&quot;"),
+         '\'' => escaped.push_str("&#39;"),
+         c => escaped.push(c),
+      }
+   }
+
+   escaped
+}
+
+/// Renders the source to an HTML `<pre>` element, with a `<span>` for every
+/// token that has the classes of [`Highlight::classes`].
+#[must_use]
+pub fn to_html(source: &str) -> String {
+   let mut html = String::from("<pre class=\"cab\"><code>");
+
+   render(source, |highlight, text| {
+      match highlight {
+         Some(highlight) => {
+            let _ = write!(
+               html,
+               "<span class=\"{classes}\">{text}</span>",
+               classes = highlight.classes(),
+               text = escape_html(text),
+            );
+         },
+
+         None => html.push_str(&escape_html(text)),
+      }
+   });
+
+   html.push_str("</code></pre>\n");
+   html
+}
+
+/// Renders the source with terminal escape sequences, in the styles of
+/// [`Highlight::style`].
+#[must_use]
+pub fn to_ansi(source: &str) -> String {
+   let mut ansi = String::new();
+
+   {
+      let writer = &mut terminal::writer(terminal::StyleChoice::Always, &mut ansi);
+
+      render(source, |highlight, text| {
+         let style = highlight.map_or_else(style::Style::new, Highlight::style);
+
+         let _ = write(writer, &text.style(style));
+      });
+
+      writer.set_style(style::Style::default());
+      let _ = writer.apply_style();
+   }
+
+   ansi
+}
+
+fn escape_latex(text: &str) -> String {
+   let mut escaped = String::with_capacity(text.len());
+
+   for c in text.chars() {
+      match c {
+         '\\' => escaped.push_str(r"\textbackslash{}"),
+         '{' => escaped.push_str(r"\{"),
+         '}' => escaped.push_str(r"\}"),
+         c => escaped.push(c),
+      }
+   }
+
+   escaped
+}
+
+/// Renders the source to a `Verbatim` environment of the `fancyvrb` package,
+/// with every token wrapped in `\CabToken{classes}{text}`, where the classes
+/// are the ones of [`Highlight::classes`]. Tokens that span multiple lines
+/// are wrapped per line, as arguments can't contain line breaks.
+#[must_use]
+pub fn to_latex(source: &str) -> String {
+   let mut latex = String::from("\\begin{Verbatim}[commandchars=\\\\\\{\\}]\n");
+
+   render(source, |highlight, text| {
+      let Some(highlight) = highlight else {
+         latex.push_str(&escape_latex(text));
+         return;
+      };
+
+      let classes = highlight.classes();
+
+      for (index, line) in text.split('\n').enumerate() {
+         if index > 0 {
+            latex.push('\n');
+         }
+
+         if !line.is_empty() {
+            let _ = write!(
+               latex,
+               "\\CabToken{{{classes}}}{{{line}}}",
+               line = escape_latex(line),
+            );
+         }
+      }
+   });
+
+   if !latex.ends_with('\n') {
+      latex.push('\n');
+   }
+
+   latex.push_str("\\end{Verbatim}\n");
+   latex
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   /// Returns the text and context of every token that has a context.
+   fn contexts(source: &str) -> Vec<(&str, Context)> {
+      highlight(source)
+         .into_iter()
+         .filter_map(|highlight| Some((&source[highlight.span.into_std()], highlight.context?)))
+         .collect()
+   }
+
+   #[test]
+   fn covers_source() {
+      let source = "{\n  #| Doc.\n  @a = \"b \\(c)\" ++ ./d;\n}\n";
+
+      let mut rendered = String::new();
+      render(source, |_, text| rendered.push_str(text));
+
+      assert_eq!(rendered, source);
+   }
+
+   #[test]
+   fn contexts_from_nodes() {
+      assert_eq!(
+         contexts("@foo = bar.baz"),
+         [
+            ("@", Context::Bind),
+            ("foo", Context::Bind),
+            ("bar", Context::Reference),
+            ("baz", Context::Attribute),
+         ],
+      );
+
+      assert_eq!(
+         contexts("#| Documented.\n# Plain.\n@x => x"),
+         [
+            ("#| Documented.", Context::Documentation),
+            ("@", Context::Bind),
+            ("x", Context::Bind),
+            ("x", Context::Reference),
+         ],
+      );
+
+      assert_eq!(
+         contexts("@port :: Integer => port"),
+         [
+            ("@", Context::Bind),
+            ("port", Context::Bind),
+            ("Integer", Context::Reference),
+            ("port", Context::Reference),
+         ],
+      );
+   }
+
+   #[test]
+   fn renders() {
+      let html = to_html("@a = \"<b>\"");
+
+      assert!(html.starts_with("<pre class=\"cab\"><code>"));
+      assert!(html.contains("<span class=\"identifier bind\">a</span>"));
+      assert!(html.contains("<span class=\"content string\">&lt;b&gt;</span>"));
+
+      let latex = to_latex("{ @a = 1 }");
+
+      assert!(latex.contains(r"\CabToken{curlybrace-left}{\{}"));
+      assert!(latex.contains(r"\CabToken{identifier bind}{a}"));
+      assert!(latex.contains(r"\CabToken{integer}{1}"));
+      assert!(latex.ends_with("\\end{Verbatim}\n"));
+
+      assert!(to_ansi("@a = 1").contains("\x1B["));
+      assert_eq!(
+         Highlight {
+            kind:    TOKEN_INTEGER,
+            node:    NODE_INTEGER,
+            context: None,
+            span:    Span::at(0_u32, 1_u32),
+         }
+         .style(),
+         COLORS[TOKEN_INTEGER as usize],
+      );
+   }
+}
@@ -1,6 +1,9 @@
 #![expect(dead_code)]
 
-use std::borrow::Cow;
+use std::{
+   borrow::Cow,
+   fmt,
+};
 
 use derive_more::{
    Deref,
@@ -8,6 +11,7 @@ use derive_more::{
 };
 use ranged::{
    IntoSpan as _,
+   Span,
    Spanned,
    SpannedExt as _,
 };
@@ -150,6 +154,11 @@ impl<'arena> Resolved<'arena, &'arena Expression> {
    pub fn propagate(self) -> ExpressionPropagated<'arena> {
       self.raw().propagate(self.arena, self.span())
    }
+
+   #[must_use]
+   pub fn tree(self) -> Tree<'arena> {
+      Tree(self)
+   }
 }
 
 // PARENTHESIS
@@ -263,8 +272,235 @@ macro_rules! segmented {
 }
 
 segmented! { Path }
-segmented! { Bind }
 segmented! { Identifier }
 segmented! { SString }
 
+/// The shape a bind is annotated with, like the `Integer` in `@port :: Integer`.
+/// Values bound to an annotated bind are checked against it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shape {
+   Boolean,
+   Char,
+   Integer,
+   Float,
+   String,
+   Path,
+   Attributes,
+   List,
+}
+
+impl Shape {
+   pub const ALL: [Self; 8] = [
+      Self::Boolean,
+      Self::Char,
+      Self::Integer,
+      Self::Float,
+      Self::String,
+      Self::Path,
+      Self::Attributes,
+      Self::List,
+   ];
+
+   /// Returns the name of the shape, as written in annotations.
+   #[must_use]
+   pub fn name(self) -> &'static str {
+      match self {
+         Self::Boolean => "Boolean",
+         Self::Char => "Char",
+         Self::Integer => "Integer",
+         Self::Float => "Float",
+         Self::String => "String",
+         Self::Path => "Path",
+         Self::Attributes => "Attributes",
+         Self::List => "List",
+      }
+   }
+
+   #[must_use]
+   pub fn from_name(name: &str) -> Option<Self> {
+      Self::ALL.into_iter().find(|shape| shape.name() == name)
+   }
+}
+
+/// A bind, along with the documentation comments before it and the shape it
+/// is annotated with.
+#[derive(Deref, Debug, Clone, PartialEq, Eq)]
+pub struct Bind {
+   #[deref]
+   pub segments:      Segments,
+   pub documentation: Option<Spanned<String>>,
+   pub shape:         Option<Spanned<Shape>>,
+}
+
+impl<'arena> Resolved<'arena, Spanned<&'arena Bind>> {
+   pub fn segments(&self) -> Resolved<'arena, &'_ Segments> {
+      (&self.segments).resolved(self.arena)
+   }
+
+   #[must_use]
+   pub fn documentation(&self) -> Option<&'arena Spanned<String>> {
+      self.value.value.documentation.as_ref()
+   }
+
+   #[must_use]
+   pub fn shape(&self) -> Option<&'arena Spanned<Shape>> {
+      self.value.value.shape.as_ref()
+   }
+}
+
 lode! { If { condition, consequence, alternative } }
+
+// TREE
+
+/// Displays an expression and all of its children, one per line and indented
+/// by depth:
+///
+/// ```text
+/// Call@0..5
+///   Identifier@0..3
+///     Content@0..3 "foo"
+///   Integer@4..5 1
+/// ```
+#[derive(Clone, Copy)]
+pub struct Tree<'arena>(Resolved<'arena, &'arena Expression>);
+
+impl fmt::Display for Tree<'_> {
+   fn fmt(&self, writer: &mut fmt::Formatter<'_>) -> fmt::Result {
+      write_tree(writer, self.0, 0)
+   }
+}
+
+fn write_line(
+   writer: &mut fmt::Formatter<'_>,
+   depth: usize,
+   name: &str,
+   span: Span,
+   value: Option<&dyn fmt::Debug>,
+) -> fmt::Result {
+   write!(writer, "{:indent$}{name}@{span}", "", indent = depth * 2)?;
+
+   if let Some(value) = value {
+      write!(writer, " {value:?}")?;
+   }
+
+   writeln!(writer)
+}
+
+fn write_segments(
+   writer: &mut fmt::Formatter<'_>,
+   depth: usize,
+   name: &str,
+   span: Span,
+   segments: Resolved<'_, &Segments>,
+) -> fmt::Result {
+   write_line(writer, depth, name, span, None)?;
+
+   for segment in segments {
+      match segment.value {
+         Segment::Content(ref content) => {
+            write_line(
+               writer,
+               depth + 1,
+               "Content",
+               segment.span(),
+               Some(&**content),
+            )?;
+         },
+
+         Segment::Interpolation(expression) => write_tree(writer, expression, depth + 1)?,
+      }
+   }
+
+   Ok(())
+}
+
+#[stacksafe::stacksafe]
+fn write_tree(
+   writer: &mut fmt::Formatter<'_>,
+   expression: Resolved<'_, &Expression>,
+   depth: usize,
+) -> fmt::Result {
+   let span = expression.span();
+
+   let (name, children) = match expression.propagate() {
+      ExpressionPropagated::Parenthesis(parenthesis) => {
+         ("Parenthesis", vec![parenthesis.expression()])
+      },
+      ExpressionPropagated::Nil(_) => ("Nil", vec![]),
+      ExpressionPropagated::Attributes(attributes) => {
+         ("Attributes", attributes.expression().into_iter().collect())
+      },
+
+      ExpressionPropagated::Same(same) => ("Same", vec![same.left(), same.right()]),
+      ExpressionPropagated::Sequence(sequence) => {
+         ("Sequence", vec![sequence.left(), sequence.right()])
+      },
+      ExpressionPropagated::Call(call) => ("Call", vec![call.function(), call.argument()]),
+      ExpressionPropagated::Construct(construct) => {
+         ("Construct", vec![construct.head(), construct.tail()])
+      },
+      ExpressionPropagated::Select(select) => ("Select", vec![select.scope(), select.expression()]),
+      ExpressionPropagated::Equal(equal) => ("Equal", vec![equal.left(), equal.right()]),
+      ExpressionPropagated::And(and) => ("And", vec![and.left(), and.right()]),
+      ExpressionPropagated::Or(or) => ("Or", vec![or.left(), or.right()]),
+      ExpressionPropagated::All(all) => ("All", vec![all.left(), all.right()]),
+      ExpressionPropagated::Any(any) => ("Any", vec![any.left(), any.right()]),
+      ExpressionPropagated::Lambda(lambda) => {
+         ("Lambda", vec![lambda.argument(), lambda.expression()])
+      },
+
+      ExpressionPropagated::Path(path) => {
+         return write_segments(writer, depth, "Path", span, path.segments());
+      },
+      ExpressionPropagated::Bind(bind) => {
+         write_segments(writer, depth, "Bind", span, bind.segments())?;
+
+         if let Some(documentation) = bind.documentation() {
+            write_line(
+               writer,
+               depth + 1,
+               "Documentation",
+               documentation.span(),
+               Some(&**documentation),
+            )?;
+         }
+
+         if let Some(shape) = bind.shape() {
+            write_line(writer, depth + 1, "Shape", shape.span(), Some(&**shape))?;
+         }
+
+         return Ok(());
+      },
+      ExpressionPropagated::Identifier(identifier) => {
+         return write_segments(writer, depth, "Identifier", span, identifier.segments());
+      },
+      ExpressionPropagated::SString(string) => {
+         return write_segments(writer, depth, "String", span, string.segments());
+      },
+
+      ExpressionPropagated::Char(char) => {
+         return write_line(writer, depth, "Char", span, Some(&***char));
+      },
+      ExpressionPropagated::Integer(integer) => {
+         return write_line(writer, depth, "Integer", span, Some(&**integer));
+      },
+      ExpressionPropagated::Float(float) => {
+         return write_line(writer, depth, "Float", span, Some(&***float));
+      },
+
+      ExpressionPropagated::If(if_) => {
+         (
+            "If",
+            vec![if_.condition(), if_.consequence(), if_.alternative()],
+         )
+      },
+   };
+
+   write_line(writer, depth, name, span, None)?;
+
+   for child in children {
+      write_tree(writer, child, depth + 1)?;
+   }
+
+   Ok(())
+}
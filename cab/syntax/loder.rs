@@ -49,6 +49,30 @@ use crate::{
 const CURRY_LEFT: &str = "left";
 const CURRY_RIGHT: &str = "right";
 
+/// The name the value a match is on is bound to, so that it is evaluated once
+/// for all arms. It can't be written as a plain identifier, so it doesn't
+/// shadow the names the arms use.
+pub const MATCH_SCRUTINEE: &str = "match scrutinee";
+
+/// Whether the pattern is a non-empty list or attributes. Those would have to
+/// be compared by their items, which the runtime can't do yet.
+fn is_structural(pattern: node::ExpressionRef<'_>) -> bool {
+   match pattern {
+      node::ExpressionRef::Parenthesis(parenthesis) => {
+         parenthesis.expression().is_some_and(is_structural)
+      },
+
+      node::ExpressionRef::List(list) => list.expression().is_some(),
+      node::ExpressionRef::Attributes(_) => true,
+
+      node::ExpressionRef::InfixOperation(operation) => {
+         operation.operator() == node::InfixOperator::Construct
+      },
+
+      _ => false,
+   }
+}
+
 #[derive(Debug, Clone)]
 pub struct Lower {
    expression: lode::ExpressionId,
@@ -165,9 +189,11 @@ impl Loder {
 
    fn bind(&mut self, identifier: Spanned<&'static str>) -> lode::ExpressionId {
       self.insert(
-         lode::Bind(lode::Segments::plain(
-            identifier.value.spanned(identifier.span()),
-         ))
+         lode::Bind {
+            segments:      lode::Segments::plain(identifier.value.spanned(identifier.span())),
+            documentation: None,
+            shape:         None,
+         }
          .spanned(identifier.span()),
       )
    }
@@ -214,6 +240,10 @@ impl Loder {
             continue;
          };
 
+         if segments.is_raw {
+            break;
+         }
+
          let Err(invalids) = token::unescape_string(text) else {
             continue;
          };
@@ -670,7 +700,67 @@ impl Loder {
          return self.throw("invalid bind".spanned(bind.span()));
       };
 
-      self.lode_identifier(identifier).is_bind(true).call()
+      let shape = match bind.shape() {
+         Some(shape) => {
+            let Some(shape) = self.lode_shape(shape) else {
+               return self.throw("invalid shape".spanned(bind.span()));
+            };
+
+            Some(shape)
+         },
+
+         None => None,
+      };
+
+      self
+         .lode_identifier(identifier)
+         .is_bind(true)
+         .maybe_documentation(bind.documentation())
+         .maybe_shape(shape)
+         .call()
+   }
+
+   fn lode_shape(&mut self, shape: node::ExpressionRef<'_>) -> Option<Spanned<lode::Shape>> {
+      let name = match shape {
+         node::ExpressionRef::Identifier(identifier)
+            if let node::IdentifierValueRef::Plain(name) = identifier.value() =>
+         {
+            name
+         },
+
+         _ => {
+            if shape.kind() != NODE_ERROR {
+               self.reports.push(Report::error("invalid shape").primary(
+                  shape.span(),
+                  format!(
+                     "expected the name of a shape, got {kind}",
+                     kind = shape.kind(),
+                  ),
+               ));
+            }
+
+            return None;
+         },
+      };
+
+      let Some(value) = lode::Shape::from_name(name.text()) else {
+         self.reports.push(
+            Report::error("unknown shape")
+               .primary(name.span(), "here")
+               .tip(format!(
+                  "valid shapes are {shapes}",
+                  shapes = lode::Shape::ALL
+                     .iter()
+                     .map(|shape| shape.name())
+                     .collect::<Vec<_>>()
+                     .join(", "),
+               )),
+         );
+
+         return None;
+      };
+
+      Some(value.spanned(name.span()))
    }
 
    #[builder]
@@ -678,6 +768,8 @@ impl Loder {
       &mut self,
       #[builder(start_fn)] identifier: &node::Identifier,
       #[builder(default)] is_bind: bool,
+      documentation: Option<Spanned<String>>,
+      shape: Option<Spanned<lode::Shape>>,
    ) -> lode::ExpressionRaw {
       let segments = match identifier.value() {
          node::IdentifierValueRef::Plain(identifier) => {
@@ -706,7 +798,12 @@ impl Loder {
       };
 
       if is_bind {
-         lode::Bind(segments).into()
+         lode::Bind {
+            segments,
+            documentation,
+            shape,
+         }
+         .into()
       } else {
          lode::Identifier(segments).into()
       }
@@ -787,14 +884,36 @@ impl Loder {
    }
 
    fn lode_float(&mut self, float: &node::Float) -> lode::ExpressionRaw {
-      let Ok(value) = float.token_float().value() else {
-         self
-            .reports
-            .push(Report::error("invalid float").primary(float.span(), "usecase?"));
+      let token = float.token_float();
 
-         return self.throw("invalid float".spanned(float.span()));
+      let (value, is_exact) = match token.value_exact() {
+         Ok(value) => value,
+
+         Err(token::FloatError::Invalid) => {
+            self
+               .reports
+               .push(Report::error("invalid float").primary(float.span(), "usecase?"));
+
+            return self.throw("invalid float".spanned(float.span()));
+         },
+
+         Err(token::FloatError::Overflow) => {
+            self.reports.push(
+               Report::error("float overflows to infinity")
+                  .primary(float.span(), "too large to be represented"),
+            );
+
+            return self.throw("float overflows to infinity".spanned(float.span()));
+         },
       };
 
+      if !is_exact {
+         self.reports.push(
+            Report::warn("float is not exactly representable")
+               .primary(float.span(), format!("will be rounded to {value:?}")),
+         );
+      }
+
       value.into()
    }
 
@@ -807,6 +926,126 @@ impl Loder {
       .into()
    }
 
+   fn lode_match(&mut self, match_: &node::Match) -> lode::ExpressionRaw {
+      if let Some(left) = match_.token_curlybrace_left()
+         && match_.token_curlybrace_right().is_none()
+      {
+         self.reports.push(
+            Report::error("unclosed match")
+               .primary(Span::empty(match_.span().end), "expected '}' here")
+               .secondary(left.span(), "unclosed '{' here"),
+         );
+      }
+
+      let scrutinee = match_.scrutinee();
+      let scrutinee_span = scrutinee.span();
+      let scrutinee = self.lode(scrutinee);
+
+      let mut arms = Vec::new();
+      let mut patterns = Vec::new();
+
+      for arm in match_.arms() {
+         let pattern_and_result = match arm {
+            node::ExpressionRef::InfixOperation(operation)
+               if operation.operator() == node::InfixOperator::Lambda =>
+            {
+               operation.left().zip(operation.right())
+            },
+
+            _ => None,
+         };
+
+         let Some((pattern, result)) = pattern_and_result else {
+            self
+               .reports
+               .push(Report::error("invalid match arm").primary(
+                  arm.span(),
+                  "expected a pattern and a result delimited by '=>'",
+               ));
+
+            continue;
+         };
+
+         if is_structural(pattern) {
+            self.reports.push(
+               Report::error("unsupported match pattern").primary(
+                  pattern.span(),
+                  "lists and attributes can't be matched against yet",
+               ),
+            );
+
+            continue;
+         }
+
+         patterns.push(format!("`{pattern}`", pattern = pattern.text()));
+         arms.push((
+            arm.span(),
+            pattern.span(),
+            self.lode(pattern),
+            self.lode(result),
+         ));
+      }
+
+      let message = if patterns.is_empty() {
+         "no arm matched, as the match has no arms".to_owned()
+      } else {
+         format!(
+            "no arm matched, tried the patterns {patterns}",
+            patterns = patterns.join(", "),
+         )
+      };
+
+      let mut expression = self.throw(message.spanned(match_.span()));
+      let mut span = match_.span();
+
+      for (arm_span, pattern_span, pattern, result) in arms.into_iter().rev() {
+         let left = self.refence(MATCH_SCRUTINEE.spanned(scrutinee_span));
+
+         let condition = self.insert(
+            lode::Equal {
+               left,
+               right: pattern,
+            }
+            .spanned(pattern_span),
+         );
+
+         let alternative = self.insert(expression.spanned(span));
+
+         let if_ = self.insert(
+            lode::If {
+               condition,
+               consequence: result,
+               alternative,
+            }
+            .spanned(arm_span),
+         );
+
+         // Every arm gets a fresh scope, so the names its pattern binds don't
+         // leak out of the match.
+         expression = lode::Parenthesis { expression: if_ }.into();
+         span = arm_span;
+      }
+
+      // The scrutinee is bound once outside of the arms, so forcing it in
+      // every arm doesn't evaluate it again.
+      let argument = self.bind(MATCH_SCRUTINEE.spanned(scrutinee_span));
+      let expression = self.insert(expression.spanned(span));
+
+      let function = self.insert(
+         lode::Lambda {
+            argument,
+            expression,
+         }
+         .spanned(match_.span()),
+      );
+
+      lode::Call {
+         function,
+         argument: scrutinee,
+      }
+      .into()
+   }
+
    fn lode(&mut self, expression: node::ExpressionRef<'_>) -> lode::ExpressionId {
       let expression = match expression {
          node::ExpressionRef::Error(error) => self.throw("syntax error".spanned(error.span())),
@@ -834,9 +1073,321 @@ impl Loder {
          node::ExpressionRef::Float(float) => self.lode_float(float),
 
          node::ExpressionRef::If(if_) => self.lode_if(if_),
+
+         node::ExpressionRef::Match(match_) => self.lode_match(match_),
       }
       .spanned(expression.span());
 
       self.insert(expression)
    }
 }
+
+#[cfg(test)]
+mod tests {
+   use proptest::prelude::*;
+
+   use super::*;
+   use crate::{
+      ParseOracle,
+      tokenize,
+   };
+
+   fn lower(source: &str) -> Lower {
+      let parse = ParseOracle::new().parse(tokenize(source));
+      assert!(parse.reports.is_empty(), "source must parse cleanly");
+
+      LowerOracle::new().lower(parse.expression.as_ref())
+   }
+
+   fn severities(lower: &Lower) -> Vec<&report::Severity> {
+      lower
+         .reports
+         .iter()
+         .map(|report| &report.severity)
+         .collect()
+   }
+
+   fn segments(source: &str) -> Vec<(Option<String>, Span)> {
+      let lower = lower(source);
+      assert!(lower.reports.is_empty(), "{source} must lower cleanly");
+
+      let lode::ExpressionPropagated::SString(string) = lower.expression().propagate() else {
+         unreachable!("{source} must be a string");
+      };
+
+      string
+         .segments()
+         .into_iter()
+         .map(|segment| {
+            let content = match segment.value {
+               lode::Segment::Content(ref content) => Some((***content).to_owned()),
+               lode::Segment::Interpolation(_) => None,
+            };
+
+            (content, segment.span())
+         })
+         .collect()
+   }
+
+   #[test]
+   fn integers() {
+      for (source, expected) in [
+         ("1_000", 1000),
+         ("0b1010", 10),
+         ("0B_1_0", 2),
+         ("0o17", 15),
+         ("0o_7_7", 63),
+         ("0xFF", 255),
+         ("0x_de_ad", 0xDEAD),
+      ] {
+         let lower = lower(source);
+
+         assert!(lower.reports.is_empty(), "{source} must lower cleanly");
+         assert_eq!(
+            *lower.expression().raw(),
+            lode::ExpressionRaw::Integer(num::BigInt::from(expected)),
+            "{source}",
+         );
+      }
+   }
+
+   #[test]
+   fn floats() {
+      for (source, expected) in [
+         ("1_000.5", 1000.5),
+         ("1.5e1_0", 1.5e10),
+         (".25", 0.25),
+         ("0b1.1", 1.5),
+         ("0o0.4", 0.5),
+         ("0x1.8", 1.5),
+         ("0x1.8p3", 12.0),
+         ("0x1p-2", 0.25),
+         ("0.0", 0.0),
+         ("1e-10000000000000000000000", 0.0),
+      ] {
+         let lower = lower(source);
+
+         assert_eq!(
+            *lower.expression().raw(),
+            lode::ExpressionRaw::Float(expected),
+            "{source}",
+         );
+      }
+
+      assert!(lower("1.5").reports.is_empty());
+      assert!(lower("0.5e1").reports.is_empty());
+   }
+
+   #[test]
+   fn float_precision() {
+      assert!(matches!(
+         *severities(&lower("0.1")),
+         [&report::Severity::Warn],
+      ));
+      assert!(matches!(
+         *severities(&lower("9007199254740993.0")),
+         [&report::Severity::Warn],
+      ));
+      assert!(matches!(
+         *severities(&lower("1e-400")),
+         [&report::Severity::Warn],
+      ));
+
+      assert!(matches!(
+         *severities(&lower("1e309")),
+         [&report::Severity::Error],
+      ));
+      assert!(matches!(
+         *severities(&lower("0x1p1024")),
+         [&report::Severity::Error],
+      ));
+      assert!(matches!(
+         *severities(&lower("1e100000000000000000000000")),
+         [&report::Severity::Error],
+      ));
+   }
+
+   #[test]
+   fn indented_strings() {
+      assert_eq!(segments("\"\n  foo\n    bar\n  \""), [(
+         Some("foo\n  bar\n".to_owned()),
+         Span::new(4_usize, 15_usize),
+      )]);
+
+      // The whitespace before an interpolation is indentation too.
+      assert_eq!(segments("\"\n  a\n    \\(b)\n\""), [
+         (Some("a\n  ".to_owned()), Span::new(4_usize, 10_usize)),
+         (None, Span::new(12_usize, 13_usize)),
+         (Some("\n".to_owned()), Span::new(14_usize, 14_usize)),
+      ]);
+
+      assert!(matches!(
+         *severities(&lower("\"foo\n  bar\"")),
+         [&report::Severity::Error],
+      ));
+      assert!(matches!(
+         *severities(&lower("\"\n  foo\n\t bar\n\"")),
+         [&report::Severity::Error],
+      ));
+   }
+
+   #[test]
+   fn raw_strings() {
+      assert_eq!(segments(r#"r"\d+ \(x)""#), [(
+         Some(r"\d+ \(x)".to_owned()),
+         Span::new(2_usize, 10_usize),
+      )]);
+
+      assert_eq!(segments(r#"r"=="quoted"==""#), [(
+         Some(r#""quoted""#.to_owned()),
+         Span::new(4_usize, 12_usize),
+      )]);
+
+      assert_eq!(segments("r\"\n   echo \\$HOME\n   \""), [(
+         Some("echo \\$HOME\n".to_owned()),
+         Span::new(6_usize, 17_usize),
+      )]);
+
+      assert!(matches!(
+         *severities(&lower(r#""\q""#)),
+         [&report::Severity::Error],
+      ));
+   }
+
+   #[test]
+   fn documentation() {
+      let lower = lower(
+         "{\n  #| Foo.\n  #|\n  #|   Bar.\n  @a = 1;\n\n  #| Detached.\n\n  @b = 2;\n  #| Not \
+          this.\n  # Neither.\n  @c = 3\n}",
+      );
+      assert!(lower.reports.is_empty());
+
+      let documentations = lower
+         .arena
+         .values()
+         .filter_map(|expression| {
+            let lode::ExpressionRaw::Bind(ref bind) = **expression else {
+               return None;
+            };
+
+            Some(bind.documentation.as_ref().map(|documentation| {
+               (documentation.value.clone(), documentation.span())
+            }))
+         })
+         .collect::<Vec<_>>();
+
+      assert_eq!(documentations, [
+         Some(("Foo.\n\n  Bar.".to_owned(), Span::new(4_usize, 28_usize))),
+         None,
+         None,
+      ]);
+   }
+
+   #[test]
+   fn shapes() {
+      let lower = lower("{ @port :: Integer = 8080; @head : None = [] }");
+      assert!(lower.reports.is_empty());
+
+      let shapes = lower
+         .arena
+         .values()
+         .filter_map(|expression| {
+            let lode::ExpressionRaw::Bind(ref bind) = **expression else {
+               return None;
+            };
+
+            Some(bind.shape.as_ref().map(|shape| (shape.value, shape.span())))
+         })
+         .collect::<Vec<_>>();
+
+      assert_eq!(shapes, [
+         Some((lode::Shape::Integer, Span::new(11_usize, 18_usize))),
+         None,
+      ]);
+
+      for source in ["@port :: Integr = 1", "@port :: [ Integer ] = 1"] {
+         assert!(
+            matches!(*severities(&lower(source)), [&report::Severity::Error]),
+            "{source}",
+         );
+      }
+
+      // A capitalized name after a single colon still constructs a list.
+      let tree = lower("@head : None").expression().tree().to_string();
+      assert!(tree.starts_with("Construct@"), "{tree}");
+   }
+
+   #[test]
+   fn match_arms() {
+      let lower = lower("match x { 1 => \"one\", @n => n, 2 => \"two\" }");
+
+      // Unreachable arms are lowered too, the compiler warns about them.
+      assert!(lower.reports.is_empty());
+
+      let tree = lower.expression().tree().to_string();
+      assert!(tree.starts_with("Call@"), "{tree}");
+      assert!(
+         tree.contains(r#""no arm matched, tried the patterns `1`, `@n`, `2`""#),
+         "{tree}",
+      );
+
+      assert!(lower("match x {}").reports.is_empty());
+      assert!(matches!(
+         *severities(&lower("match x { 1 }")),
+         [&report::Severity::Error],
+      ));
+
+      for source in [
+         "match [ 1 ] { [ @x ] => x }",
+         "match 1 { [ 1 ] => 1 }",
+         "match x { (@head : @tail) => head }",
+         "match { @a = 1 } { { @a = @y } => y }",
+         "match x { {} => 1 }",
+      ] {
+         assert!(
+            matches!(*severities(&lower(source)), [&report::Severity::Error]),
+            "{source}",
+         );
+      }
+
+      assert!(
+         lower("match [ 1 ] { [] => 0, @list => list }")
+            .reports
+            .is_empty()
+      );
+   }
+
+   proptest! {
+      #[test]
+      fn spans_point_inside_source(source in prop_oneof![any::<String>(), "[\t\n -~]{0,64}"]) {
+         let parse = ParseOracle::new().parse(tokenize(&source));
+         let lower = LowerOracle::new().lower(parse.expression.as_ref());
+
+         let bounds = Span::up_to(source.len());
+
+         for expression in lower.arena.values() {
+            prop_assert!(
+               bounds.contains(expression.span()),
+               "{expression:?} must point inside {source:?}",
+            );
+
+            let (lode::ExpressionRaw::Path(lode::Path(ref segments))
+            | lode::ExpressionRaw::Bind(lode::Bind { ref segments, .. })
+            | lode::ExpressionRaw::Identifier(lode::Identifier(ref segments))
+            | lode::ExpressionRaw::SString(lode::SString(ref segments))) = **expression
+            else {
+               continue;
+            };
+
+            for segment in &segments.0 {
+               if let &lode::Segment::Content(ref content) = segment {
+                  prop_assert!(
+                     bounds.contains(content.span()),
+                     "{content:?} must point inside {source:?}",
+                  );
+               }
+            }
+         }
+      }
+   }
+}
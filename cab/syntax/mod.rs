@@ -16,6 +16,17 @@ use enumset::{
    enum_set,
 };
 
+mod highlight;
+pub use highlight::{
+   Context,
+   Highlight,
+   escape_html,
+   highlight,
+   to_ansi,
+   to_html,
+   to_latex,
+};
+
 pub mod node;
 mod noder;
 pub use noder::{
@@ -28,6 +39,7 @@ mod loder;
 pub use loder::{
    Lower,
    LowerOracle,
+   MATCH_SCRUTINEE,
 };
 
 pub mod token;
@@ -145,6 +157,9 @@ pub enum Kind {
    #[display("':'")]
    #[static_text(":")]
    TOKEN_COLON,
+   #[display("'::'")]
+   #[static_text("::")]
+   TOKEN_COLON_COLON,
    #[display("'++'")]
    #[static_text("++")]
    TOKEN_PLUS_PLUS,
@@ -242,6 +257,9 @@ pub enum Kind {
    #[display("the keyword 'else'")]
    #[static_text("else")]
    TOKEN_KEYWORD_ELSE,
+   #[display("the keyword 'match'")]
+   #[static_text("match")]
+   TOKEN_KEYWORD_MATCH,
 
    /// See [`NODE_STRING`].
    #[display("content")]
@@ -273,6 +291,8 @@ pub enum Kind {
 
    #[display("a string")]
    TOKEN_STRING_START,
+   #[display("a raw string")]
+   TOKEN_RAW_STRING_START,
    #[display("the closing delimiter of a string")]
    TOKEN_STRING_END,
 
@@ -312,7 +332,13 @@ pub enum Kind {
    NODE_PATH,
 
    /// A node that starts with a [`TOKEN_AT`] and has a [`NODE_IDENTIFIER`] as
-   /// a child, used for binding expressions to identifiers.
+   /// a child, used for binding expressions to identifiers. May be followed
+   /// by a [`TOKEN_COLON_COLON`] and another identifier, naming the shape of
+   /// the bound value.
+   ///
+   /// ```text
+   /// @port :: Integer
+   /// ```
    #[display("a bind")]
    NODE_BIND,
 
@@ -330,6 +356,17 @@ pub enum Kind {
    /// A stringlike is a sequence of nodes and tokens, where all the immediate
    /// children tokens are start, end or [`TOKEN_CONTENT`]s, while all the
    /// immediate children nodes are all [`NODE_INTERPOLATION`]s.
+   ///
+   /// A string that starts with [`TOKEN_RAW_STRING_START`] is raw, it has no
+   /// escapes or interpolations:
+   ///
+   /// ```text
+   /// r"= \d+"\s* ="
+   /// ```
+   ///
+   /// Strings that span multiple lines must have their first and last lines
+   /// empty. Those lines are dropped and the common indentation of the
+   /// remaining lines is stripped.
    #[display("a string")]
    NODE_STRING,
 
@@ -345,6 +382,16 @@ pub enum Kind {
 
    #[display("an if")]
    NODE_IF,
+
+   /// A node which starts with a [`TOKEN_KEYWORD_MATCH`], followed by the
+   /// expression that is matched and the arms delimited by curlybraces. The
+   /// arms are lambdas delimited by commas:
+   ///
+   /// ```text
+   /// match value { 0 => "zero", @n => "nonzero" }
+   /// ```
+   #[display("a match")]
+   NODE_MATCH,
 }
 
 use Kind::*;
@@ -358,11 +405,13 @@ impl Kind {
          | TOKEN_INTEGER
          | TOKEN_FLOAT
          | TOKEN_KEYWORD_IF
+         | TOKEN_KEYWORD_MATCH
          | TOKEN_PATH_START
          | TOKEN_AT
          | TOKEN_IDENTIFIER
          | TOKEN_QUOTED_IDENTIFIER_START
          | TOKEN_STRING_START
+         | TOKEN_RAW_STRING_START
          | TOKEN_CHAR_START
          // Error nodes are expressions.
          | TOKEN_ERROR_UNKNOWN
@@ -384,6 +433,7 @@ impl Kind {
    pub fn is_argument(self) -> bool {
       let mut arguments = Self::EXPRESSIONS;
       arguments.remove(TOKEN_KEYWORD_IF);
+      arguments.remove(TOKEN_KEYWORD_MATCH);
 
       arguments.contains(self)
    }
@@ -400,7 +450,7 @@ impl Kind {
       Some(match self {
          TOKEN_PATH_START => (NODE_PATH, TOKEN_PATH_END),
          TOKEN_QUOTED_IDENTIFIER_START => (NODE_IDENTIFIER, TOKEN_QUOTED_IDENTIFIER_END),
-         TOKEN_STRING_START => (NODE_STRING, TOKEN_STRING_END),
+         TOKEN_STRING_START | TOKEN_RAW_STRING_START => (NODE_STRING, TOKEN_STRING_END),
          TOKEN_CHAR_START => (NODE_CHAR, TOKEN_CHAR_END),
          _ => return None,
       })
@@ -11,6 +11,12 @@ use cab_util::reffed;
 use derive_more::Deref;
 use dup::Dupe;
 use paste::paste;
+use ranged::{
+   IntoSpan as _,
+   Span,
+   Spanned,
+   SpannedExt as _,
+};
 pub use segment::{
    Segment,
    Segmented,
@@ -262,6 +268,7 @@ node! {
       Float,
 
       If,
+      Match,
    )]
    /// An expression. Everything is an expression.
    enum Expression;
@@ -718,6 +725,8 @@ impl Bind {
 
    get_node! { expression -> ExpressionRef<'_> }
 
+   get_node! { shape -> 1 @ Option<ExpressionRef<'_>> }
+
    #[must_use]
    pub fn identifier(&self) -> &Identifier {
       let ExpressionRef::Identifier(identifier) = self.expression() else {
@@ -726,6 +735,51 @@ impl Bind {
 
       identifier
    }
+
+   /// Returns the documentation of this bind, which is the content of the
+   /// documentation comments right before it, joined by newlines.
+   ///
+   /// Comments are trivia, so they end up before the outermost node that
+   /// starts with this bind rather than in this node. An empty line or any
+   /// other comment between them detaches the documentation.
+   #[must_use]
+   pub fn documentation(&self) -> Option<Spanned<String>> {
+      let mut node: &red::Node = self;
+      let mut previous = node.prev_sibling_or_token();
+
+      while previous.is_none() {
+         node = node.parent()?;
+         previous = node.prev_sibling_or_token();
+      }
+
+      let mut lines = Vec::new();
+      let mut span = None::<Span>;
+
+      while let Some(red::ElementRef::Token(token)) = previous {
+         match token.kind() {
+            TOKEN_SPACE
+               if <&token::Space>::try_from(token).is_ok_and(|space| space.line_count() <= 2) => {},
+
+            TOKEN_COMMENT
+               if let Some(line) = <&token::Comment>::try_from(token)
+                  .ok()
+                  .and_then(token::Comment::documentation) =>
+            {
+               lines.push(line);
+               span = Some(span.map_or(token.span(), |span| span.cover(token.span())));
+            },
+
+            _ => break,
+         }
+
+         previous = token.prev_sibling_or_token();
+      }
+
+      let span = span?;
+
+      lines.reverse();
+      Some(lines.join("\n").spanned(span))
+   }
 }
 
 // IDENTIFIER
@@ -874,6 +928,35 @@ impl If {
    get_node! { alternative -> 2 @ ExpressionRef<'_> }
 }
 
+// MATCH
+
+node! {
+   #[from(NODE_MATCH)]
+   /// A match. Contains the expression that is matched and the arms, which
+   /// are lambdas delimited by the same operator.
+   struct Match;
+}
+
+impl Match {
+   get_token! { token_match -> TOKEN_KEYWORD_MATCH }
+
+   get_node! { scrutinee -> 0 @ ExpressionRef<'_> }
+
+   get_token! { token_curlybrace_left -> Option<TOKEN_CURLYBRACE_LEFT> }
+
+   get_node! { expression -> 1 @ Option<ExpressionRef<'_>> }
+
+   get_token! { token_curlybrace_right -> Option<TOKEN_CURLYBRACE_RIGHT> }
+
+   /// Iterates over all the arms of the match.
+   pub fn arms(&self) -> impl Iterator<Item = ExpressionRef<'_>> {
+      self
+         .expression()
+         .into_iter()
+         .flat_map(ExpressionRef::same_items)
+   }
+}
+
 mod segment {
    // For the next poor soul that will step in this module:
    //
@@ -897,6 +980,7 @@ mod segment {
    use smallvec::SmallVec;
 
    use crate::{
+      Kind,
       node,
       red,
       token,
@@ -998,6 +1082,7 @@ mod segment {
    pub struct Segments<'a> {
       pub span: Span,
 
+      pub is_raw:       bool,
       pub is_multiline: bool,
 
       pub line_span_first: Option<Span>,
@@ -1023,7 +1108,7 @@ mod segment {
             for straight in self.straights {
                match straight {
                   Straight::Line {
-                     span,
+                     mut span,
                      mut text,
                      is_from_line_start,
                      is_to_line_end,
@@ -1047,23 +1132,29 @@ mod segment {
 
                         if is_to_line_end {
                            text = text.trim_end();
+                           span = Span::at(span.start, text.size());
                         }
 
-                        if is_from_line_start {
-                           text = if text.chars().all(char::is_whitespace) {
-                              ""
-                           } else {
-                              assert!(
-                                 text[..indent_width].chars().all(|c| c == indent.unwrap()),
-                                 "multiline string must be valid and not mix indents"
-                              );
-                              &text[indent_width..]
-                           }
+                        // Lines that are only whitespace and are followed by
+                        // an interpolation are not blank, they are indented.
+                        if is_from_line_start && !text.is_empty() {
+                           let indent_len = indent.map_or(0, char::len_utf8) * indent_width;
+
+                           assert!(
+                              text[..indent_len].chars().all(|c| Some(c) == indent),
+                              "multiline string must be valid and not mix indents"
+                           );
+
+                           text = &text[indent_len..];
+                           span = Span::at_end(span.end, text.size());
                         }
                      }
 
-                     let (unescaped, escaped_newline) =
-                        token::unescape_string(text).expect("string content must be valid");
+                     let (unescaped, escaped_newline) = if self.is_raw {
+                        (text.to_owned(), false)
+                     } else {
+                        token::unescape_string(text).expect("string content must be valid")
+                     };
 
                      buffer.push_str(&unescaped);
 
@@ -1121,6 +1212,7 @@ mod segment {
             let &Straight::Line {
                text,
                is_from_line_start: true,
+               is_to_line_end,
                is_last: false,
                ..
             } = straight
@@ -1128,7 +1220,9 @@ mod segment {
                continue;
             };
 
-            if text.chars().all(char::is_whitespace) {
+            // Blank lines don't count, but whitespace before an interpolation
+            // does.
+            if is_to_line_end && text.chars().all(char::is_whitespace) {
                continue;
             }
 
@@ -1163,6 +1257,11 @@ mod segment {
 
    pub trait Segmented: ops::Deref<Target = red::Node> {
       fn segments(&self) -> Segments<'_> {
+         let is_raw = self
+            .children_with_tokens()
+            .next()
+            .is_some_and(|child| child.kind() == Kind::TOKEN_RAW_STRING_START);
+
          let mut is_multiline = false;
 
          let mut line_span_first = None::<Span>;
@@ -1296,6 +1395,7 @@ mod segment {
          Segments {
             span: self.span(),
 
+            is_raw,
             is_multiline,
 
             line_span_first,
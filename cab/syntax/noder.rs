@@ -408,7 +408,11 @@ impl<'a, I: Iterator<Item = (Kind, &'a str)>> Noder<'a, I> {
          this.next_expect(TOKEN_AT, Kind::IDENTIFIERS);
 
          this.next_while_trivia();
-         this.node_expression_single(until);
+         this.node_expression_single(until | TOKEN_COLON_COLON);
+
+         if this.next_if(TOKEN_COLON_COLON) {
+            this.node_expression_single(until);
+         }
       });
    }
 
@@ -505,6 +509,34 @@ impl<'a, I: Iterator<Item = (Kind, &'a str)>> Noder<'a, I> {
       });
    }
 
+   fn node_match(&mut self, until: EnumSet<Kind>) {
+      // `match f x { .. }` would otherwise call `x` with the arms.
+      let scrutinee_binding_power = node::InfixOperator::ImplicitCall.binding_power().0 + 1;
+
+      self.node(NODE_MATCH).with(|this| {
+         this.next_expect(
+            TOKEN_KEYWORD_MATCH,
+            until | Kind::EXPRESSIONS | TOKEN_CURLYBRACE_LEFT,
+         );
+
+         this.node_expression_binding_power(scrutinee_binding_power, until | TOKEN_CURLYBRACE_LEFT);
+
+         this.next_expect(
+            TOKEN_CURLYBRACE_LEFT,
+            until | Kind::EXPRESSIONS | TOKEN_CURLYBRACE_RIGHT,
+         );
+
+         if this
+            .peek()
+            .is_some_and(|kind| kind != TOKEN_CURLYBRACE_RIGHT)
+         {
+            this.node_expression(until | TOKEN_CURLYBRACE_RIGHT);
+         }
+
+         this.next_if(TOKEN_CURLYBRACE_RIGHT);
+      });
+   }
+
    #[stacksafe::stacksafe]
    fn node_expression_single(&mut self, until: EnumSet<Kind>) {
       let expected_at = self.checkpoint();
@@ -520,13 +552,16 @@ impl<'a, I: Iterator<Item = (Kind, &'a str)>> Noder<'a, I> {
          Some(TOKEN_FLOAT) => self.node_float(until),
 
          Some(TOKEN_KEYWORD_IF) => self.node_if(until),
+         Some(TOKEN_KEYWORD_MATCH) => self.node_match(until),
 
          Some(TOKEN_PATH_START) => self.node_delimited(),
 
          Some(TOKEN_AT) => self.node_bind(until),
          Some(next) if Kind::IDENTIFIERS.contains(next) => self.node_identifier(until),
 
-         Some(TOKEN_STRING_START | TOKEN_CHAR_START) => self.node_delimited(),
+         Some(TOKEN_STRING_START | TOKEN_RAW_STRING_START | TOKEN_CHAR_START) => {
+            self.node_delimited()
+         },
 
          // The rest are errors.
          Some(kind) if Kind::EXPRESSIONS.contains(kind) => {
@@ -656,3 +691,308 @@ impl<'a, I: Iterator<Item = (Kind, &'a str)>> Noder<'a, I> {
       self.node_expression_binding_power(0, until);
    }
 }
+
+#[cfg(test)]
+mod tests {
+   use proptest::prelude::*;
+   use ranged::IntoSpan as _;
+
+   use super::*;
+   use crate::{
+      node::Segmented as _,
+      tokenize,
+   };
+
+   /// The operators that are generated, with the text they are printed with.
+   const OPERATORS: &[(node::InfixOperator, &str)] = &[
+      (node::InfixOperator::ImplicitCall, " "),
+      (node::InfixOperator::Call, " <| "),
+      (node::InfixOperator::Pipe, " |> "),
+      (node::InfixOperator::Concat, " ++ "),
+      (node::InfixOperator::Construct, " : "),
+      (node::InfixOperator::LessOrEqual, " <= "),
+      (node::InfixOperator::Less, " < "),
+      (node::InfixOperator::MoreOrEqual, " >= "),
+      (node::InfixOperator::More, " > "),
+      (node::InfixOperator::Equal, " = "),
+      (node::InfixOperator::NotEqual, " != "),
+      (node::InfixOperator::And, " && "),
+      (node::InfixOperator::Or, " || "),
+      (node::InfixOperator::Implication, " -> "),
+      (node::InfixOperator::All, " & "),
+      (node::InfixOperator::Any, " | "),
+      (node::InfixOperator::Addition, " + "),
+      (node::InfixOperator::Subtraction, " - "),
+      (node::InfixOperator::Multiplication, " * "),
+      (node::InfixOperator::Power, " ^ "),
+      (node::InfixOperator::Division, " / "),
+   ];
+
+   /// An expression that can be printed and parsed back.
+   #[derive(Debug, Clone, PartialEq, Eq)]
+   enum Ast {
+      Integer(num::BigInt),
+      Identifier(String),
+      SString(String),
+      List(Vec<Ast>),
+      Infix(node::InfixOperator, Box<Ast>, Box<Ast>),
+      Lambda(String, Box<Ast>),
+      If(Box<Ast>, Box<Ast>, Box<Ast>),
+      Match(Box<Ast>, Vec<(Ast, Ast)>),
+   }
+
+   impl Ast {
+      fn from_node(expression: node::ExpressionRef<'_>) -> Self {
+         let plain = |identifier: &node::Identifier| {
+            let node::IdentifierValueRef::Plain(token) = identifier.value() else {
+               unreachable!("generated identifiers must be plain");
+            };
+
+            token.text().to_owned()
+         };
+
+         let from_node = |expression: Option<node::ExpressionRef<'_>>| {
+            Box::new(Self::from_node(expression.expect("operand must exist")))
+         };
+
+         match expression {
+            node::ExpressionRef::Parenthesis(parenthesis) => *from_node(parenthesis.expression()),
+
+            node::ExpressionRef::Integer(integer) => {
+               Self::Integer(
+                  integer
+                     .token_integer()
+                     .value()
+                     .expect("generated integers must be valid"),
+               )
+            },
+
+            node::ExpressionRef::Identifier(identifier) => Self::Identifier(plain(identifier)),
+
+            node::ExpressionRef::SString(string) => {
+               Self::SString(
+                  string
+                     .segments()
+                     .into_iter()
+                     .map(|segment| {
+                        let node::Segment::Content { content, .. } = segment else {
+                           unreachable!("generated strings must not be interpolated");
+                        };
+
+                        content
+                     })
+                     .collect(),
+               )
+            },
+
+            node::ExpressionRef::List(list) => {
+               Self::List(list.items().map(Self::from_node).collect())
+            },
+
+            node::ExpressionRef::InfixOperation(operation)
+               if operation.operator() == node::InfixOperator::Lambda =>
+            {
+               let Some(node::ExpressionRef::Bind(bind)) = operation.left() else {
+                  unreachable!("generated lambdas must bind their argument");
+               };
+
+               Self::Lambda(plain(bind.identifier()), from_node(operation.right()))
+            },
+
+            node::ExpressionRef::InfixOperation(operation) => {
+               Self::Infix(
+                  operation.operator(),
+                  from_node(operation.left()),
+                  from_node(operation.right()),
+               )
+            },
+
+            node::ExpressionRef::If(if_) => {
+               Self::If(
+                  from_node(Some(if_.condition())),
+                  from_node(Some(if_.consequence())),
+                  from_node(Some(if_.alternative())),
+               )
+            },
+
+            node::ExpressionRef::Match(match_) => {
+               Self::Match(
+                  from_node(Some(match_.scrutinee())),
+                  match_
+                     .arms()
+                     .map(|arm| {
+                        let node::ExpressionRef::InfixOperation(arm) = arm else {
+                           unreachable!("generated arms must be lambdas");
+                        };
+
+                        (*from_node(arm.left()), *from_node(arm.right()))
+                     })
+                     .collect(),
+               )
+            },
+
+            _ => unreachable!("expression must be generated"),
+         }
+      }
+
+      fn is_atom(&self) -> bool {
+         matches!(
+            *self,
+            Self::Integer(_) | Self::Identifier(_) | Self::SString(_) | Self::List(_)
+         )
+      }
+
+      /// Writes the expression, parenthesizing every operand that is not an
+      /// atom so that the printed form does not depend on binding powers.
+      fn write(&self, source: &mut String) {
+         match *self {
+            Self::Integer(ref integer) => write!(source, "{integer}").unwrap(),
+
+            Self::Identifier(ref identifier) => source.push_str(identifier),
+
+            Self::SString(ref content) => write!(source, "\"{content}\"").unwrap(),
+
+            Self::List(ref items) => {
+               source.push('[');
+
+               for (index, item) in items.iter().enumerate() {
+                  if index != 0 {
+                     source.push_str(", ");
+                  }
+
+                  item.write_operand(source);
+               }
+
+               source.push(']');
+            },
+
+            Self::Infix(operator, ref left, ref right) => {
+               let &(_, text) = OPERATORS
+                  .iter()
+                  .find(|&&(generated, _)| generated == operator)
+                  .expect("operator must be generated");
+
+               left.write_operand(source);
+               source.push_str(text);
+               right.write_operand(source);
+            },
+
+            Self::Lambda(ref argument, ref body) => {
+               write!(source, "@{argument} => ").unwrap();
+               body.write_operand(source);
+            },
+
+            Self::If(ref condition, ref consequence, ref alternative) => {
+               source.push_str("if ");
+               condition.write_operand(source);
+               source.push_str(" then ");
+               consequence.write_operand(source);
+               source.push_str(" else ");
+               alternative.write_operand(source);
+            },
+
+            Self::Match(ref scrutinee, ref arms) => {
+               source.push_str("match ");
+               scrutinee.write_operand(source);
+               source.push_str(" {");
+
+               for (index, &(ref pattern, ref result)) in arms.iter().enumerate() {
+                  source.push_str(if index == 0 { " " } else { ", " });
+
+                  pattern.write_operand(source);
+                  source.push_str(" => ");
+                  result.write_operand(source);
+               }
+
+               source.push_str(" }");
+            },
+         }
+      }
+
+      fn write_operand(&self, source: &mut String) {
+         if self.is_atom() {
+            self.write(source);
+         } else {
+            source.push('(');
+            self.write(source);
+            source.push(')');
+         }
+      }
+   }
+
+   fn arbitrary_ast() -> impl Strategy<Value = Ast> {
+      let identifier = || {
+         "[a-z][a-z0-9_]{0,5}".prop_filter("identifier must not be a keyword", |identifier| {
+            !matches!(&**identifier, "if" | "then" | "else" | "match")
+         })
+      };
+
+      let leaf = prop_oneof![
+         any::<u64>().prop_map(|integer| Ast::Integer(integer.into())),
+         identifier().prop_map(Ast::Identifier),
+         "[a-z]{0,8}".prop_map(Ast::SString),
+      ];
+
+      leaf.prop_recursive(4, 32, 4, move |ast| {
+         prop_oneof![
+            prop::collection::vec(ast.clone(), 0..4).prop_map(Ast::List),
+            (prop::sample::select(OPERATORS), ast.clone(), ast.clone()).prop_map(
+               |((operator, _), left, right)| Ast::Infix(operator, Box::new(left), Box::new(right))
+            ),
+            (identifier(), ast.clone())
+               .prop_map(|(argument, body)| Ast::Lambda(argument, Box::new(body))),
+            (ast.clone(), ast.clone(), ast.clone()).prop_map(
+               |(condition, consequence, alternative)| {
+                  Ast::If(
+                     Box::new(condition),
+                     Box::new(consequence),
+                     Box::new(alternative),
+                  )
+               }
+            ),
+            (ast.clone(), prop::collection::vec((ast.clone(), ast), 0..3))
+               .prop_map(|(scrutinee, arms)| Ast::Match(Box::new(scrutinee), arms)),
+         ]
+      })
+   }
+
+   fn parse(source: &str) -> Parse {
+      ParseOracle::new().parse(tokenize(source))
+   }
+
+   proptest! {
+      #[test]
+      fn lossless(source in prop_oneof![any::<String>(), "[\t\n -~]{0,64}"]) {
+         let parse = parse(&source);
+
+         prop_assert_eq!(parse.node.text().to_string(), source);
+      }
+
+      #[test]
+      fn spans_nest(source in prop_oneof![any::<String>(), "[\t\n -~]{0,64}"]) {
+         let parse = parse(&source);
+
+         prop_assert_eq!(parse.node.span(), Span::up_to(source.len()));
+
+         for node in parse.node.descendants() {
+            for child in node.children_with_tokens() {
+               prop_assert!(
+                  node.span().contains(child.span()),
+                  "{child:?} must be contained in {node:?}",
+               );
+            }
+         }
+      }
+
+      #[test]
+      fn round_trip(ast in arbitrary_ast()) {
+         let mut source = String::new();
+         ast.write(&mut source);
+
+         let parse = parse(&source);
+
+         prop_assert!(parse.reports.is_empty(), "{source} must parse cleanly");
+         prop_assert_eq!(Ast::from_node(parse.expression.as_ref()), ast, "{}", source);
+      }
+   }
+}
@@ -10,7 +10,6 @@ use derive_more::Deref;
 use num::{
    Num as _,
    bigint as num_bigint,
-   traits as num_traits,
 };
 use ranged::{
    IntoSize as _,
@@ -256,6 +255,8 @@ token! {
 }
 
 impl Comment {
+   /// The starting delimiter of documentation comments.
+   pub const DOCUMENTATION_START: &str = "#|";
    const START_HASHTAG_LEN: usize = '#'.len_utf8();
 
    /// Returns the starting delimiter of this comment.
@@ -284,6 +285,17 @@ impl Comment {
          .copied()
          .is_some_and(|c| c == b'=')
    }
+
+   /// Returns the content of this comment if it is a documentation comment,
+   /// which is a singleline comment that starts with
+   /// [`Self::DOCUMENTATION_START`]. The space after the delimiter is not a
+   /// part of the content.
+   #[must_use]
+   pub fn documentation(&self) -> Option<&str> {
+      let content = self.text().strip_prefix(Self::DOCUMENTATION_START)?;
+
+      Some(content.strip_prefix(' ').unwrap_or(content).trim_end())
+   }
 }
 
 // IDENTIFIER
@@ -310,22 +322,30 @@ token! {
 }
 
 impl Integer {
-   /// Returns the value of this integer, after resolving binary,
-   /// octadecimal and hexadecimal notation if it exists.
-   #[rustfmt::skip]
+   /// Returns the value of this integer, after resolving binary, octal and
+   /// hexadecimal notation and removing `_` separators.
    pub fn value(&self) -> Result<num::BigInt, num_bigint::ParseBigIntError> {
-      let text = self.text();
+      let (radix, digits) = split_radix(self.text());
 
-      match text.as_bytes().get(1).copied() {
-         // Remove leading `_` because that makes num_bigint's parser see the literal as `_BEEF` when we have `0x_BEEF`.
-         Some(b'b' | b'B') => num::BigInt::from_str_radix(text["0b".len()..].trim_start_matches('_'), 2),
-         Some(b'o' | b'O') => num::BigInt::from_str_radix(text["0o".len()..].trim_start_matches('_'), 8),
-         Some(b'x' | b'X') => num::BigInt::from_str_radix(text["0x".len()..].trim_start_matches('_'), 16),
-         _ => num::BigInt::from_str_radix(text, 10),
-      }
+      num::BigInt::from_str_radix(&without_separators(digits), radix)
    }
 }
 
+/// Splits a number literal into its radix and the rest of the literal after
+/// the radix prefix.
+fn split_radix(text: &str) -> (u32, &str) {
+   match text.as_bytes().get(1).copied() {
+      Some(b'b' | b'B') => (2, &text["0b".len()..]),
+      Some(b'o' | b'O') => (8, &text["0o".len()..]),
+      Some(b'x' | b'X') => (16, &text["0x".len()..]),
+      _ => (10, text),
+   }
+}
+
+fn without_separators(text: &str) -> String {
+   text.chars().filter(|&c| c != '_').collect()
+}
+
 // FLOAT
 
 token! {
@@ -334,18 +354,128 @@ token! {
    struct Float;
 }
 
+/// Why a float literal doesn't have a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FloatError {
+   /// The literal is malformed.
+   Invalid,
+   /// The literal is too large in magnitude to be represented, and would be
+   /// infinity.
+   Overflow,
+}
+
 impl Float {
-   /// Returns the value of the float by parsing the underlying slice.
-   pub fn value(&self) -> Result<f64, num_traits::ParseFloatError> {
-      let text = self.text();
+   /// The binary magnitude above which a literal is certainly infinity and
+   /// below which it is certainly zero. Checked before computing the exact
+   /// value, so huge exponents don't allocate huge integers.
+   const MAGNITUDE_LIMIT: i128 = 1 << 11;
+
+   /// Returns the value of the float, after resolving binary, octal and
+   /// hexadecimal notation and removing `_` separators. The value is the
+   /// nearest float to the literal, which might not be exactly equal to it.
+   ///
+   /// Hexadecimal floats use `p` for their exponent, which is a power of two
+   /// written in decimal.
+   pub fn value(&self) -> Result<f64, FloatError> {
+      self.value_exact().map(|(value, _)| value)
+   }
+
+   /// Returns the value of the float like [`Self::value`], along with whether
+   /// it is exactly equal to the literal. Parses the literal only once.
+   pub fn value_exact(&self) -> Result<(f64, bool), FloatError> {
+      use num::{
+         BigRational,
+         ToPrimitive as _,
+         Zero as _,
+      };
+
+      let (radix, text) = split_radix(self.text());
+
+      let (mantissa, exponent) = match radix {
+         10 => text.split_once(['e', 'E']),
+         16 => text.split_once(['p', 'P']),
+         _ => None,
+      }
+      .map_or((text, None), |(mantissa, exponent)| {
+         (mantissa, Some(exponent))
+      });
+
+      let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+      let fraction = without_separators(fraction);
 
-      match text.as_bytes().get(1).copied() {
-         // Remove leading `_` because that makes num_bigint's parser see the literal as `_BEEF`
-         // when we have `0x_BE.EF`.
-         Some(b'b' | b'B') => f64::from_str_radix(text["0b".len()..].trim_start_matches('_'), 2),
-         Some(b'o' | b'O') => f64::from_str_radix(text["0o".len()..].trim_start_matches('_'), 8),
-         Some(b'x' | b'X') => f64::from_str_radix(text["0x".len()..].trim_start_matches('_'), 16),
-         _ => f64::from_str_radix(text, 10),
+      let mantissa = num::BigInt::from_str_radix(&(without_separators(integer) + &fraction), radix)
+         .map_err(|_| FloatError::Invalid)?;
+
+      if mantissa.is_zero() {
+         return Ok((0.0, true));
       }
+
+      // Exponents that don't fit saturate, as they are way past the magnitude limit anyway.
+      let exponent = match exponent {
+         None => 0,
+         Some(exponent) => {
+            let exponent = num::BigInt::from_str_radix(&without_separators(exponent), 10)
+               .map_err(|_| FloatError::Invalid)?;
+
+            exponent
+               .to_i128()
+               .unwrap_or(if exponent.sign() == num_bigint::Sign::Minus {
+                  -i128::from(u64::MAX)
+               } else {
+                  i128::from(u64::MAX)
+               })
+         },
+      };
+
+      let fraction_len = i128::try_from(fraction.len()).map_err(|_| FloatError::Invalid)?;
+
+      // The value is mantissa * base ^ scale.
+      let (base, scale) = match radix {
+         10 => (10_u32, exponent - fraction_len),
+         16 => (2, exponent - 4 * fraction_len),
+         8 => (2, -3 * fraction_len),
+         _ => (2, -fraction_len),
+      };
+
+      // Bounds of the binary logarithm of the value. 2^3 < 10 < 2^4.
+      let bits = i128::try_from(mantissa.bits()).map_err(|_| FloatError::Invalid)?;
+      let (factor_low, factor_high) = match (base, scale >= 0) {
+         (10, true) => (3, 4),
+         (10, false) => (4, 3),
+         _ => (1, 1),
+      };
+
+      if bits - 1 + scale * factor_low > Self::MAGNITUDE_LIMIT {
+         return Err(FloatError::Overflow);
+      }
+
+      if bits + scale * factor_high < -Self::MAGNITUDE_LIMIT {
+         return Ok((0.0, false));
+      }
+
+      let power = num::BigInt::from(base)
+         .pow(u32::try_from(scale.unsigned_abs()).expect("scale was checked to be small"));
+
+      let exact = if scale < 0 {
+         BigRational::new(mantissa, power)
+      } else {
+         BigRational::from_integer(mantissa * power)
+      };
+
+      let value = if radix == 10 {
+         without_separators(text)
+            .parse::<f64>()
+            .map_err(|_| FloatError::Invalid)?
+      } else {
+         exact.to_f64().ok_or(FloatError::Overflow)?
+      };
+
+      if value.is_infinite() {
+         return Err(FloatError::Overflow);
+      }
+
+      let is_exact = BigRational::from_float(value).is_some_and(|value| value == exact);
+
+      Ok((value, is_exact))
    }
 }
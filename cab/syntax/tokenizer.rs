@@ -21,6 +21,7 @@ enum Context<'a> {
    Delimited {
       before: Option<&'a str>,
       end:    char,
+      is_raw: bool,
    },
    DelimitedEnd {
       before: Option<&'a str>,
@@ -151,7 +152,7 @@ impl<'a> Tokenizer<'a> {
       }
    }
 
-   fn consume_delimited(&mut self, before: Option<&'a str>, end: char) -> Kind {
+   fn consume_delimited(&mut self, before: Option<&'a str>, end: char, is_raw: bool) -> Kind {
       loop {
          let remaining = self.remaining();
 
@@ -160,18 +161,31 @@ impl<'a> Tokenizer<'a> {
                .get(before.map_or(0, str::len)..)
                .is_some_and(|remaining| remaining.starts_with(end))
          {
-            self.context_pop(Context::Delimited { before, end });
+            self.context_pop(Context::Delimited {
+               before,
+               end,
+               is_raw,
+            });
             self.context_push(Context::DelimitedEnd { before, end });
 
             return TOKEN_CONTENT;
          }
 
          if self.peek_character().is_none() {
-            self.context_pop(Context::Delimited { before, end });
+            self.context_pop(Context::Delimited {
+               before,
+               end,
+               is_raw,
+            });
 
             return TOKEN_CONTENT;
          }
 
+         if is_raw {
+            self.consume_character();
+            continue;
+         }
+
          if let Some(kind) = self.consume_delimited_segment() {
             return kind;
          }
@@ -251,8 +265,12 @@ impl<'a> Tokenizer<'a> {
             return Some(TOKEN_PATH_END);
          },
 
-         Some(Context::Delimited { before, end }) => {
-            return Some(self.consume_delimited(before, end));
+         Some(Context::Delimited {
+            before,
+            end,
+            is_raw,
+         }) => {
+            return Some(self.consume_delimited(before, end, is_raw));
          },
          Some(Context::DelimitedEnd { before, end }) => {
             if let Some(before) = before {
@@ -367,6 +385,7 @@ impl<'a> Tokenizer<'a> {
 
          '=' if self.try_consume_character('>') => TOKEN_EQUAL_MORE,
 
+         ':' if self.try_consume_character(':') => TOKEN_COLON_COLON,
          ':' => TOKEN_COLON,
          '+' if self.try_consume_character('+') => TOKEN_PLUS_PLUS,
          '[' => TOKEN_BRACKET_LEFT,
@@ -477,11 +496,26 @@ impl<'a> Tokenizer<'a> {
          // After the `.123` literal parsing.
          '.' => TOKEN_PERIOD,
 
+         // r"= \d+"\s* ="
+         'r' if self.try_consume_character('"') => {
+            let equals_len = self.consume_while(|c| c == '=');
+            let equals = self.consumed_since(self.offset - equals_len);
+
+            self.context_push(Context::Delimited {
+               before: Some(equals),
+               end:    '"',
+               is_raw: true,
+            });
+
+            TOKEN_RAW_STRING_START
+         },
+
          initial_letter if token::is_valid_initial_plain_identifier_character(initial_letter) => {
             const KEYWORDS: phf::Map<&'static str, Kind> = phf::phf_map! {
                 "if" => TOKEN_KEYWORD_IF,
                 "then" => TOKEN_KEYWORD_THEN,
                 "else" => TOKEN_KEYWORD_ELSE,
+                "match" => TOKEN_KEYWORD_MATCH,
             };
 
             self.consume_while(token::is_valid_plain_identifier_character);
@@ -520,6 +554,7 @@ impl<'a> Tokenizer<'a> {
             self.context_push(Context::Delimited {
                before: Some(equals),
                end:    start,
+               is_raw: false,
             });
 
             match start {
@@ -537,6 +572,8 @@ impl<'a> Tokenizer<'a> {
 
 #[cfg(test)]
 mod tests {
+   use proptest::prelude::*;
+
    use super::*;
 
    macro_rules! assert_matches {
@@ -585,6 +622,40 @@ mod tests {
       );
    }
 
+   #[test]
+   fn numbers() {
+      assert_token_matches!(
+         "1_000 0b1_0 0o7_7 0x_FF 1_0.5e1_0 0x1.8p-3",
+         (TOKEN_INTEGER, "1_000"),
+         (TOKEN_SPACE, " "),
+         (TOKEN_INTEGER, "0b1_0"),
+         (TOKEN_SPACE, " "),
+         (TOKEN_INTEGER, "0o7_7"),
+         (TOKEN_SPACE, " "),
+         (TOKEN_INTEGER, "0x_FF"),
+         (TOKEN_SPACE, " "),
+         (TOKEN_FLOAT, "1_0.5e1_0"),
+         (TOKEN_SPACE, " "),
+         (TOKEN_FLOAT, "0x1.8p-3"),
+      );
+   }
+
+   #[test]
+   fn raw_string() {
+      assert_token_matches!(
+         r#"r"=\d "x" \(y)=" r "z""#,
+         (TOKEN_RAW_STRING_START, r#"r"="#),
+         (TOKEN_CONTENT, r#"\d "x" \(y)"#),
+         (TOKEN_STRING_END, r#"=""#),
+         (TOKEN_SPACE, " "),
+         (TOKEN_IDENTIFIER, "r"),
+         (TOKEN_SPACE, " "),
+         (TOKEN_STRING_START, r#"""#),
+         (TOKEN_CONTENT, "z"),
+         (TOKEN_STRING_END, r#"""#),
+      );
+   }
+
    #[test]
    fn path() {
       assert_token_matches!(
@@ -608,4 +679,13 @@ mod tests {
          (TOKEN_ERROR_UNKNOWN, "~")
       );
    }
+
+   proptest! {
+      #[test]
+      fn lossless(source in prop_oneof![any::<String>(), "[\t\n -~]{0,64}"]) {
+         let tokens = tokenize(&source).map(|(_, slice)| slice).collect::<String>();
+
+         prop_assert_eq!(tokens, source);
+      }
+   }
 }
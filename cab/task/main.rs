@@ -1,18 +1,43 @@
 use std::{
-   env,
-   fmt::Write as _,
+   fmt::{
+      self,
+      Write as _,
+   },
    fs,
-   io::Write as _,
+   io::{
+      self,
+      Write as _,
+   },
+   path::{
+      Path,
+      PathBuf,
+   },
    process,
+   time::{
+      Duration,
+      Instant,
+   },
 };
 
-use cab::syntax;
+use cab::{
+   runtime::{
+      self,
+      value,
+   },
+   syntax,
+   util::suffix::Arc as _,
+};
 use clap::Parser as _;
 use cyn::{
    self,
    ResultExt as _,
 };
+use dup::Dupe as _;
+use ranged::Span;
+use rpds::ListSync as List;
 use ust::{
+   Display as _,
+   report,
    style::StyledExt as _,
    terminal,
    write,
@@ -32,21 +57,300 @@ enum Command {
       #[arg(long, global = true)]
       fail_fast: bool,
 
+      /// Whether to overwrite test cases that do not match with the actual
+      /// result, and to record the results of test cases that do not have
+      /// one yet.
+      #[arg(long, global = true, conflicts_with = "fail_fast")]
+      overwrite: bool,
+
+      /// The directory to read the test cases from. Defaults to `cab/tests`.
+      #[arg(long, global = true, value_name = "PATH")]
+      directory: Option<PathBuf>,
+
       #[command(subcommand)]
       command: Check,
    },
+
+   /// Measures how much faster the benchmarks evaluate when references skip
+   /// the scopes that are known not to bind them, compared to searching every
+   /// scope.
+   Bench {
+      /// How many times to evaluate every benchmark with each resolution.
+      #[arg(long, default_value_t = 1000)]
+      iterations: u32,
+
+      /// The directory to read the benchmarks from. Defaults to `cab/benches`.
+      #[arg(long, value_name = "PATH")]
+      directory: Option<PathBuf>,
+   },
 }
 
 /// Checks the specified crate for correctness.
-#[derive(clap::Subcommand, Debug, Clone)]
+///
+/// Every `<name>.cab` file in the test directory is a test case, and its
+/// expected result for a stage is stored in `<name>.<stage>.expect` next to
+/// it. A test case without an expected result fails like one whose result
+/// changed.
+#[derive(clap::Subcommand, Debug, Clone, Copy)]
 enum Check {
-   /// Compares the test data and expected results with the actual results.
-   Syntax {
-      /// Whether to overwrite test cases that do not match with the actual
-      /// result.
-      #[arg(long, conflicts_with = "fail_fast")]
-      overwrite: bool,
-   },
+   /// Compares the parse trees of the test cases with the expected results.
+   Syntax,
+   /// Compares the lowered trees and reports of the test cases with the
+   /// expected results.
+   Lower,
+   /// Compares the code of the test cases before and after optimization with
+   /// the expected results.
+   Compile,
+   /// Compares the values and reports of the test cases with the expected
+   /// results.
+   Eval,
+   /// Runs every stage.
+   All,
+}
+
+impl Check {
+   fn stages(self) -> &'static [Stage] {
+      match self {
+         Self::Syntax => &[Stage::Syntax],
+         Self::Lower => &[Stage::Lower],
+         Self::Compile => &[Stage::Compile],
+         Self::Eval => &[Stage::Eval],
+         Self::All => &[Stage::Syntax, Stage::Lower, Stage::Compile, Stage::Eval],
+      }
+   }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+   Syntax,
+   Lower,
+   Compile,
+   Eval,
+}
+
+impl fmt::Display for Stage {
+   fn fmt(&self, writer: &mut fmt::Formatter<'_>) -> fmt::Result {
+      writer.write_str(match *self {
+         Self::Syntax => "syntax",
+         Self::Lower => "lower",
+         Self::Compile => "compile",
+         Self::Eval => "eval",
+      })
+   }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Summary {
+   matched: usize,
+   changed: usize,
+   missing: usize,
+}
+
+/// Writes the result of running the source through every stage up to and
+/// including the given one.
+///
+/// Reports of earlier stages are part of the result. If a stage fails, the
+/// result ends with its reports.
+async fn write_actual(
+   writer: &mut impl ust::Write,
+   stage: Stage,
+   name: &str,
+   source: &str,
+) -> cyn::Result<()> {
+   // SOURCE -> PARSE
+   let parse_oracle = syntax::ParseOracle::new();
+   let parse = parse_oracle.parse(syntax::tokenize(source));
+
+   if stage == Stage::Syntax {
+      return write!(writer, "{node:#?}", node = parse.node)
+         .chain_err("failed to write parse tree");
+   }
+
+   let path = value::Path::rootless(List::new_sync().push_front(value::SString::from(name)));
+   let source = report::PositionStr::new(source);
+
+   let Ok(expression) = parse.extractlnln(writer, &path, &source) else {
+      return Ok(());
+   };
+
+   // EXPRESSION -> LOWERED EXPRESSION
+   let lower_oracle = syntax::LowerOracle::new();
+   let lower = lower_oracle.lower(expression.as_ref());
+
+   let Ok(expression) = lower.extractlnln(writer, &path, &source) else {
+      return Ok(());
+   };
+
+   if stage == Stage::Lower {
+      return write!(writer, "{tree}", tree = expression.tree())
+         .chain_err("failed to write lowered tree");
+   }
+
+   // LOWERED EXPRESSION -> CODE
+   let compile_oracle = runtime::CompileOracle::new();
+   let compile = compile_oracle.compile(expression).path(path.dupe());
+
+   for report in &*compile.reports {
+      writer
+         .write_report(report, &path, &source)
+         .chain_err("failed to write report")?;
+
+      write!(writer, "\n\n").chain_err("failed to write report")?;
+   }
+
+   let code = compile.code;
+   let code_optimized = code.optimized();
+
+   if stage == Stage::Compile {
+      for (header, code) in [("unoptimized", &code), ("optimized", &code_optimized)] {
+         writeln!(
+            writer,
+            "// {header}\n{assembly}",
+            assembly = code.assembly()
+         )
+         .chain_err("failed to write code")?;
+      }
+
+      return Ok(());
+   }
+
+   // CODE -> VALUE
+   let thunk = value::Thunk::forceable(code_optimized.arc())
+      .scopes(runtime::Scopes::new().push(runtime::Scope::from(&runtime::builtins())))
+      .location(value::Location::new(path, Span::at(0_u32, source.len())));
+
+   let state = runtime::State {
+      parse_oracle,
+      compile_oracle,
+      ..runtime::State::new()
+   };
+
+   thunk.force(&state).await;
+
+   let (_, value) = thunk.get().await;
+   let value = value.forced_deep(&state).await;
+
+   value
+      .display_styled(writer)
+      .chain_err("failed to write value")?;
+   writeln!(writer).chain_err("failed to write value")
+}
+
+async fn actual(stage: Stage, name: &str, source: &str) -> cyn::Result<String> {
+   let mut actual = String::new();
+
+   {
+      let writer = &mut terminal::writer(terminal::StyleChoice::Never, &mut actual);
+      write_actual(writer, stage, name, source).await?;
+   }
+
+   Ok(actual)
+}
+
+/// Evaluates the source the given number of times and returns how long it
+/// took in total. Compilation is not measured.
+async fn measure(
+   err: &mut impl ust::Write,
+   name: &str,
+   source: &str,
+   iterations: u32,
+   compile_oracle: runtime::CompileOracle,
+) -> cyn::Result<Duration> {
+   let path = value::Path::rootless(List::new_sync().push_front(value::SString::from(name)));
+
+   let evaluator = cab::Evaluator::new()
+      .cache(None)
+      .compile_oracle(compile_oracle);
+
+   let compiled = match evaluator.compile(path, source.to_owned()) {
+      Ok(compiled) => compiled,
+
+      Err(failure) => {
+         failure
+            .write_reports(err)
+            .chain_err("failed to write reports")?;
+
+         cyn::bail!("failed to compile {name}");
+      },
+   };
+
+   let state = evaluator.state();
+   let start = Instant::now();
+
+   for _ in 0..iterations {
+      let _ = evaluator
+         .run(&compiled, &state)
+         .await
+         .forced_deep(&state)
+         .await;
+   }
+
+   Ok(start.elapsed())
+}
+
+fn cases(directory: &Path) -> cyn::Result<Vec<PathBuf>> {
+   let mut cases = fs::read_dir(directory)
+      .chain_err_with(|| {
+         format!(
+            "failed to list {directory}",
+            directory = directory.display(),
+         )
+      })?
+      .filter_map(|entry| {
+         let path = entry.ok()?.path();
+
+         path
+            .extension()
+            .is_some_and(|extension| extension == "cab")
+            .then_some(path)
+      })
+      .collect::<Vec<_>>();
+
+   cases.sort();
+
+   Ok(cases)
+}
+
+fn write_summary(err: &mut impl ust::Write, summaries: &[(Stage, Summary)]) -> cyn::Result<()> {
+   writeln!(err).chain_err("failed to write to stderr")?;
+   write(
+      err,
+      &format!(
+         "{stage:<8} {matched:>8} {changed:>8} {missing:>8}",
+         stage = "stage",
+         matched = "matched",
+         changed = "changed",
+         missing = "missing",
+      )
+      .bold(),
+   )
+   .chain_err("failed to write to stderr")?;
+   writeln!(err).chain_err("failed to write to stderr")?;
+
+   for &(stage, summary) in summaries {
+      write!(err, "{stage:<8} ").chain_err("failed to write to stderr")?;
+      write(
+         err,
+         &format!("{matched:>8} ", matched = summary.matched).green(),
+      )
+      .chain_err("failed to write to stderr")?;
+
+      for count in [summary.changed, summary.missing] {
+         let count_display = format!("{count:>8} ");
+
+         let result = if count == 0 {
+            write!(err, "{count_display}")
+         } else {
+            write(err, &count_display.yellow())
+         };
+
+         result.chain_err("failed to write to stderr")?;
+      }
+
+      writeln!(err).chain_err("failed to write to stderr")?;
+   }
+
+   Ok(())
 }
 
 #[tokio::main]
@@ -58,110 +362,186 @@ async fn main() -> cyn::Termination {
    match cli.command {
       Command::Check {
          fail_fast,
-         command: Check::Syntax { overwrite },
+         overwrite,
+         directory,
+         command,
       } => {
-         let mut fail_count: usize = 0;
-
          let diff_tool = which("difft")
             .or_else(|_| which("diff"))
             .chain_err("failed to find diff tool")?;
 
-         let parse_oracle = syntax::ParseOracle::new();
+         let directory = directory.unwrap_or_else(|| {
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+               .parent()
+               .expect("cab-task must be in the cab directory")
+               .join("tests")
+         });
 
-         let root = env::current_dir().unwrap();
-         let root = root.parent().unwrap().join("target").join("cab-noder-fuzz");
+         let cases = cases(&directory)?;
 
-         fs::read_dir(&root)
-            .chain_err("failed to list cab-syntax/test/data")?
-            .filter_map(|entry| {
-               let mut path = entry.ok()?.path();
+         let mut summaries = Vec::new();
 
-               if path.extension().is_none_or(|extension| extension != "cab") {
-                  return None;
-               }
+         for &stage in command.stages() {
+            let mut summary = Summary::default();
+
+            for source_file in &cases {
+               let name = source_file
+                  .file_stem()
+                  .and_then(|name| name.to_str())
+                  .expect("test case names must be valid UTF-8");
+
+               let expected_file = source_file.with_extension(format!("{stage}.expect"));
 
-               Some((path.clone(), {
-                  path.set_extension("expect");
-                  path
-               }))
-            })
-            .try_for_each(|(source_file, expected_display_file)| {
-               let source = fs::read_to_string(&source_file).chain_err_with(|| {
+               let source = fs::read_to_string(source_file).chain_err_with(|| {
                   format!(
                      "failed to read source file {source_file}",
                      source_file = source_file.display(),
                   )
                })?;
 
-               let expected_display =
-                  fs::read_to_string(&expected_display_file).chain_err_with(|| {
-                     format!(
-                        "failed to read expected display file {expected_display_file}",
-                        expected_display_file = expected_display_file.display(),
-                     )
-                  })?;
-
-               let actual_display = {
-                  let node = parse_oracle.parse(syntax::tokenize(&source)).node;
-                  format!("{node:#?}")
+               let expected = match fs::read_to_string(&expected_file) {
+                  Ok(expected) => Some(expected),
+                  Err(error) if error.kind() == io::ErrorKind::NotFound => None,
+
+                  Err(error) => {
+                     Err(error).chain_err_with(|| {
+                        format!(
+                           "failed to read expected file {expected_file}",
+                           expected_file = expected_file.display(),
+                        )
+                     })?
+                  },
                };
 
-               let name = source_file.file_stem().unwrap().to_str().unwrap().bold();
-
-               if expected_display == actual_display {
-                  write!(err, "expected and actual display matched for ")
-                     .chain_err("failed to write to stderr")?;
-                  write(err, &name.green()).chain_err("failed to write to stderr")?;
-                  return Ok(());
-               }
-
-               write!(err, "behaviour has changed for ").chain_err("failed to write to stderr")?;
-               write(err, &name.yellow()).chain_err("failed to write to stderr")?;
-               write!(err, "! diffing expected vs actual display")
-                  .chain_err("failed to write to stderr")?;
-
-               let mut child = process::Command::new(&diff_tool)
-                  .arg(&expected_display_file)
-                  .arg("/dev/stdin")
-                  .stdin(process::Stdio::piped())
-                  .spawn()
-                  .chain_err("failed to spawn diff tool")?;
-
-               if let Some(mut stdin) = child.stdin.take() {
-                  write!(stdin, "{actual_display}")
-                     .chain_err("failed to feed display to diff tool")?;
+               let actual = actual(stage, name, &source)
+                  .await
+                  .chain_err_with(|| format!("failed to run {name} through {stage}"))?;
+
+               let name = name.bold();
+
+               match expected {
+                  Some(ref expected) if *expected == actual => {
+                     write!(err, "{stage}: expected and actual matched for ")
+                        .chain_err("failed to write to stderr")?;
+                     write(err, &name.green()).chain_err("failed to write to stderr")?;
+                     writeln!(err).chain_err("failed to write to stderr")?;
+
+                     summary.matched += 1;
+                     continue;
+                  },
+
+                  Some(_) => {
+                     write!(err, "{stage}: behaviour has changed for ")
+                        .chain_err("failed to write to stderr")?;
+                     write(err, &name.yellow()).chain_err("failed to write to stderr")?;
+                     writeln!(err, "! diffing expected vs actual")
+                        .chain_err("failed to write to stderr")?;
+
+                     let mut child = process::Command::new(&diff_tool)
+                        .arg(&expected_file)
+                        .arg("/dev/stdin")
+                        .stdin(process::Stdio::piped())
+                        .spawn()
+                        .chain_err("failed to spawn diff tool")?;
+
+                     if let Some(mut stdin) = child.stdin.take() {
+                        write!(stdin, "{actual}")
+                           .chain_err("failed to feed actual to diff tool")?;
+                     }
+
+                     child
+                        .wait()
+                        .chain_err("failed to wait for diff tool to complete")?;
+
+                     summary.changed += 1;
+                  },
+
+                  None => {
+                     write!(err, "{stage}: no expected result for ")
+                        .chain_err("failed to write to stderr")?;
+                     write(err, &name.yellow()).chain_err("failed to write to stderr")?;
+                     writeln!(err).chain_err("failed to write to stderr")?;
+
+                     summary.missing += 1;
+                  },
                }
 
-               child
-                  .wait()
-                  .chain_err("failed to wait for diff tool to complete")?;
-
                if overwrite {
-                  eprintln!("overwriting old test case...");
-                  fs::write(&expected_display_file, &actual_display).chain_err_with(|| {
+                  writeln!(err, "overwriting old test case...")
+                     .chain_err("failed to write to stderr")?;
+
+                  fs::write(&expected_file, &actual).chain_err_with(|| {
                      format!(
-                        "failed to override expected display file {expected_display_file} with \
-                         actual display",
-                        expected_display_file = expected_display_file.display(),
+                        "failed to overwrite expected file {expected_file} with actual result",
+                        expected_file = expected_file.display(),
                      )
                   })?;
                }
 
-               fail_count += 1;
-
                if fail_fast {
                   cyn::bail!("failed fast");
                }
+            }
 
-               Ok::<(), cyn::Chain>(())
-            })?;
+            summaries.push((stage, summary));
+         }
+
+         write_summary(err, &summaries)?;
+
+         let fail_count: usize = summaries
+            .iter()
+            .map(|&(_, summary)| summary.changed + summary.missing)
+            .sum();
 
          if fail_count > 0 {
-            if !fail_fast {
-               eprintln!("behaviour has changed for {fail_count} test cases");
-            }
+            cyn::bail!("exiting due to {fail_count} failed test cases");
+         }
+      },
+
+      Command::Bench {
+         iterations,
+         directory,
+      } => {
+         let out = &mut terminal::stdout();
+
+         let directory = directory.unwrap_or_else(|| {
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+               .parent()
+               .expect("cab-task must be in the cab directory")
+               .join("benches")
+         });
+
+         for source_file in &cases(&directory)? {
+            let name = source_file
+               .file_stem()
+               .and_then(|name| name.to_str())
+               .expect("benchmark names must be valid UTF-8");
+
+            let source = fs::read_to_string(source_file).chain_err_with(|| {
+               format!(
+                  "failed to read source file {source_file}",
+                  source_file = source_file.display(),
+               )
+            })?;
 
-            cyn::bail!("exiting due to {fail_count} previous errors");
+            let statically =
+               measure(err, name, &source, iterations, runtime::CompileOracle::new()).await?;
+            let dynamically = measure(
+               err,
+               name,
+               &source,
+               iterations,
+               runtime::CompileOracle::new().dynamic(),
+            )
+            .await?;
+
+            writeln!(
+               out,
+               "{name:<16} static {statically:>12.2?} dynamic {dynamically:>12.2?} speedup \
+                {speedup:.2}x",
+               speedup = dynamically.as_secs_f64() / statically.as_secs_f64(),
+            )
+            .chain_err("failed to write to stdout")?;
          }
       },
    }
@@ -233,9 +233,12 @@ impl<T> ResultExt<T> for result::Result<T, Chain> {
 /// function.
 ///
 /// Can be created directly or from a [`Chain`] with the `?` operator. Will
-/// pretty print the chain.
+/// pretty print the chain and exit with its code, which is 1 unless set.
 #[derive(Clone, Dupe)]
-pub struct Termination(result::Result<(), Chain>);
+pub struct Termination {
+   result: result::Result<(), Chain>,
+   code:   u8,
+}
 
 impl ops::Try for Termination {
    type Output = ();
@@ -246,7 +249,7 @@ impl ops::Try for Termination {
    }
 
    fn branch(self) -> ops::ControlFlow<Self::Residual, Self::Output> {
-      match self.0 {
+      match self.result {
          Ok(()) => ops::ControlFlow::Continue(()),
          Err(_) => ops::ControlFlow::Break(self),
       }
@@ -283,13 +286,13 @@ impl ops::FromResidual<Termination> for Termination {
 
 impl process::Termination for Termination {
    fn report(self) -> process::ExitCode {
-      match self.0 {
+      match self.result {
          Ok(()) => process::ExitCode::SUCCESS,
 
          Err(chain) => {
             let writer = &mut terminal::stderr();
             let _ = chain.display_styled(writer);
-            process::ExitCode::FAILURE
+            process::ExitCode::from(self.code)
          },
       }
    }
@@ -299,12 +302,25 @@ impl Termination {
    /// Creates a successful [`Termination`] that returns success.
    #[must_use]
    pub fn success() -> Self {
-      Self(Ok(()))
+      Self {
+         result: Ok(()),
+         code:   0,
+      }
    }
 
    /// Creates a [`Termination`] from the provided [`Chain`].
    #[must_use]
    pub fn error(chain: Chain) -> Self {
-      Self(Err(chain))
+      Self {
+         result: Err(chain),
+         code:   1,
+      }
+   }
+
+   /// Sets the code to exit with if the [`Termination`] is an error.
+   #[must_use]
+   pub fn code(mut self, code: u8) -> Self {
+      self.code = code;
+      self
    }
 }